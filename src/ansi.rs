@@ -0,0 +1,143 @@
+use std::mem::take;
+
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::Span,
+};
+
+#[derive(Debug, Default)]
+enum State {
+    #[default]
+    Ground,
+    Escape,
+    Csi(String),
+    // operating system command, terminated by BEL or ESC \
+    Osc,
+    OscEscape,
+}
+
+/// Incremental parser for ANSI escape sequences. SGR sequences are turned
+/// into styles, everything else is stripped. Both the current style and any
+/// partially received sequence carry over between calls.
+#[derive(Debug, Default)]
+pub struct AnsiParser {
+    style: Style,
+    state: State,
+}
+
+impl AnsiParser {
+    pub fn parse(&mut self, text: &str) -> Vec<Span<'static>> {
+        let mut spans = Vec::new();
+        let mut run = String::new();
+        for c in text.chars() {
+            match &mut self.state {
+                State::Ground if c == '\x1b' => {
+                    if !run.is_empty() {
+                        spans.push(Span::styled(take(&mut run), self.style));
+                    }
+                    self.state = State::Escape;
+                }
+                State::Ground => run.push(c),
+                State::Escape => {
+                    self.state = match c {
+                        '[' => State::Csi(String::new()),
+                        ']' => State::Osc,
+                        _ => State::Ground,
+                    }
+                }
+                State::Csi(params) => match c {
+                    '\x40'..='\x7e' => {
+                        if c == 'm' {
+                            let params = take(params);
+                            self.apply_sgr(&params);
+                        }
+                        self.state = State::Ground;
+                    }
+                    _ => params.push(c),
+                },
+                State::Osc => match c {
+                    '\x07' => self.state = State::Ground,
+                    '\x1b' => self.state = State::OscEscape,
+                    _ => {}
+                },
+                State::OscEscape => self.state = State::Ground,
+            }
+        }
+        if !run.is_empty() {
+            spans.push(Span::styled(run, self.style));
+        }
+        spans
+    }
+
+    fn apply_sgr(&mut self, params: &str) {
+        let mut codes = params.split(';').map(|p| p.parse::<u16>().unwrap_or(0));
+        while let Some(code) = codes.next() {
+            self.style = match code {
+                0 => Style::default(),
+                1 => self.style.add_modifier(Modifier::BOLD),
+                2 => self.style.add_modifier(Modifier::DIM),
+                3 => self.style.add_modifier(Modifier::ITALIC),
+                4 => self.style.add_modifier(Modifier::UNDERLINED),
+                7 => self.style.add_modifier(Modifier::REVERSED),
+                22 => self.style.remove_modifier(Modifier::BOLD | Modifier::DIM),
+                23 => self.style.remove_modifier(Modifier::ITALIC),
+                24 => self.style.remove_modifier(Modifier::UNDERLINED),
+                27 => self.style.remove_modifier(Modifier::REVERSED),
+                30..=37 => self.style.fg(basic_color(code - 30)),
+                38 => match extended_color(&mut codes) {
+                    Some(c) => self.style.fg(c),
+                    None => self.style,
+                },
+                39 => self.style.fg(Color::Reset),
+                40..=47 => self.style.bg(basic_color(code - 40)),
+                48 => match extended_color(&mut codes) {
+                    Some(c) => self.style.bg(c),
+                    None => self.style,
+                },
+                49 => self.style.bg(Color::Reset),
+                90..=97 => self.style.fg(bright_color(code - 90)),
+                100..=107 => self.style.bg(bright_color(code - 100)),
+                _ => self.style,
+            };
+        }
+    }
+}
+
+fn basic_color(i: u16) -> Color {
+    [
+        Color::Black,
+        Color::Red,
+        Color::Green,
+        Color::Yellow,
+        Color::Blue,
+        Color::Magenta,
+        Color::Cyan,
+        Color::Gray,
+    ][i as usize]
+}
+
+fn bright_color(i: u16) -> Color {
+    [
+        Color::DarkGray,
+        Color::LightRed,
+        Color::LightGreen,
+        Color::LightYellow,
+        Color::LightBlue,
+        Color::LightMagenta,
+        Color::LightCyan,
+        Color::White,
+    ][i as usize]
+}
+
+// 38;5;n and 38;2;r;g;b forms, shared with the background variant
+fn extended_color(codes: &mut impl Iterator<Item = u16>) -> Option<Color> {
+    match codes.next()? {
+        5 => Some(Color::Indexed(codes.next()? as u8)),
+        2 => Some(Color::Rgb(
+            codes.next()? as u8,
+            codes.next()? as u8,
+            codes.next()? as u8,
+        )),
+        _ => None,
+    }
+}
@@ -1,127 +1,469 @@
-use std::path::PathBuf;
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
 
 use crate::{
-    cli::DeviceOptions,
-    device_finder::{Baud, DeviceConfig, DeviceConfigurer, DeviceFinder},
+    cli::{DeviceOptions, TerminalOptions, WatchOptions},
+    device_finder::{DeviceConfig, DeviceConfigurer, DeviceFinder, port_present},
+    emit::{JsonSink, Origin},
+    error::SerialError,
     event::{
-        AppEvent, FromFileWatcher, FromSerialData, GuiEvent, Messenger, Reactive, Severity,
-        ToAppEvent, ToFileWatcher, ToSerialData, crossterm_handler, new_filewatcher,
-        serial_handler,
+        APP_SESSION, AppEvent, Capture, Drawable, EventListener, FromFileWatcher, FromSerialData,
+        GuiEvent, Messenger, Reactive, SERIAL_QUEUE, Severity, ToAppEvent, ToFileWatcher,
+        ToSerialData, UploadRun, crossterm_handler, new_filewatcher, serial_handler,
     },
     fileviewer::{CmdInput, FileViewer},
-    notif::Notification,
-    ui::Dashboard,
+    keys::{Action, KeyMap},
+    notif::{ConfirmQuit, Help, UploadOutput},
+    replay,
+    state::{LastDevice, UploadHistory},
+    theme::Theme,
+    ui::{Dashboard, parse_hex},
 };
 
-use crossterm::event::KeyEvent;
-use eyre::OptionExt;
-use ratatui::{DefaultTerminal, Frame, layout::Rect};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use eyre::{OptionExt, eyre};
+use ratatui::{
+    DefaultTerminal, Frame,
+    layout::{Constraint, Flex, Layout, Rect},
+    widgets::{Block, Tabs},
+};
 
-use color_eyre::{Result, eyre::WrapErr};
-use tokio::sync::mpsc;
+use color_eyre::Result;
+use tokio::{sync::mpsc, task::JoinHandle};
+use tokio_serial::SerialStream;
 use tracing::{Instrument, instrument, trace};
 
 pub struct App {
     running: bool,
+    // untagged, sessions hand out their own messengers
     to_self: Messenger,
-    inbox: mpsc::UnboundedReceiver<ToAppEvent>,
+    inbox: mpsc::UnboundedReceiver<(usize, ToAppEvent)>,
+    // from serial handlers, read alongside inbox
+    serial_inbox: mpsc::Receiver<(usize, FromSerialData)>,
+    // popups, drawn over the focused session
     stack: Vec<Box<dyn Reactive>>,
-    serial: Option<mpsc::UnboundedSender<ToSerialData>>,
-    serial_cfg: Option<DeviceConfig>,
-    watcher: Option<mpsc::UnboundedSender<ToFileWatcher>>,
+    // one per tab, never empty
+    sessions: Vec<Session>,
+    // index into sessions of the focused tab
+    active: usize,
+    next_id: usize,
+    term: TerminalOptions,
+    json: Option<JsonSink>,
+    // played back in place of opening a device
+    replay: Option<PathBuf>,
+    watch_opts: WatchOptions,
+    // most recent upload command results, oldest first
+    uploads: VecDeque<UploadRun>,
+    uploading: bool,
+    confirm_quit: bool,
+    last_quit_request: Option<Instant>,
+    help: Arc<AtomicBool>,
+    keys: KeyMap,
+    theme: Theme,
 }
 
+const UPLOAD_HISTORY: usize = 5;
+
 impl std::fmt::Debug for App {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("App")
             .field("running", &self.running)
             .field("inbox", &self.inbox)
-            .field("serial", &self.serial)
-            .field("serial_cfg", &self.serial_cfg)
-            .field("uploader", &self.watcher)
+            .field("serial_inbox", &self.serial_inbox)
+            .field("sessions", &self.sessions)
+            .field("active", &self.active)
+            .field("next_id", &self.next_id)
+            .field("json", &self.json)
+            .field("replay", &self.replay)
+            .field("watch_opts", &self.watch_opts)
+            .field("uploads", &self.uploads)
+            .field("uploading", &self.uploading)
+            .field("confirm_quit", &self.confirm_quit)
+            .field("last_quit_request", &self.last_quit_request)
+            .field("help", &self.help)
+            .field("keys", &self.keys)
+            .field("theme", &self.theme)
             .finish()
     }
 }
 
+/// A device and the dashboard showing it, one per tab. Everything sent on
+/// its messenger is tagged with its id, so replies from the tasks it starts
+/// come back to it even when another tab has focus.
+#[derive(Debug)]
+struct Session {
+    id: usize,
+    to_self: Messenger,
+    dashboard: Dashboard,
+    serial: Option<mpsc::UnboundedSender<ToSerialData>>,
+    serial_cfg: Option<DeviceConfig>,
+    watcher: Option<mpsc::UnboundedSender<ToFileWatcher>>,
+    capture: Capture,
+    reconnect: Option<JoinHandle<()>>,
+    // asks the serial task for the control lines every status_poll_ms
+    status_poll: Option<JoinHandle<()>>,
+    // looks for the port every presence_poll_ms
+    presence_poll: Option<JoinHandle<()>>,
+    // sends --heartbeat while the port is open
+    heartbeat: Option<JoinHandle<()>>,
+    // set with alt+h, kept across reconnects
+    heartbeat_paused: Arc<AtomicBool>,
+    // the device was closed from the keyboard rather than for an upload
+    user_disconnect: bool,
+    // open serial_cfg again once the current port has closed
+    reopen: bool,
+}
+
+// the poll task holds a sender, so the serial task would otherwise outlive
+// a closed tab
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.stop_reconnect();
+        if let Some(task) = self.status_poll.take() {
+            task.abort();
+        }
+        if let Some(task) = self.presence_poll.take() {
+            task.abort();
+        }
+        if let Some(task) = self.heartbeat.take() {
+            task.abort();
+        }
+    }
+}
+
 impl Default for App {
     fn default() -> Self {
-        Self::new()
+        Self::new(TerminalOptions::default(), WatchOptions::default())
     }
 }
 
 impl App {
-    pub fn new() -> Self {
+    pub fn new(term: TerminalOptions, watch_opts: WatchOptions) -> Self {
         let (tx, rx) = mpsc::unbounded_channel();
-        let tx = Messenger::new(tx);
+        let (serial_tx, serial_rx) = mpsc::channel(SERIAL_QUEUE);
+        let tx = Messenger::new(tx, serial_tx).for_session(APP_SESSION);
         crossterm_handler(tx.clone());
-        Self {
+        let mut app = Self {
             running: true,
-            to_self: tx.clone(),
+            to_self: tx,
             inbox: rx,
-            stack: vec![Box::new(Dashboard::new(tx))],
-            serial: None,
-            serial_cfg: None,
-            watcher: None,
-        }
+            serial_inbox: serial_rx,
+            stack: Vec::new(),
+            sessions: Vec::new(),
+            active: 0,
+            next_id: 0,
+            json: None,
+            replay: None,
+            watch_opts,
+            uploads: VecDeque::new(),
+            uploading: false,
+            confirm_quit: term.confirm_quit,
+            last_quit_request: None,
+            help: Arc::default(),
+            keys: term.keys.clone(),
+            theme: Theme::new(term.theme, &term.colors),
+            term,
+        };
+        app.new_session();
+        app
+    }
+
+    pub fn emit_json(mut self, sink: Option<JsonSink>) -> Self {
+        self.json = sink;
+        self
     }
+
+    pub fn replay(mut self, path: Option<PathBuf>) -> Self {
+        self.replay = path;
+        self
+    }
+
     #[instrument(skip(terminal))]
     pub async fn run(
         mut self,
         mut terminal: DefaultTerminal,
         mut default_dev: DeviceOptions,
-        default_cmd: String,
-        default_path: Option<String>,
+        default_cmd: Option<String>,
+        default_path: Option<PathBuf>,
+        capture_path: Option<PathBuf>,
     ) -> color_eyre::Result<()> {
         use AppEvent::{
-            Leave, Quit, RequestSerial, RequestUpload, SendSerial, SendUpload, SerialConnect,
-            Watcher,
+            Leave, Marker, Quit, RequestQuit, RequestSendFile, RequestSerial, RequestUpload,
+            RequestXmodem, SendAndWait, SendFile, SendHexFile, SendSerial, SendUpload,
+            SerialConnect, ToggleCapture, Watcher, XmodemSend,
         };
         use ToAppEvent::{App, Gui, Popup};
         trace!("Starting main loop!");
-        if let Some(device) = default_dev.to_config() {
-            self.connect_serial_now(device);
+        if let Some(ref path) = capture_path {
+            self.session().start_capture(path.clone());
+        }
+        if let Some(path) = self.replay.take() {
+            replay::start(path, self.session().to_self.clone());
+        } else if let Some(device) = default_dev.to_config() {
+            // fall back to picking a device rather than exiting
+            self.session().connect_serial_now(device, true);
+        } else if default_dev.last {
+            self.session().connect_last(&default_dev);
         }
         while self.running {
             terminal.draw(|frame| self.draw(frame))?;
-            match self.next().await? {
+            let Some((id, event)) = self.next().await? else {
+                continue;
+            };
+            if let Gui(GuiEvent::Serial(ref d)) = event {
+                self.emit(id, |sink, origin| sink.received(origin, d));
+            }
+            match event {
                 Gui(GuiEvent::Serial(FromSerialData::Gone)) => {
-                    self.watcher
-                        .as_mut()
-                        .inspect(|u| _ = u.send(ToFileWatcher::Disconnected));
-                    self.handle_key_events(GuiEvent::Serial(FromSerialData::Gone));
+                    if let Some(s) = self.session_by_id(id) {
+                        if std::mem::take(&mut s.user_disconnect) {
+                            s.serial = None;
+                        } else {
+                            s.watcher
+                                .as_mut()
+                                .inspect(|u| _ = u.send(ToFileWatcher::Disconnected));
+                        }
+                    }
+                    self.handle_key_events(id, GuiEvent::Serial(FromSerialData::Gone));
+                    if let Some(s) = self.session_by_id(id)
+                        && std::mem::take(&mut s.reopen)
+                    {
+                        s.reopen_device();
+                    }
+                }
+                // kept so a reconnect or --last opens the port the same way
+                Gui(GuiEvent::Serial(FromSerialData::Configured(c))) => {
+                    if let Some(s) = self.session_by_id(id) {
+                        if let Err(e) = LastDevice::save(&c) {
+                            s.to_self
+                                .log(Severity::Debug, format!("Could not save device: {}", e));
+                        }
+                        s.serial_cfg = Some(c.clone());
+                    }
+                    self.handle_key_events(id, GuiEvent::Serial(FromSerialData::Configured(c)));
+                }
+                Gui(GuiEvent::Serial(FromSerialData::Lost(e))) => {
+                    if let Some(s) = self.session_by_id(id) {
+                        s.serial = None;
+                        if !std::mem::take(&mut s.user_disconnect) {
+                            s.watcher
+                                .as_mut()
+                                .inspect(|u| _ = u.send(ToFileWatcher::Disconnected));
+                        }
+                        // waiting won't fix a permission or settings problem
+                        if default_dev.auto_reconnect && e.is_disconnect() {
+                            s.auto_reconnect();
+                        } else if default_dev.auto_reconnect {
+                            s.to_self.log(
+                                Severity::Info,
+                                "Not reconnecting, the port did not go away".into(),
+                            );
+                        }
+                    }
+                    self.handle_key_events(id, GuiEvent::Serial(FromSerialData::Lost(e)));
                 }
-                Gui(g) => self.handle_key_events(g),
+                Gui(g) => self.handle_key_events(id, g),
                 App(Leave) => {
-                    _ = self.stack.pop();
-                    if self.stack.is_empty() {
+                    if self.stack.pop().is_none() {
+                        self.shutdown().await;
                         return Ok(());
                     }
                 }
-                App(Quit) => self.running = false,
-                App(RequestSerial) => self.connect_serial(default_dev.clone()),
+                App(RequestQuit) => self.request_quit(),
+                App(Quit) => {
+                    self.shutdown().await;
+                    self.running = false;
+                }
+                App(RequestSerial) => {
+                    if let Some(s) = self.session_by_id(id) {
+                        s.stop_reconnect();
+                        s.connect_serial(default_dev.clone());
+                    }
+                }
                 App(RequestUpload) => {
-                    self.upload_file(default_path.clone(), default_cmd.clone(), true)
+                    self.upload_file(id, default_path.clone(), default_cmd.clone(), true)
+                }
+                App(RequestSendFile) => self.select_send_file(id, default_path.clone(), false),
+                App(SendFile(path)) => self.send_file(id, path, ToSerialData::RawBytes, false),
+                App(SendHexFile(path)) => self.send_file(id, path, ToSerialData::RawBytes, true),
+                App(RequestXmodem) => self.select_send_file(id, default_path.clone(), true),
+                App(XmodemSend(path)) => self.send_file(id, path, ToSerialData::Xmodem, false),
+                App(SendAndWait(b, timeout)) => {
+                    self.send_serial(id, ToSerialData::Query(b, timeout))
                 }
                 App(SendSerial(s)) => {
-                    self.send_serial(s);
+                    self.send_serial(id, s);
                 }
-                App(SerialConnect(s, c)) => {
-                    self.serial = Some(s);
-                    self.serial_cfg = Some(c);
+                App(SerialConnect(serial, c)) => {
+                    let Some(s) = self.session_by_id(id) else {
+                        continue;
+                    };
+                    // only the reconnect task connects while it is running
+                    let reconnected = s.reconnect.take().is_some();
+                    s.dashboard.listen(&GuiEvent::SerialConfig(c.clone()));
+                    if let Err(e) = LastDevice::save(&c) {
+                        s.to_self
+                            .log(Severity::Debug, format!("Could not save device: {}", e));
+                    }
+                    s.poll_status(&serial, c.status_poll_ms);
+                    s.poll_presence(&serial, &c);
+                    s.start_heartbeat(&serial, &default_dev);
+                    s.serial = Some(serial);
+                    s.serial_cfg = Some(c);
+                    let greeting = if reconnected {
+                        &default_dev.on_reconnect
+                    } else {
+                        &default_dev.on_connect
+                    };
+                    if let Some(line) = greeting.clone() {
+                        // the handle queues anything sent before the serial task starts reading
+                        s.to_self
+                            .log(Severity::Debug, format!("Auto-sending {:?}", line));
+                        s.dashboard.listen(&GuiEvent::SendLine(line));
+                    }
+                }
+                App(ToggleCapture) => {
+                    if let Some(s) = self.session_by_id(id) {
+                        s.toggle_capture(capture_path.clone());
+                    }
+                }
+                App(Marker(label)) => {
+                    if let Some(s) = self.session_by_id(id)
+                        && let Err(e) = s.capture.marker(&label)
+                    {
+                        s.to_self
+                            .log(Severity::Error, format!("Unable to write capture: {}", e));
+                    }
                 }
                 App(SendUpload(u)) => {
-                    self.watcher = Some(u);
+                    if let Some(s) = self.session_by_id(id) {
+                        s.watcher = Some(u);
+                    }
                 }
-                App(Watcher(w)) => self.handle_watcher(w),
+                App(Watcher(w)) => self.handle_watcher(id, w),
                 Popup(reactive) => self.stack.push(reactive),
             }
         }
         Ok(())
     }
 
-    fn handle_key_events(&mut self, event: GuiEvent) {
-        use crate::event::GuiEvent::Crossterm;
-        use crossterm::event::{Event::Key, KeyEventKind::Press};
+    // Closes every port in an orderly way before the terminal is restored,
+    // giving the serial tasks a moment to flush and acknowledge.
+    async fn shutdown(&mut self) {
+        const WAIT: Duration = Duration::from_millis(500);
+        let mut open = Vec::new();
+        for s in &mut self.sessions {
+            s.stop_reconnect();
+            let Some(ref se) = s.serial else {
+                continue;
+            };
+            if se.send(ToSerialData::Disconnect).is_ok() {
+                open.push(s.id);
+            }
+        }
+        let deadline = tokio::time::Instant::now() + WAIT;
+        while !open.is_empty() {
+            use FromSerialData::{Gone, Lost};
+            match tokio::time::timeout_at(deadline, self.recv()).await {
+                Ok(Some((id, ToAppEvent::Gui(GuiEvent::Serial(Gone | Lost(_)))))) => {
+                    open.retain(|s| *s != id)
+                }
+                Ok(Some(_)) => {}
+                Ok(None) | Err(_) => break,
+            }
+        }
+    }
+
+    // the focused session
+    fn session(&mut self) -> &mut Session {
+        &mut self.sessions[self.active]
+    }
+
+    fn session_by_id(&mut self, id: usize) -> Option<&mut Session> {
+        self.sessions.iter_mut().find(|s| s.id == id)
+    }
+
+    // opens a tab and focuses it
+    fn new_session(&mut self) -> &mut Session {
+        let id = self.next_id;
+        self.next_id += 1;
+        let to_self = self.to_self.for_session(id);
+        self.sessions.push(Session {
+            id,
+            dashboard: Dashboard::new(to_self.clone(), self.term.clone()),
+            to_self,
+            serial: None,
+            serial_cfg: None,
+            watcher: None,
+            capture: Capture::new(self.term.capture_format),
+            reconnect: None,
+            status_poll: None,
+            presence_poll: None,
+            heartbeat: None,
+            heartbeat_paused: Arc::default(),
+            user_disconnect: false,
+            reopen: false,
+        });
+        self.active = self.sessions.len() - 1;
+        self.session()
+    }
+
+    fn close_session(&mut self) {
+        if self.sessions.len() == 1 {
+            self.session()
+                .to_self
+                .log(Severity::Info, "Cannot close the last tab".into());
+            return;
+        }
+        // dropping the session closes its port
+        self.sessions.remove(self.active);
+        self.active = self.active.min(self.sessions.len() - 1);
+    }
+
+    fn focus(&mut self, index: usize) {
+        if index < self.sessions.len() {
+            self.active = index;
+        }
+    }
+
+    // The sink is dropped on the first error, a reader going away should
+    // not produce an error for every line that follows.
+    fn emit(&mut self, id: usize, write: impl FnOnce(&mut JsonSink, Origin) -> Result<()>) {
+        let Some(sink) = self.json.as_mut() else {
+            return;
+        };
+        let device = self
+            .sessions
+            .iter()
+            .find(|s| s.id == id)
+            .and_then(|s| s.serial_cfg.as_ref())
+            .map(|c| c.path.to_string_lossy());
+        let origin = Origin {
+            session: id,
+            device: device.as_deref(),
+        };
+        if let Err(e) = write(sink, origin) {
+            let msg = format!("Stopped writing JSON to {}: {}", sink.target(), e);
+            self.session().to_self.log(Severity::Error, msg);
+            self.json = None;
+        }
+    }
+
+    // Key presses go to the focused tab, anything else to the session that
+    // sent it. Popups see everything first.
+    fn handle_key_events(&mut self, id: usize, event: GuiEvent) {
+        use crate::event::GuiEvent::{Crossterm, Log};
+        use crossterm::event::{
+            Event::{FocusGained, FocusLost, Key},
+            KeyEventKind::Press,
+        };
         let event = match event {
             Crossterm(Key(event)) if event.kind != Press => {
                 return;
@@ -129,38 +471,380 @@ impl App {
             e => e,
         };
 
-        for component in self.stack.iter_mut().rev() {
-            if component.listen(&event) {
-                break;
+        // every tab hears about focus, so switching to one later draws it right
+        if let Crossterm(FocusGained | FocusLost) = event {
+            for s in &mut self.sessions {
+                s.dashboard.listen(&event);
+            }
+            return;
+        }
+        let handled = self.stack.iter_mut().rev().any(|c| c.listen(&event));
+        if !handled {
+            let session = match event {
+                Crossterm(_) => Some(self.session()),
+                // a log from a tab that has since closed is still worth showing
+                Log(..) => match self.sessions.iter().position(|s| s.id == id) {
+                    Some(i) => Some(&mut self.sessions[i]),
+                    None => Some(self.session()),
+                },
+                _ => self.session_by_id(id),
+            };
+            if let Some(s) = session {
+                s.dashboard.listen(&event);
             }
         }
-        if let Crossterm(Key(k)) = event {
+        // in raw mode the dashboard sends every key it gets to the device
+        // and a popup that took Esc used it to back out of something itself
+        if let Crossterm(Key(k)) = event
+            && (handled || !self.session().dashboard.raw())
+            && !(handled && k.code == KeyCode::Esc)
+        {
             self.handle_keys(k)
         }
     }
 
     fn handle_keys(&mut self, key: KeyEvent) {
-        use crossterm::event::{KeyCode::Char, KeyCode::Esc, KeyModifiers};
-        match (key.modifiers, key.code) {
-            (_, Esc) => {
-                self.to_self.send_app(AppEvent::Leave);
+        if key.code == KeyCode::Esc {
+            self.to_self.send_app(AppEvent::Leave);
+            return;
+        }
+        // alt+1 to alt+9 pick a tab
+        if key.modifiers == KeyModifiers::ALT
+            && let KeyCode::Char(c @ '1'..='9') = key.code
+        {
+            self.focus(c as usize - '1' as usize);
+            return;
+        }
+        let to_session = self.session().to_self.clone();
+        // the rest are handled by the dashboard
+        match self.keys.lookup(&key) {
+            Some(Action::Quit) => {
+                self.to_self.send_app(AppEvent::RequestQuit);
+            }
+            Some(Action::FindDevice) => {
+                to_session.send_app(AppEvent::RequestSerial);
+            }
+            Some(Action::NewTab) => {
+                let s = self.new_session();
+                s.to_self.send_app(AppEvent::RequestSerial);
+            }
+            Some(Action::CloseTab) => self.close_session(),
+            Some(Action::NextTab) => self.focus((self.active + 1) % self.sessions.len()),
+            Some(Action::PrevTab) => {
+                let count = self.sessions.len();
+                self.focus((self.active + count - 1) % count);
             }
-            (KeyModifiers::CONTROL, Char('c')) => {
-                self.to_self.send_app(AppEvent::Quit);
+            Some(Action::Upload) => {
+                to_session.send_app(AppEvent::RequestUpload);
             }
-            (KeyModifiers::CONTROL, Char('f')) => {
-                self.to_self.send_app(AppEvent::RequestSerial);
+            Some(Action::SendFile) => {
+                to_session.send_app(AppEvent::RequestSendFile);
             }
-            (KeyModifiers::CONTROL, Char('u')) => {
-                self.to_self.send_app(AppEvent::RequestUpload);
+            Some(Action::XmodemSend) => {
+                to_session.send_app(AppEvent::RequestXmodem);
             }
-            (KeyModifiers::ALT, Char('?')) => {
+            Some(Action::Disconnect) => self.session().disconnect(),
+            Some(Action::ToggleHeartbeat) => self.session().toggle_heartbeat(),
+            Some(Action::PortSettings) => self.session().configure_port(),
+            Some(Action::Reconnect) => {
+                let s = self.session();
+                if s.serial.is_some() {
+                    // the port has to close before it can be opened again
+                    s.reopen = true;
+                    s.disconnect();
+                } else {
+                    s.reopen_device();
+                }
+            }
+            Some(Action::UploadOutput) => {
+                if self.uploads.is_empty() {
+                    to_session.log(Severity::Info, "No upload has run yet".into());
+                } else {
+                    let runs = self.uploads.iter().cloned().collect();
+                    self.to_self
+                        .new_component(Box::new(UploadOutput::new(runs)));
+                }
+            }
+            Some(Action::Help) => {
                 self.create_help();
             }
             _ => {}
         }
     }
 
+    // With --confirm-quit, quitting mid capture or upload asks first. A second
+    // ctrl+c shortly after the first skips the question.
+    fn request_quit(&mut self) {
+        const DOUBLE_PRESS: Duration = Duration::from_secs(1);
+        let repeated = self
+            .last_quit_request
+            .is_some_and(|t| t.elapsed() < DOUBLE_PRESS);
+        self.last_quit_request = Some(Instant::now());
+        let mut busy = Vec::new();
+        if self.sessions.iter().any(|s| s.capture.is_active()) {
+            busy.push("a capture is running");
+        }
+        if self.uploading {
+            busy.push("an upload is in progress");
+        }
+        if !self.confirm_quit || repeated || busy.is_empty() {
+            self.to_self.send_app(AppEvent::Quit);
+            return;
+        }
+        let reason = busy.join(" and ");
+        self.to_self.new_component(Box::new(ConfirmQuit::new(
+            format!("Quit while {}?", reason),
+            self.to_self.clone(),
+        )));
+    }
+
+    fn handle_watcher(&mut self, id: usize, w: FromFileWatcher) {
+        match w {
+            FromFileWatcher::Busy(b) => self.uploading = b,
+            FromFileWatcher::Output(run) => {
+                if self.uploads.len() == UPLOAD_HISTORY {
+                    self.uploads.pop_front();
+                }
+                self.uploads.push_back(run);
+            }
+            // a watcher goes away with its tab
+            w => {
+                if let Some(s) = self.session_by_id(id) {
+                    s.handle_watcher(w);
+                }
+            }
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        trace!("Drawing frame");
+        self.stack.retain(|i| i.alive());
+        frame.render_widget(Block::new().style(self.theme.base()), frame.area());
+        let mut area = frame.area();
+        // the tab bar only shows up once there is more than one tab
+        if self.sessions.len() > 1 {
+            let [bar, rest] =
+                Layout::vertical([Constraint::Length(1), Constraint::Min(0)]).areas(area);
+            let titles = self
+                .sessions
+                .iter()
+                .enumerate()
+                .map(|(i, s)| format!("{}: {}", i + 1, s.title()));
+            let tabs = Tabs::new(titles)
+                .select(self.active)
+                .highlight_style(self.theme.selected());
+            frame.render_widget(tabs, bar);
+            area = rest;
+        }
+        let theme = &self.theme;
+        self.sessions[self.active]
+            .dashboard
+            .draw(area, frame, theme);
+        for component in &mut self.stack {
+            trace!("Drawing popup!");
+            render_popup(component.as_mut(), frame.area(), frame, theme);
+        }
+        trace!("Done Drawing");
+    }
+
+    // --watch-path and --default-cmd each skip their prompt, a directory
+    // given as the watch path only sets where the picker starts
+    fn upload_file(
+        &mut self,
+        id: usize,
+        path: Option<PathBuf>,
+        cmd: Option<String>,
+        autorun: bool,
+    ) {
+        use crate::event::Severity;
+        let to_dash = self.to_self.for_session(id);
+        let opts = self.watch_opts.clone();
+        tokio::spawn(
+            async move {
+                let file = match path {
+                    Some(p) if p.is_file() => p,
+                    path => {
+                        let Ok((finder, f)) =
+                            FileViewer::new("Select Binary".into(), to_dash.clone(), path)
+                        else {
+                            to_dash.log(Severity::Error, "Could not open working directory".into());
+                            return;
+                        };
+                        let finder = finder.with_extensions(&opts.extensions).allow_dirs();
+                        to_dash.new_component(Box::new(finder));
+                        let Ok(file) = f.await else {
+                            return;
+                        };
+                        file
+                    }
+                };
+                let cmd = match cmd.filter(|c| !c.trim().is_empty()) {
+                    Some(cmd) => cmd,
+                    None => {
+                        let history = UploadHistory::load().unwrap_or_else(|e| {
+                            to_dash.log(Severity::Debug, format!("Could not load history: {}", e));
+                            UploadHistory::default()
+                        });
+                        let (input, cmd) = CmdInput::new(
+                            "Enter upload command (replace binary path with #BIN#)".into(),
+                            String::new(),
+                        );
+                        let input = input
+                            .with_history(history.commands())
+                            .with_templates(&opts.templates);
+                        to_dash.new_component(Box::new(input));
+                        let Ok(cmd) = cmd.await else {
+                            return;
+                        };
+                        if let Err(e) = history.save(&cmd) {
+                            to_dash.log(Severity::Debug, format!("Could not save history: {}", e));
+                        }
+                        cmd
+                    }
+                };
+                let watcher = match new_filewatcher(&file, cmd, to_dash.clone(), autorun, opts) {
+                    Ok(w) => w,
+                    Err(e) => {
+                        to_dash.log(Severity::Error, format!("Could not watch file: {}", e));
+                        return;
+                    }
+                };
+                to_dash.send_app(AppEvent::SendUpload(watcher));
+            }
+            .instrument(tracing::info_span!("Watcher sequence")),
+        );
+    }
+
+    fn select_send_file(&mut self, id: usize, path: Option<PathBuf>, xmodem: bool) {
+        let to_dash = self.to_self.for_session(id);
+        let title = if xmodem {
+            "Select file to send with XMODEM"
+        } else {
+            "Select file to send"
+        };
+        let hex = Arc::new(AtomicBool::new(false));
+        tokio::spawn(
+            async move {
+                let Ok((mut finder, f)) = FileViewer::new(title.into(), to_dash.clone(), path)
+                else {
+                    to_dash.log(Severity::Error, "Could not open working directory".into());
+                    return;
+                };
+                if !xmodem {
+                    finder = finder.with_hex_toggle(hex.clone());
+                }
+                to_dash.new_component(Box::new(finder));
+                let Ok(file) = f.await else {
+                    return;
+                };
+                to_dash.send_app(if xmodem {
+                    AppEvent::XmodemSend(file)
+                } else if hex.load(Ordering::Relaxed) {
+                    AppEvent::SendHexFile(file)
+                } else {
+                    AppEvent::SendFile(file)
+                });
+            }
+            .instrument(tracing::info_span!("Send file sequence")),
+        );
+    }
+
+    fn send_file(&mut self, id: usize, path: PathBuf, how: fn(Vec<u8>) -> ToSerialData, hex: bool) {
+        let Some(s) = self.session_by_id(id) else {
+            return;
+        };
+        if s.serial.is_none() {
+            s.to_self.log(
+                Severity::Error,
+                "Not currently connected to a device".into(),
+            );
+            return;
+        }
+        let to_self = s.to_self.clone();
+        tokio::spawn(async move {
+            match read_file(&path, hex).await {
+                Ok(bytes) => to_self.send_app(AppEvent::SendSerial(how(bytes))),
+                Err(e) => to_self.log(
+                    Severity::Error,
+                    format!("Unable to send {}: {}", path.display(), e),
+                ),
+            }
+        });
+    }
+
+    // None when nothing happened for a while, so the screen is still redrawn
+    // to keep timers moving
+    async fn next(&mut self) -> color_eyre::Result<Option<(usize, ToAppEvent)>> {
+        const REDRAW: Duration = Duration::from_secs(1);
+        match tokio::time::timeout(REDRAW, self.recv()).await {
+            Ok(event) => event.map(Some).ok_or_eyre("Failed to receive event"),
+            Err(_) => Ok(None),
+        }
+    }
+
+    async fn recv(&mut self) -> Option<(usize, ToAppEvent)> {
+        tokio::select! {
+            e = self.inbox.recv() => e,
+            Some((id, d)) = self.serial_inbox.recv() => {
+                Some((id, ToAppEvent::Gui(GuiEvent::Serial(d))))
+            }
+        }
+    }
+
+    fn send_serial(&mut self, id: usize, data: ToSerialData) {
+        if self.session_by_id(id).is_some_and(|s| s.serial.is_some()) {
+            self.emit(id, |sink, origin| sink.sent(origin, &data));
+        }
+        if let Some(s) = self.session_by_id(id) {
+            s.send_serial(data);
+        }
+    }
+
+    // alt+? toggles, so the open flag is shared with the popup
+    fn create_help(&mut self) {
+        if self.help.load(Ordering::Relaxed) {
+            self.help.store(false, Ordering::Relaxed);
+            return;
+        }
+        self.help.store(true, Ordering::Relaxed);
+        self.to_self.new_component(Box::new(Help::new(
+            self.help.clone(),
+            self.keys.help_rows(),
+        )));
+    }
+}
+
+impl Session {
+    // shown in the tab bar
+    fn title(&self) -> String {
+        match &self.serial_cfg {
+            Some(c) => c
+                .path
+                .file_name()
+                .unwrap_or(c.path.as_os_str())
+                .to_string_lossy()
+                .into_owned(),
+            None => "no device".into(),
+        }
+    }
+
+    // the configurer again, but applied to the open port instead of
+    // reopening it
+    fn configure_port(&mut self) {
+        let (Some(serial), Some(config)) = (self.serial.clone(), self.serial_cfg.clone()) else {
+            self.to_self
+                .log(Severity::Error, "No device is connected".into());
+            return;
+        };
+        let (popup, config) = DeviceConfigurer::new(config);
+        self.to_self.new_component(Box::new(popup.for_open_port()));
+        tokio::spawn(async move {
+            if let Ok(config) = config.await {
+                _ = serial.send(ToSerialData::Configure(config));
+            }
+        });
+    }
+
     fn handle_watcher(&mut self, w: FromFileWatcher) {
         match w {
             FromFileWatcher::DisonnectRequest => {
@@ -173,12 +857,14 @@ impl App {
                     return;
                 };
                 _ = se.send(ToSerialData::Disconnect);
+                self.dashboard.listen(&GuiEvent::Reflashing(true));
             }
             FromFileWatcher::ReconnectRequest => {
                 let cfg = self.serial_cfg.clone().unwrap();
-                let serial = match cfg.to_serial() {
+                let serial = match cfg.clone().to_serial() {
                     Ok(o) => o,
                     Err(e) => {
+                        self.dashboard.listen(&GuiEvent::Reflashing(false));
                         self.to_self.log(
                             Severity::Error,
                             format!("Could not connect to serial: {}", e),
@@ -186,31 +872,25 @@ impl App {
                         return;
                     }
                 };
-                self.serial = Some(serial_handler(serial, self.to_self.clone()));
+                self.serial = Some(serial_handler(
+                    serial,
+                    self.to_self.clone(),
+                    self.capture.clone(),
+                    &cfg,
+                ));
             }
+            FromFileWatcher::Busy(_) | FromFileWatcher::Output(_) => {}
         }
     }
 
-    fn draw(&mut self, frame: &mut Frame) {
-        trace!("Drawing frame");
-        self.stack.retain(|i| i.alive());
-        // first element is given more space than others
-        // todo maybe consider having stuff store their own space?
-        self.stack.first_mut().unwrap().draw(frame.area(), frame);
-        for component in &mut self.stack.iter_mut().skip(1) {
-            trace!("Drawing popup!");
-            render_popup(component.as_mut(), frame.area(), frame);
-        }
-        trace!("Done Drawing");
-    }
-
     fn connect_serial(&mut self, cfg: DeviceOptions) {
         use crate::event::Severity;
         let app = self.to_self.clone();
+        let capture = self.capture.clone();
         tokio::spawn(
             async move {
                 let r: Result<()> = async {
-                    let (finder, rx) = DeviceFinder::new()?;
+                    let (finder, rx) = DeviceFinder::new(cfg.usb_filter(), cfg.all_ports)?;
                     app.new_component(Box::new(finder));
                     let Ok(path) = rx.await else { return Ok(()) };
                     let (popup, config) = DeviceConfigurer::new(cfg.to_config_path(path.into()));
@@ -218,11 +898,8 @@ impl App {
                     let Ok(config) = config.await else {
                         return Ok(());
                     };
-                    let serial = config
-                        .clone()
-                        .to_serial()
-                        .wrap_err("Could not connect to serial port")?;
-                    let serial = serial_handler(serial, app.clone());
+                    let serial = open_serial(&app, config.clone()).await?;
+                    let serial = serial_handler(serial, app.clone(), capture, &config);
                     app.send_app(AppEvent::SerialConnect(serial, config));
                     app.send_notif(GuiEvent::SerialDone);
                     Ok(())
@@ -236,59 +913,253 @@ impl App {
         );
     }
 
-    fn connect_serial_now(&mut self, config: DeviceConfig) {
-        let serial = match config.clone().to_serial() {
-            Ok(o) => o,
+    // falls back to the finder whenever the saved device can't be used
+    fn connect_last(&mut self, opts: &DeviceOptions) {
+        let config = match LastDevice::load() {
+            Ok(Some(last)) if last.is_present() => last.to_config(opts),
+            Ok(Some(last)) => Err(eyre!("{} is not present", last.path().display())),
+            Ok(None) => Err(eyre!("No device has been connected before")),
+            Err(e) => Err(e),
+        };
+        match config {
+            Ok(config) => self.connect_serial_now(config, true),
             Err(e) => {
                 self.to_self.log(
-                    Severity::Error,
-                    format!("Unable to connect to serial: {}", e),
+                    Severity::Info,
+                    format!("Could not reopen last device: {}", e),
                 );
-                return;
+                self.connect_serial(opts.clone());
             }
+        }
+    }
+
+    // Runs until the serial task goes away, so nothing is polled while no
+    // device is connected.
+    fn poll_status(&mut self, serial: &mpsc::UnboundedSender<ToSerialData>, every_ms: u64) {
+        if let Some(task) = self.status_poll.take() {
+            task.abort();
+        }
+        if every_ms == 0 {
+            return;
+        }
+        let serial = serial.clone();
+        self.status_poll = Some(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_millis(every_ms));
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                if serial.send(ToSerialData::RequestStatus).is_err() {
+                    break;
+                }
+            }
+        }));
+    }
+
+    // Tied to this port like the polls, so it stops while the device is
+    // closed for flashing and a new one starts on reconnect.
+    fn start_heartbeat(
+        &mut self,
+        serial: &mpsc::UnboundedSender<ToSerialData>,
+        opts: &DeviceOptions,
+    ) {
+        if let Some(task) = self.heartbeat.take() {
+            task.abort();
+        }
+        let Some(text) = opts.heartbeat.clone() else {
+            return;
         };
-        let serial = serial_handler(serial, self.to_self.clone());
-        self.to_self
-            .send_app(AppEvent::SerialConnect(serial, config));
+        if opts.heartbeat_interval == 0 {
+            return;
+        }
+        let every = Duration::from_secs(opts.heartbeat_interval);
+        let serial = serial.clone();
+        let to_self = self.to_self.clone();
+        let paused = self.heartbeat_paused.clone();
+        self.heartbeat = Some(tokio::spawn(async move {
+            let start = tokio::time::Instant::now() + every;
+            let mut interval = tokio::time::interval_at(start, every);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                if serial.is_closed() {
+                    break;
+                }
+                if paused.load(Ordering::Relaxed) {
+                    continue;
+                }
+                to_self.log(Severity::Debug, format!("Heartbeat {:?}", text));
+                to_self.send_app(AppEvent::SendSerial(ToSerialData::Data(text.clone())));
+            }
+        }));
     }
 
-    fn upload_file(&mut self, path: Option<String>, cmd_default: String, autorun: bool) {
-        use crate::event::Severity;
-        let to_dash = self.to_self.clone();
+    fn toggle_heartbeat(&mut self) {
+        if self.heartbeat.as_ref().is_none_or(|t| t.is_finished()) {
+            self.to_self.log(
+                Severity::Info,
+                "No heartbeat is running, see --heartbeat".into(),
+            );
+            return;
+        }
+        let paused = !self.heartbeat_paused.fetch_xor(true, Ordering::Relaxed);
+        let msg = if paused {
+            "Heartbeat paused"
+        } else {
+            "Heartbeat resumed"
+        };
+        self.to_self.log(Severity::Info, msg.into());
+    }
+
+    // Some adapters go quiet when pulled out instead of failing the read, so
+    // the port is looked for while connected. Stops with the serial task.
+    fn poll_presence(&mut self, serial: &mpsc::UnboundedSender<ToSerialData>, c: &DeviceConfig) {
+        if let Some(task) = self.presence_poll.take() {
+            task.abort();
+        }
+        if c.presence_poll_ms == 0 {
+            return;
+        }
+        let serial = serial.clone();
+        let path = c.path.clone();
+        let every = Duration::from_millis(c.presence_poll_ms);
+        self.presence_poll = Some(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(every);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            while !serial.is_closed() {
+                interval.tick().await;
+                let path = path.clone();
+                // listing ports can block on udev
+                let present = tokio::task::spawn_blocking(move || port_present(&path))
+                    .await
+                    .unwrap_or(true);
+                if !present {
+                    _ = serial.send(ToSerialData::Unplugged);
+                    break;
+                }
+            }
+        }));
+    }
+
+    fn disconnect(&mut self) {
+        self.stop_reconnect();
+        let Some(se) = self.serial.as_ref() else {
+            self.to_self
+                .log(Severity::Info, "Not currently connected to a device".into());
+            self.reopen = false;
+            return;
+        };
+        self.user_disconnect = true;
+        _ = se.send(ToSerialData::Disconnect);
+    }
+
+    // opens the most recently used configuration again
+    fn reopen_device(&mut self) {
+        self.stop_reconnect();
+        match self.serial_cfg.clone() {
+            Some(cfg) => self.connect_serial_now(cfg, false),
+            None => self
+                .to_self
+                .log(Severity::Info, "No device to reconnect to".into()),
+        }
+    }
+
+    // opens config without asking, or the finder with fallback if that fails
+    fn connect_serial_now(&mut self, config: DeviceConfig, fallback: bool) {
+        let app = self.to_self.clone();
+        let capture = self.capture.clone();
         tokio::spawn(
             async move {
-                let path = path.map(PathBuf::from);
-                let Ok((finder, f)) =
-                    FileViewer::new("Select Binary".into(), to_dash.clone(), path)
-                else {
-                    to_dash.log(Severity::Error, "Could not open working directory".into());
-                    return;
-                };
-                to_dash.new_component(Box::new(finder));
-                let Ok(file) = f.await else {
-                    return;
-                };
-                let (input, cmd) = CmdInput::new(
-                    "Enter upload command (replace binary path with #BIN#)".into(),
-                    cmd_default,
-                );
-                to_dash.new_component(Box::new(input));
-                let Ok(cmd) = cmd.await else {
-                    return;
-                };
-                let Ok(watcher) = new_filewatcher(&file, cmd, to_dash.clone(), autorun) else {
-                    return;
-                };
-                to_dash.send_app(AppEvent::SendUpload(watcher));
+                match open_serial(&app, config.clone()).await {
+                    Ok(serial) => {
+                        let serial = serial_handler(serial, app.clone(), capture, &config);
+                        app.send_app(AppEvent::SerialConnect(serial, config));
+                    }
+                    Err(e) => {
+                        app.log(Severity::Error, format!("Unable to connect: {}", e));
+                        if fallback {
+                            app.send_app(AppEvent::RequestSerial);
+                        }
+                    }
+                }
             }
-            .instrument(tracing::info_span!("Watcher sequence")),
+            .instrument(tracing::info_span!("Open")),
         );
     }
-    async fn next(&mut self) -> color_eyre::Result<ToAppEvent> {
-        self.inbox
-            .recv()
-            .await
-            .ok_or_eyre("Failed to receive event")
+
+    // Retries the last configuration with a growing delay until the port is
+    // listed again.
+    fn auto_reconnect(&mut self) {
+        let Some(config) = self.serial_cfg.clone() else {
+            return;
+        };
+        let app = self.to_self.clone();
+        let capture = self.capture.clone();
+        let task = async move {
+            const MAX_DELAY: Duration = Duration::from_secs(4);
+            let mut delay = Duration::from_millis(500);
+            let name = config.path.to_string_lossy().to_string();
+            for attempt in 1.. {
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(MAX_DELAY);
+                app.log(
+                    Severity::Info,
+                    format!("Reconnecting to {} (attempt {})", name, attempt),
+                );
+                let present = tokio_serial::available_ports()
+                    .map(|ports| ports.iter().any(|p| p.port_name == name))
+                    .unwrap_or(false);
+                if !present {
+                    continue;
+                }
+                match open_serial(&app, config.clone()).await {
+                    Ok(serial) => {
+                        let serial = serial_handler(serial, app.clone(), capture, &config);
+                        app.send_app(AppEvent::SerialConnect(serial, config));
+                        return;
+                    }
+                    Err(e) => app.log(Severity::Info, format!("Reconnect failed: {}", e)),
+                }
+            }
+        };
+        self.stop_reconnect();
+        self.reconnect = Some(tokio::spawn(
+            task.instrument(tracing::info_span!("Reconnect")),
+        ));
+    }
+
+    fn stop_reconnect(&mut self) {
+        if let Some(task) = self.reconnect.take() {
+            task.abort();
+        }
+    }
+
+    fn toggle_capture(&mut self, path: Option<PathBuf>) {
+        if let Some(old) = self.capture.stop() {
+            self.to_self.log(
+                Severity::Info,
+                format!("Stopped capture to {}", old.display()),
+            );
+            self.dashboard.listen(&GuiEvent::Capture(None));
+            return;
+        }
+        let path = path.unwrap_or_else(|| {
+            let secs = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default();
+            PathBuf::from(format!("seterm-capture-{}.log", secs))
+        });
+        self.start_capture(path);
+    }
+
+    fn start_capture(&mut self, path: PathBuf) {
+        if let Err(e) = self.capture.start(path.clone()) {
+            self.to_self.log(Severity::Error, format!("{}", e));
+            return;
+        }
+        self.to_self
+            .log(Severity::Info, format!("Capturing to {}", path.display()));
+        self.dashboard.listen(&GuiEvent::Capture(Some(path)));
     }
 
     fn send_serial(&mut self, data: ToSerialData) {
@@ -303,22 +1174,47 @@ impl App {
             );
         }
     }
+}
 
-    fn create_help(&mut self) {
-        const HELP_STRING: &str = "ALT+?: Show this help\nctrl+c: Exit application\n\
-          ESC: Close popup/exit application\nctrl+f: Find serial\nctrl+u: Upload file";
-        self.to_self
-            .new_component(Box::new(Notification::new(HELP_STRING.into())));
+// a hex file is decoded completely before anything is sent
+async fn read_file(path: &Path, hex: bool) -> Result<Vec<u8>> {
+    let bytes = tokio::fs::read(path).await?;
+    if !hex {
+        return Ok(bytes);
     }
+    parse_hex(std::str::from_utf8(&bytes)?)
 }
 
-fn render_popup(popup: &mut dyn Reactive, area: Rect, buf: &mut Frame) {
-    let x_margin = area.width / 4;
-    let y_margin = area.height / 4;
-    let area = area.inner(ratatui::layout::Margin {
-        horizontal: x_margin,
-        vertical: y_margin,
-    });
+// popups without a size of their own take half the screen, but no less
+// than will fit a table of settings and no more than is comfortable to read
+// on a large monitor
+const POPUP_MIN: (u16, u16) = (60, 14);
+const POPUP_MAX: (u16, u16) = (120, 32);
+
+fn render_popup(popup: &mut dyn Reactive, area: Rect, buf: &mut Frame, theme: &Theme) {
+    let (width, height) = popup.preferred_size().unwrap_or((
+        (area.width / 2).clamp(POPUP_MIN.0, POPUP_MAX.0),
+        (area.height / 2).clamp(POPUP_MIN.1, POPUP_MAX.1),
+    ));
+    popup.draw(centered(area, width, height), buf, theme);
+}
+
+// a width by height rect in the middle of area, cut down to fit it
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let [area] = Layout::horizontal([Constraint::Length(width)])
+        .flex(Flex::Center)
+        .areas(area);
+    let [area] = Layout::vertical([Constraint::Length(height)])
+        .flex(Flex::Center)
+        .areas(area);
+    area
+}
 
-    popup.draw(area, buf);
+// Opening can take a while on a flaky adapter, so it runs off the async
+// threads while the dashboard shows what is being connected to.
+async fn open_serial(app: &Messenger, config: DeviceConfig) -> Result<SerialStream, SerialError> {
+    app.send_notif(GuiEvent::Connecting(Some(config.path.clone())));
+    let opened = tokio::task::spawn_blocking(move || config.to_serial()).await;
+    app.send_notif(GuiEvent::Connecting(None));
+    opened.unwrap_or_else(|e| Err(SerialError::Io(e.to_string())))
 }
@@ -1,30 +1,357 @@
-use clap::{ArgAction, Parser};
+use clap::{
+    ArgAction, ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum,
+    error::ErrorKind, parser::ValueSource,
+};
 use color_eyre::Result;
-use eyre::eyre;
-use notify::event::DataChange;
+use crossterm::event::KeyCode;
+use eyre::{Context, eyre};
+use serde::Deserialize;
 use serialport::{DataBits, FlowControl, Parity, StopBits};
-use std::{fmt::Display, path::PathBuf};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    path::{Path, PathBuf},
+};
 
-use crate::device_finder::{Baud, DeviceConfig};
+use crate::{
+    device_finder::{
+        Baud, DEFAULT_READ_BUFFER, DeviceConfig, LineOnClose, ResetSequence, TxPacing, UsbFilter,
+    },
+    event::CaptureFormat,
+    framing::Framing,
+    highlight::{HighlightConfig, HighlightRule},
+    keys::{Action, KeyMap, Macro, MacroConfig, parse_function_key, unescape},
+    state::ViewPrefs,
+    theme::{ColorsConfig, ThemeColors, ThemePreset},
+    trigger::{Trigger, TriggerConfig},
+    ui::LineEnding,
+};
 
 #[derive(Debug, Parser)]
 #[command(version, about, long_about)]
 /// Seterm configuration is done primarily through TUI, although defaults can be set via the commandline.
 /// Use the keyboard to enter input, and use alt+? to view the help menu. Use ctrl+c to quit the application,
 /// or ESC to close a popup. Use ctrl+f to find and connect a device, and ctrl+u to select a file to upload.
-/// Use ctrl+d to toggle DTR, and ctrl+r to toggle rts.
+/// Use ctrl+t to send the contents of a file over the serial line (press x while picking to send a text file of hex bytes decoded), or ctrl+x to send it with XMODEM, and ctrl+o to view the output of recent uploads.
+/// Use ctrl+d to toggle DTR, and ctrl+r to toggle rts. Use alt+b to reset the board with a DTR/RTS pulse, see --reset. Use ctrl+g to switch between text and hex view.
+/// Use alt+/ to search the scrollback, then n/N to step between matches and alt+c to toggle case.
+/// Use alt+s to select lines with the arrow keys, v to mark the start of a range and y to copy it (needs the clipboard feature).
+/// Use alt+enter to start another line in the input, enter then sends them all with the line ending after each.
+/// Use ctrl+b to type raw bytes as hex instead of text, or alt+i to send every key as it is typed, for shells and REPLs on the device. Use ctrl+e to cycle the line ending appended to sent lines. Use ctrl+s to start or stop capturing received data to a file.
+/// Use alt+l to show only info or error messages in the log pane.
+/// Use ctrl+l to clear the scrollback, ctrl+w to save it to a file, ctrl+p to stop following new output so the view stays put while it keeps being received,
+/// and alt+d to switch between showing all lines, only received lines or only sent lines. Sent lines are marked with ">".
+/// Use alt+v to show control characters as ^G style escapes, and alt+w to wrap long lines instead of cutting them off.
+/// Use alt+t to open another device in a new tab, alt+. and alt+, or alt+1..9 to switch tabs, and alt+q to close one.
+/// Use alt+x to disconnect the device and alt+r to reconnect it with the same settings.
+/// Use --heartbeat to send a keep-alive every --heartbeat-interval seconds while connected, and alt+h to pause or resume it.
+/// Press alt+p to change the baud rate, framing or flow control of the open port without reconnecting.
+/// Press alt+a instead of enter to send a line and log whatever the device answers within --response-timeout-ms, e.g. for AT commands.
+/// Press alt+m to type a label and drop a timestamped marker into the scrollback and any capture file.
+/// Use --last to reopen whichever device was connected most recently.
+/// Use --close-dtr and --close-rts (high, low or keep) to choose where the control lines are left when a port is closed, and --break-on-close to send a break first.
+/// Use --replay to show a file written by --capture or --emit-json as if it were being received, keeping the original timing of JSON sessions.
+/// Use --emit-json to stream everything sent and received as JSON lines to a file, named pipe or unix:PATH socket for other tools.
+/// Set LOG_PATH to write a diagnostic log, filtered with RUST_LOG; --log-max-size and --log-keep rotate it.
+/// Defaults are read from ~/.config/seterm/config.toml if it exists; flags given on the commandline take priority.
+/// Keys can be rebound in its [keys] table, e.g. upload = "f5" or toggle-dtr = "f2".
+/// Received text can be colored with [[highlight]] entries, e.g. text = "ERROR" and color = "red", or regex = "WARN.*" with whole-line = true.
+/// Received text can also set off a [[trigger]], e.g. text = "PANIC", which rings the bell and flashes the status pane
+/// until a key is pressed; bell = false and flash = false turn those off, and command = "notify-send seterm" runs a command with the line in $SETERM_LINE.
+/// Function keys can send text from its [macros] table, e.g. f1 = "reset" or f2 = { text = "\\x1bstatus", line-ending = "none" }.
+/// Pick a color scheme with --theme, and override single colors in its [colors] table, e.g. debug = "green" or highlight-bg = "yellow",
+/// and glyphs with scrollbar-thumb, scrollbar-track, cursor, led-on and led-off. The selected item of lists and tables uses selected-fg, selected-bg and selected-bold = true/false.
+/// Upload command templates can be added in its [templates] table, e.g. pico = "picotool load -x #BIN#".
+/// Use `seterm check-upload FILE CMD` to check an upload command without watching anything; the same checks run when an upload is armed.
 pub struct CliConfiguration {
-    #[arg(long, help = "Default binary to upload")]
+    #[arg(
+        long,
+        help = "Read defaults from this file instead of ~/.config/seterm/config.toml"
+    )]
+    pub config: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "Binary ctrl+u watches without asking, or a directory to start picking from"
+    )]
     pub watch_path: Option<PathBuf>,
-    #[arg(short = 'c', long, help = "Default upload command")]
+    #[arg(
+        short = 'c',
+        long,
+        help = "Upload command ctrl+u uses without asking once a binary is picked"
+    )]
     pub default_cmd: Option<String>,
+    #[arg(long, help = "Append all received data to this file")]
+    pub capture: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "TARGET",
+        help = "Also write everything sent and received as JSON lines to this file, or to unix:PATH"
+    )]
+    pub emit_json: Option<String>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Play back a capture or --emit-json file instead of opening a device"
+    )]
+    pub replay: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "Copy stdin to --path and what it sends to stdout, without the TUI. Ends when stdin does"
+    )]
+    pub pipe: bool,
+    #[arg(
+        long,
+        value_parser = parse_size,
+        default_value = "0",
+        help = "Start a new LOG_PATH file once it reaches this size, e.g. 10M. 0 disables. Needs --log-keep"
+    )]
+    pub log_max_size: u64,
+    #[arg(
+        long,
+        default_value_t = 0,
+        help = "Keep this many old LOG_PATH files as LOG_PATH.1, .2 and so on, starting a new one each run"
+    )]
+    pub log_keep: usize,
     #[command(flatten)]
     pub device: DeviceOptions,
+    #[command(flatten)]
+    pub terminal: TerminalOptions,
+    #[command(flatten)]
+    pub watch: WatchOptions,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Print the available serial devices and exit
+    Ports {
+        #[arg(long, help = "Print as a JSON array")]
+        json: bool,
+        #[arg(
+            long,
+            help = "Also list built-in and unknown ports, not only USB and Bluetooth"
+        )]
+        all: bool,
+    },
+    /// Check an upload command against a binary without watching or running it
+    CheckUpload {
+        #[arg(help = "File, directory or glob that would be watched")]
+        file: PathBuf,
+        #[arg(help = "Upload command, with #BIN# where the binary path goes")]
+        cmd: String,
+    },
+}
+
+impl CliConfiguration {
+    /// Parses the commandline alone, so the diagnostic log can be set up
+    /// before the config file is read.
+    pub fn parse_flags() -> (Self, ArgMatches) {
+        let matches = Self::command().get_matches();
+        let args = Self::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+        // starting over at the limit with nothing kept would lose the whole log
+        if args.log_max_size > 0 && args.log_keep == 0 {
+            Self::command()
+                .error(
+                    ErrorKind::MissingRequiredArgument,
+                    "--log-max-size needs --log-keep of at least 1",
+                )
+                .exit();
+        }
+        (args, matches)
+    }
+
+    /// Fills in anything not given explicitly on the commandline from the config file.
+    pub fn load(self, matches: &ArgMatches) -> Result<Self> {
+        let mut args = self;
+        let file = match &args.config {
+            Some(path) => Some(ConfigFile::read(path)?),
+            None => match default_config_path() {
+                Some(path) if path.exists() => Some(ConfigFile::read(&path)?),
+                _ => None,
+            },
+        };
+        let file_line_ending = file.as_ref().is_some_and(|f| f.line_ending.is_some());
+        if let Some(file) = file {
+            file.apply(&mut args, matches)?;
+        }
+        // A broken state file only costs the saved view, so it isn't fatal.
+        // The view only fills in what neither the flags nor the file set.
+        if let Ok(Some(view)) = ViewPrefs::load() {
+            let saved = |id: &str| matches.value_source(id) != Some(ValueSource::CommandLine);
+            if saved("line_ending") && !file_line_ending {
+                args.terminal.line_ending = view.line_ending;
+            }
+            if saved("wrap") {
+                args.terminal.wrap = view.wrap;
+            }
+            args.terminal.view = view;
+        }
+        Ok(args)
+    }
+}
+
+/// Where seterm keeps its config file and saved state.
+pub fn config_dir() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("seterm"))
+}
+
+fn default_config_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("config.toml"))
+}
+
+/// Numbers can be written either bare or quoted, e.g. `baud = 9600` or `baud = "1152k"`
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Scalar {
+    Int(i64),
+    Str(String),
+}
+
+impl Display for Scalar {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Scalar::Int(i) => write!(f, "{i}"),
+            Scalar::Str(s) => write!(f, "{s}"),
+        }
+    }
+}
+
+/// Contents of the config file. Every field is optional and uses the same
+/// values as the matching commandline flag.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct ConfigFile {
+    baud: Option<Scalar>,
+    bits: Option<Scalar>,
+    flow: Option<String>,
+    parity: Option<String>,
+    stop: Option<Scalar>,
+    dtr: Option<bool>,
+    close_dtr: Option<String>,
+    close_rts: Option<String>,
+    break_on_close: Option<u64>,
+    default_cmd: Option<String>,
+    watch_path: Option<PathBuf>,
+    line_ending: Option<String>,
+    framing: Option<String>,
+    dim_unfocused: Option<bool>,
+    // action name to key, e.g. `upload = "f5"`
+    keys: Option<HashMap<Action, String>>,
+    // extra upload command templates, name to command
+    templates: Option<BTreeMap<String, String>>,
+    highlight: Option<Vec<HighlightConfig>>,
+    trigger: Option<Vec<TriggerConfig>>,
+    theme: Option<String>,
+    colors: Option<ColorsConfig>,
+    // function key name to macro, e.g. `f1 = "reset"`
+    macros: Option<HashMap<String, MacroConfig>>,
+}
+
+impl ConfigFile {
+    fn read(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("Could not read config file {}", path.display()))?;
+        toml::from_str(&text).wrap_err_with(|| format!("Malformed config file {}", path.display()))
+    }
+
+    fn apply(self, args: &mut CliConfiguration, matches: &ArgMatches) -> Result<()> {
+        // explicit flags always win over the file
+        let from_file = |id: &str| matches.value_source(id) != Some(ValueSource::CommandLine);
+        let invalid = |key: &str| format!("Invalid {key} in config file");
+        let dev = &mut args.device;
+        if let Some(b) = self.baud.filter(|_| from_file("baud")) {
+            dev.baud = parse_baud(&b.to_string()).wrap_err_with(|| invalid("baud"))?;
+        }
+        if let Some(b) = self.bits.filter(|_| from_file("bits")) {
+            dev.bits = parse_data(&b.to_string()).wrap_err_with(|| invalid("bits"))?;
+        }
+        if let Some(f) = self.flow.filter(|_| from_file("flow")) {
+            dev.flow = parse_flow(&f).wrap_err_with(|| invalid("flow"))?;
+        }
+        if let Some(p) = self.parity.filter(|_| from_file("parity")) {
+            dev.parity = parse_parity(&p).wrap_err_with(|| invalid("parity"))?;
+        }
+        if let Some(s) = self.stop.filter(|_| from_file("stop")) {
+            dev.stop = parse_stop(&s.to_string()).wrap_err_with(|| invalid("stop"))?;
+        }
+        if let Some(d) = self.dtr.filter(|_| from_file("dtr")) {
+            dev.dtr = d;
+        }
+        if let Some(d) = self.close_dtr.filter(|_| from_file("close_dtr")) {
+            dev.close_dtr = LineOnClose::from_str(&d, true)
+                .map_err(|e| eyre!(e))
+                .wrap_err_with(|| invalid("close-dtr"))?;
+        }
+        if let Some(r) = self.close_rts.filter(|_| from_file("close_rts")) {
+            dev.close_rts = LineOnClose::from_str(&r, true)
+                .map_err(|e| eyre!(e))
+                .wrap_err_with(|| invalid("close-rts"))?;
+        }
+        if let Some(b) = self.break_on_close.filter(|_| from_file("break_on_close")) {
+            dev.break_on_close = b;
+        }
+        if let Some(c) = self.default_cmd.filter(|_| from_file("default_cmd")) {
+            args.default_cmd = Some(c);
+        }
+        if let Some(p) = self.watch_path.filter(|_| from_file("watch_path")) {
+            args.watch_path = Some(p);
+        }
+        if let Some(e) = self.line_ending.filter(|_| from_file("line_ending")) {
+            args.terminal.line_ending = LineEnding::from_str(&e, true)
+                .map_err(|e| eyre!(e))
+                .wrap_err_with(|| invalid("line-ending"))?;
+        }
+        if let Some(f) = self.framing.filter(|_| from_file("framing")) {
+            args.terminal.framing = f.parse().wrap_err_with(|| invalid("framing"))?;
+        }
+        if let Some(d) = self.dim_unfocused.filter(|_| from_file("dim_unfocused")) {
+            args.terminal.dim_unfocused = d;
+        }
+        if let Some(t) = self.theme.filter(|_| from_file("theme")) {
+            args.terminal.theme = ThemePreset::from_str(&t, true)
+                .map_err(|e| eyre!(e))
+                .wrap_err_with(|| invalid("theme"))?;
+        }
+        if let Some(c) = self.colors {
+            args.terminal.colors = c.try_into().wrap_err_with(|| invalid("colors"))?;
+        }
+        if let Some(rules) = self.highlight {
+            args.terminal.highlights = rules
+                .into_iter()
+                .map(HighlightRule::try_from)
+                .collect::<Result<_>>()
+                .wrap_err_with(|| invalid("highlight"))?;
+        }
+        if let Some(triggers) = self.trigger {
+            args.terminal.triggers = triggers
+                .into_iter()
+                .map(Trigger::try_from)
+                .collect::<Result<_>>()
+                .wrap_err_with(|| invalid("trigger"))?;
+        }
+        if let Some(templates) = self.templates {
+            args.watch.templates = templates;
+        }
+        if let Some(keys) = self.keys {
+            args.terminal.keys = KeyMap::with_overrides(keys).wrap_err_with(|| invalid("keys"))?;
+        }
+        // after [keys], so a key taken by an action can be reported
+        for (key, m) in self.macros.unwrap_or_default() {
+            let n = parse_function_key(&key).wrap_err_with(|| invalid("macros"))?;
+            if args.terminal.keys.is_bound(KeyCode::F(n)) {
+                return Err(eyre!("{} is used by both a macro and a keybinding", key));
+            }
+            let m = Macro::try_from(m).wrap_err_with(|| invalid("macros"))?;
+            args.terminal.macros.insert(n, m);
+        }
+        Ok(())
+    }
 }
 
 impl Display for Baud {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", *self as usize)
+        write!(f, "{}", self.0)
     }
 }
 
@@ -44,6 +371,99 @@ pub struct DeviceOptions {
     pub stop: StopBits,
     #[arg(short = 'r', long="no-dtr", long, default_value_t = true, action = ArgAction::SetFalse, help = "Whether DTR is asserted on start or not")]
     pub dtr: bool,
+    #[arg(long, value_parser = parse_read_buffer, default_value = "4096", help = "Bytes to read from the port at once")]
+    pub read_buffer: usize,
+    #[arg(
+        long,
+        default_value_t = 0,
+        help = "Milliseconds to wait after each line sent (or byte, see --tx-pacing). 0 sends at full speed"
+    )]
+    pub tx_delay_ms: u64,
+    #[arg(
+        long,
+        value_enum,
+        default_value = "line",
+        help = "Whether --tx-delay-ms applies per line or per byte"
+    )]
+    pub tx_pacing: TxPacing,
+    #[arg(
+        long,
+        default_value_t = 0,
+        help = "Report the device as idle after this many seconds without data. 0 disables"
+    )]
+    pub idle_timeout: u64,
+    #[arg(
+        long,
+        default_value_t = 500,
+        help = "Milliseconds between reads of the control lines (CTS/DSR/RI/CD). 0 disables"
+    )]
+    pub status_poll_ms: u64,
+    #[arg(
+        long,
+        default_value_t = 1000,
+        help = "Milliseconds between checks that the device is still plugged in. 0 disables"
+    )]
+    pub presence_poll_ms: u64,
+    #[arg(
+        long,
+        value_enum,
+        default_value = "dtr",
+        help = "How the control lines are pulsed to reset the board (alt+b)"
+    )]
+    pub reset: ResetSequence,
+    #[arg(
+        long,
+        default_value_t = 100,
+        help = "Milliseconds the reset lines are held asserted"
+    )]
+    pub reset_pulse_ms: u64,
+    #[arg(long, help = "Same as --close-dtr low --close-rts low")]
+    pub release_on_quit: bool,
+    #[arg(
+        long,
+        value_enum,
+        default_value = "keep",
+        help = "Level to leave DTR at when the port is closed"
+    )]
+    pub close_dtr: LineOnClose,
+    #[arg(
+        long,
+        value_enum,
+        default_value = "keep",
+        help = "Level to leave RTS at when the port is closed"
+    )]
+    pub close_rts: LineOnClose,
+    #[arg(
+        long,
+        default_value_t = 0,
+        help = "Send a break of this many milliseconds before the port is closed. 0 disables"
+    )]
+    pub break_on_close: u64,
+    #[arg(long, help = "Reopen the device used last time")]
+    pub last: bool,
+    #[arg(long, help = "Keep trying to reopen the port if it is lost")]
+    pub auto_reconnect: bool,
+    #[arg(
+        long,
+        help = "List built-in and unknown ports in the finder too, not only USB and Bluetooth"
+    )]
+    pub all_ports: bool,
+    #[arg(long, value_parser = parse_usb_id, help = "Only list USB devices with this vendor id (hex)")]
+    pub vid: Option<u16>,
+    #[arg(long, value_parser = parse_usb_id, help = "Only list USB devices with this product id (hex)")]
+    pub pid: Option<u16>,
+    #[arg(long, help = "Send this line as soon as a device is connected")]
+    pub on_connect: Option<String>,
+    #[arg(long, help = "Send this line after an automatic reconnect")]
+    pub on_reconnect: Option<String>,
+    #[arg(
+        long,
+        value_parser = parse_escaped,
+        help = "Send this text every --heartbeat-interval while connected, with escapes like \\r (alt+h pauses)"
+    )]
+    pub heartbeat: Option<String>,
+    #[arg(long, default_value_t = 5, help = "Seconds between heartbeats")]
+    pub heartbeat_interval: u64,
 }
 
 impl Default for DeviceOptions {
@@ -56,6 +476,163 @@ impl Default for DeviceOptions {
             parity: Parity::None,
             stop: StopBits::One,
             dtr: true,
+            read_buffer: DEFAULT_READ_BUFFER,
+            tx_delay_ms: 0,
+            tx_pacing: TxPacing::Line,
+            idle_timeout: 0,
+            status_poll_ms: 500,
+            presence_poll_ms: 1000,
+            reset: ResetSequence::Dtr,
+            reset_pulse_ms: 100,
+            release_on_quit: false,
+            close_dtr: LineOnClose::Keep,
+            close_rts: LineOnClose::Keep,
+            break_on_close: 0,
+            last: false,
+            auto_reconnect: false,
+            all_ports: false,
+            vid: None,
+            pid: None,
+            on_connect: None,
+            on_reconnect: None,
+            heartbeat: None,
+            heartbeat_interval: 5,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Parser)]
+pub struct TerminalOptions {
+    #[arg(
+        short = 'e',
+        long,
+        value_enum,
+        default_value = "lf",
+        help = "Appended to each line sent"
+    )]
+    pub line_ending: LineEnding,
+    #[arg(
+        long,
+        default_value = "lf",
+        help = "Where received lines end: lf, cr, crlf, byte:N for another ASCII byte, or fixed:N for every N characters"
+    )]
+    pub framing: Framing,
+    #[arg(long, help = "Ask before quitting while capturing or uploading")]
+    pub confirm_quit: bool,
+    #[arg(
+        long,
+        default_value_t = 4096,
+        help = "Split received lines longer than this many characters. 0 disables"
+    )]
+    pub max_line_len: usize,
+    #[arg(
+        long,
+        default_value_t = 8,
+        help = "Expand tabs in received text to every this many columns. 0 leaves them as received"
+    )]
+    pub tabstop: usize,
+    #[arg(
+        long,
+        value_enum,
+        default_value = "raw",
+        help = "How --capture and ctrl+s write their file. json and text-ts also record what was sent"
+    )]
+    pub capture_format: CaptureFormat,
+    #[arg(
+        long,
+        default_value_t = 10000,
+        help = "Lines of received output to keep. 0 keeps everything"
+    )]
+    pub scrollback: usize,
+    #[arg(long, help = "Wrap long lines instead of cutting them off (alt+w)")]
+    pub wrap: bool,
+    #[arg(long = "no-follow", default_value_t = true, action = ArgAction::SetFalse, help = "Start with the view staying put as new output arrives (ctrl+p)")]
+    pub follow: bool,
+    #[arg(
+        long,
+        help = "Dim the display and stop the cursor blinking while the terminal window is in the background"
+    )]
+    pub dim_unfocused: bool,
+    #[arg(
+        long,
+        default_value_t = 1000,
+        help = "Milliseconds alt+a collects the response to a line for"
+    )]
+    pub response_timeout_ms: u64,
+    #[arg(long, value_enum, default_value = "dark", help = "Color scheme")]
+    pub theme: ThemePreset,
+    // from the [colors] table of the config file
+    #[arg(skip)]
+    pub colors: ThemeColors,
+    #[arg(skip)]
+    pub keys: KeyMap,
+    // from the [[highlight]] entries of the config file
+    #[arg(skip)]
+    pub highlights: Vec<HighlightRule>,
+    // from the [[trigger]] entries of the config file
+    #[arg(skip)]
+    pub triggers: Vec<Trigger>,
+    // function key number to what it sends, from the [macros] table
+    #[arg(skip)]
+    pub macros: HashMap<u8, Macro>,
+    // how the dashboard was set up last time
+    #[arg(skip)]
+    pub view: ViewPrefs,
+}
+
+impl Default for TerminalOptions {
+    fn default() -> Self {
+        Self {
+            line_ending: LineEnding::Lf,
+            framing: Framing::Lf,
+            confirm_quit: false,
+            max_line_len: 4096,
+            tabstop: 8,
+            capture_format: CaptureFormat::Raw,
+            scrollback: 10000,
+            wrap: false,
+            follow: true,
+            dim_unfocused: false,
+            response_timeout_ms: 1000,
+            theme: ThemePreset::Dark,
+            colors: ThemeColors::default(),
+            keys: KeyMap::default(),
+            highlights: Vec::new(),
+            triggers: Vec::new(),
+            macros: HashMap::new(),
+            view: ViewPrefs::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Parser)]
+pub struct WatchOptions {
+    #[arg(
+        long,
+        default_value_t = 300,
+        help = "Milliseconds the watched file must stay unchanged before uploading"
+    )]
+    pub debounce_ms: u64,
+    #[arg(long, help = "Keep the serial port open while the upload command runs")]
+    pub no_disconnect: bool,
+    #[arg(
+        long = "ext",
+        value_delimiter = ',',
+        help = "Only list files with these extensions when picking a binary, e.g. --ext bin,elf,hex"
+    )]
+    pub extensions: Vec<String>,
+    // from the [templates] table of the config file
+    #[arg(skip)]
+    pub templates: BTreeMap<String, String>,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        Self {
+            debounce_ms: 300,
+            no_disconnect: false,
+            extensions: Vec::new(),
+            templates: BTreeMap::new(),
         }
     }
 }
@@ -63,15 +640,22 @@ impl Default for DeviceOptions {
 impl DeviceOptions {
     pub fn to_config(&mut self) -> Option<DeviceConfig> {
         let path = self.path.take()?;
-        Some(DeviceConfig {
-            path,
-            baud: self.baud,
-            bits: self.bits,
-            flow: self.flow,
-            parity: self.parity,
-            stop: self.stop,
-            dtr: self.dtr,
-        })
+        Some(self.to_config_path(path))
+    }
+
+    pub fn usb_filter(&self) -> UsbFilter {
+        UsbFilter {
+            vid: self.vid,
+            pid: self.pid,
+        }
+    }
+
+    // --release-on-quit lowers whichever line has no level of its own
+    fn close_level(&self, level: LineOnClose) -> LineOnClose {
+        match level {
+            LineOnClose::Keep if self.release_on_quit => LineOnClose::Low,
+            level => level,
+        }
     }
 
     pub fn to_config_path(&self, path: PathBuf) -> DeviceConfig {
@@ -83,26 +667,78 @@ impl DeviceOptions {
             parity: self.parity,
             stop: self.stop,
             dtr: self.dtr,
+            read_buffer: self.read_buffer,
+            tx_delay_ms: self.tx_delay_ms,
+            tx_pacing: self.tx_pacing,
+            idle_timeout_secs: self.idle_timeout,
+            status_poll_ms: self.status_poll_ms,
+            presence_poll_ms: self.presence_poll_ms,
+            reset: self.reset,
+            reset_pulse_ms: self.reset_pulse_ms,
+            close_dtr: self.close_level(self.close_dtr),
+            close_rts: self.close_level(self.close_rts),
+            close_break_ms: self.break_on_close,
         }
     }
 }
 
-fn parse_baud(arg: &str) -> Result<Baud> {
-    use Baud::{B48, B96, B192, B384, B576, B1152};
+pub fn parse_baud(arg: &str) -> Result<Baud> {
     match arg {
-        "4800" | "48k" => Ok(B48),
-        "9600" | "96k" => Ok(B96),
-        "19200" | "192k" => Ok(B192),
-        "38400" | "384k" => Ok(B384),
-        "57600" | "576k" => Ok(B576),
-        "115200" | "1152k" => Ok(B1152),
-        _ => Err(eyre!(
-            "Valid baud rates are: 48k, 96k, 192k, 384k, 576k, 1152k"
-        )),
+        "48k" => Ok(Baud::B48),
+        "96k" => Ok(Baud::B96),
+        "192k" => Ok(Baud::B192),
+        "384k" => Ok(Baud::B384),
+        "576k" => Ok(Baud::B576),
+        "1152k" => Ok(Baud::B1152),
+        "2304k" => Ok(Baud::B2304),
+        "4608k" => Ok(Baud::B4608),
+        "9216k" => Ok(Baud::B9216),
+        _ => match arg.parse::<u32>() {
+            Ok(b) if b > 0 => Ok(Baud(b)),
+            _ => Err(eyre!(
+                "Baud rate must be a positive integer or one of: 48k, 96k, 192k, 384k, 576k, 1152k, 2304k, 4608k, 9216k"
+            )),
+        },
+    }
+}
+
+fn parse_read_buffer(arg: &str) -> Result<usize> {
+    match arg.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(eyre!("Read buffer size must be a positive integer")),
     }
 }
 
-fn parse_data(arg: &str) -> Result<DataBits> {
+// bytes, with an optional K, M or G suffix
+fn parse_size(arg: &str) -> Result<u64> {
+    let (digits, unit) = match arg.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&arg[..i], 1 << 10),
+        Some((i, 'm' | 'M')) => (&arg[..i], 1 << 20),
+        Some((i, 'g' | 'G')) => (&arg[..i], 1 << 30),
+        _ => (arg, 1),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(unit))
+        .ok_or_else(|| eyre!("Not a valid size, e.g. 4096, 512K or 10M"))
+}
+
+// sent as text, so escapes that don't make UTF-8 are refused
+fn parse_escaped(arg: &str) -> Result<String> {
+    String::from_utf8(unescape(arg)?).map_err(|_| eyre!("{:?} is not valid UTF-8", arg))
+}
+
+fn parse_usb_id(arg: &str) -> Result<u16> {
+    let digits = arg
+        .strip_prefix("0x")
+        .or_else(|| arg.strip_prefix("0X"))
+        .unwrap_or(arg);
+    u16::from_str_radix(digits, 16)
+        .map_err(|_| eyre!("Not a valid USB id (4 hex digits, e.g. 1a86)"))
+}
+
+pub fn parse_data(arg: &str) -> Result<DataBits> {
     match arg {
         "5" => Ok(DataBits::Five),
         "6" => Ok(DataBits::Six),
@@ -112,7 +748,7 @@ fn parse_data(arg: &str) -> Result<DataBits> {
     }
 }
 
-fn parse_flow(arg: &str) -> Result<FlowControl> {
+pub fn parse_flow(arg: &str) -> Result<FlowControl> {
     match arg {
         "none" => Ok(FlowControl::None),
         "software" | "xonxoff" => Ok(FlowControl::Software),
@@ -121,7 +757,7 @@ fn parse_flow(arg: &str) -> Result<FlowControl> {
     }
 }
 
-fn parse_parity(arg: &str) -> Result<Parity> {
+pub fn parse_parity(arg: &str) -> Result<Parity> {
     match arg {
         "none" => Ok(Parity::None),
         "odd" => Ok(Parity::Odd),
@@ -130,10 +766,24 @@ fn parse_parity(arg: &str) -> Result<Parity> {
     }
 }
 
-fn parse_stop(arg: &str) -> Result<StopBits> {
+pub fn parse_stop(arg: &str) -> Result<StopBits> {
     match arg {
         "1" => Ok(StopBits::One),
         "2" => Ok(StopBits::Two),
         _ => Err(eyre!("Not a valid number of stop bits (1 or 2)")),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn usb_ids_take_either_prefix() {
+        for arg in ["1a86", "0x1a86", "0X1a86", "0x1A86"] {
+            assert_eq!(parse_usb_id(arg).unwrap(), 0x1a86, "{arg}");
+        }
+        assert!(parse_usb_id("0x0x1a86").is_err());
+        assert!(parse_usb_id("0x").is_err());
+    }
+}
@@ -0,0 +1,24 @@
+use eyre::Result;
+
+/// Puts the text on the system clipboard.
+#[cfg(feature = "clipboard")]
+pub fn copy(text: String) -> Result<()> {
+    use std::sync::Mutex;
+    // on X11 and Wayland the text is only served while the clipboard lives,
+    // so it is kept around instead of dropped after each copy
+    static CLIPBOARD: Mutex<Option<arboard::Clipboard>> = Mutex::new(None);
+    let mut clipboard = CLIPBOARD.lock().unwrap();
+    if clipboard.is_none() {
+        *clipboard = Some(arboard::Clipboard::new()?);
+    }
+    clipboard.as_mut().unwrap().set_text(text)?;
+    Ok(())
+}
+
+/// Puts the text on the system clipboard.
+#[cfg(not(feature = "clipboard"))]
+pub fn copy(_: String) -> Result<()> {
+    Err(eyre::eyre!(
+        "seterm was built without clipboard support, rebuild with --features clipboard"
+    ))
+}
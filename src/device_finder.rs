@@ -1,55 +1,74 @@
-use std::{mem::take, path::PathBuf};
+use std::{
+    fmt::Display,
+    mem::take,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use clap::ValueEnum;
 use crossterm::event::{KeyCode, KeyEvent};
 use ratatui::{
     Frame,
-    buffer::Buffer,
     layout::{Constraint, Layout, Rect},
-    style::{Style, Stylize},
-    text::Text,
-    widgets::{
-        Block, Borders, Clear, List, ListState, Paragraph, Row, StatefulWidget, Table, TableState,
-        Widget,
-    },
+    style::Stylize,
+    text::{Line, Span, Text},
+    widgets::{Block, Borders, Clear, List, ListState, Paragraph, Row, Table, TableState, Wrap},
 };
+use serde::Serialize;
 use serialport::{DataBits, FlowControl, Parity, SerialPortInfo, StopBits};
 use tokio::sync::oneshot;
 
 use color_eyre::Result;
-use eyre::eyre;
-use tokio_serial::{SerialPortBuilderExt, SerialStream};
+use tokio_serial::{SerialPort, SerialPortBuilderExt, SerialStream};
 
-use crate::event::{Drawable, EventListener, GuiEvent};
+use crate::{
+    error::SerialError,
+    event::{Drawable, EventListener, GuiEvent},
+    theme::Theme,
+};
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
-pub enum Baud {
-    B48 = 4800,
-    B96 = 9600,
-    B192 = 19200,
-    B384 = 38400,
-    B576 = 57600,
-    B1152 = 115200,
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Baud(pub u32);
+
+impl Baud {
+    pub const B48: Baud = Baud(4800);
+    pub const B96: Baud = Baud(9600);
+    pub const B192: Baud = Baud(19200);
+    pub const B384: Baud = Baud(38400);
+    pub const B576: Baud = Baud(57600);
+    pub const B1152: Baud = Baud(115200);
+    pub const B2304: Baud = Baud(230400);
+    pub const B4608: Baud = Baud(460800);
+    pub const B9216: Baud = Baud(921600);
 }
 
-const BAUDS: [Baud; 6] = [
+const BAUDS: [Baud; 9] = [
     Baud::B48,
     Baud::B96,
     Baud::B192,
     Baud::B384,
     Baud::B576,
     Baud::B1152,
+    Baud::B2304,
+    Baud::B4608,
+    Baud::B9216,
 ];
 
-fn baud_idx(b: Baud) -> isize {
-    match b {
-        Baud::B48 => 0,
-        Baud::B96 => 1,
-        Baud::B192 => 2,
-        Baud::B384 => 3,
-        Baud::B576 => 4,
-        Baud::B1152 => 5,
-    }
+/// Steps through the common rates. Custom rates step to the nearest common
+/// rate in the requested direction.
+fn step_baud(b: Baud, inc: isize) -> Baud {
+    let i = match BAUDS.iter().position(|&x| x == b) {
+        Some(i) => i as isize + inc,
+        None => {
+            let above = BAUDS.partition_point(|x| x.0 < b.0) as isize;
+            if inc > 0 {
+                above + inc - 1
+            } else {
+                above + inc
+            }
+        }
+    };
+    BAUDS[i.rem_euclid(BAUDS.len() as isize) as usize]
 }
 
 const DATABITSS: [DataBits; 4] = [
@@ -73,53 +92,230 @@ const PARITY_STRS: [&str; 3] = ["None", "Odd", "Even"];
 const STOPBITSS: [StopBits; 2] = [StopBits::One, StopBits::Two];
 const STOPBIT_STRS: [&str; 2] = ["1", "2"];
 
+/// Narrows the finder down to USB devices with the given vendor and/or product id.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct UsbFilter {
+    pub vid: Option<u16>,
+    pub pid: Option<u16>,
+}
+
+impl UsbFilter {
+    pub fn is_empty(&self) -> bool {
+        self.vid.is_none() && self.pid.is_none()
+    }
+
+    pub fn matches(&self, info: &SerialPortInfo) -> bool {
+        match &info.port_type {
+            serialport::SerialPortType::UsbPort(usb) => {
+                self.vid.is_none_or(|v| v == usb.vid) && self.pid.is_none_or(|p| p == usb.pid)
+            }
+            _ => self.is_empty(),
+        }
+    }
+}
+
+impl Display for UsbFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let vid = self.vid.map(|v| format!("{v:04x}"));
+        let pid = self.pid.map(|p| format!("{p:04x}"));
+        write!(
+            f,
+            "{}:{}",
+            vid.as_deref().unwrap_or("*"),
+            pid.as_deref().unwrap_or("*")
+        )
+    }
+}
+
 pub struct DeviceFinder {
-    devices: Vec<SerialPortInfo>,
+    // everything found, the filter only applies when drawing and selecting
+    devices: Vec<Listed>,
+    filter: UsbFilter,
+    filtering: bool,
+    // PCI and unknown ports are listed too
+    all_ports: bool,
     state: ListState,
+    // typed in port path, for anything the scan doesn't pick up
+    manual: Option<String>,
     tx: Option<oneshot::Sender<String>>,
 }
 
+// a found port with its entry formatted once, as that reads sysfs
+struct Listed {
+    info: SerialPortInfo,
+    text: Text<'static>,
+}
+
+fn listed(devices: Vec<SerialPortInfo>) -> Vec<Listed> {
+    devices
+        .into_iter()
+        .map(|info| Listed {
+            text: format_device_info(&info),
+            info,
+        })
+        .collect()
+}
+
+/// Lists the serial devices worth offering to the user, USB and Bluetooth
+/// ports unless `all` also asks for built-in UARTs and anything unknown.
+pub fn find_devices(all: bool) -> Result<Vec<SerialPortInfo>> {
+    Ok(tokio_serial::available_ports()?
+        .into_iter()
+        .filter(|i| {
+            all || matches!(
+                &i.port_type,
+                serialport::SerialPortType::UsbPort(_) | serialport::SerialPortType::BluetoothPort
+            )
+        })
+        .collect())
+}
+
+/// Whether the port can still be found, by its device node or else in the
+/// list of ports.
+pub fn port_present(path: &Path) -> bool {
+    let name = path.to_string_lossy();
+    path.exists()
+        || tokio_serial::available_ports()
+            .map(|ports| ports.iter().any(|p| p.port_name == name))
+            .unwrap_or(false)
+}
+
 impl DeviceFinder {
-    pub fn new() -> Result<(DeviceFinder, oneshot::Receiver<String>)> {
-        let devices: Vec<_> = tokio_serial::available_ports()?
-            .into_iter()
-            .filter(|i| {
-                matches!(
-                    &i.port_type,
-                    serialport::SerialPortType::UsbPort(_)
-                        | serialport::SerialPortType::BluetoothPort
-                )
-            })
-            .collect();
-        if devices.is_empty() {
-            return Err(eyre!("Found no serial devices"));
-        }
+    pub fn new(
+        filter: UsbFilter,
+        all_ports: bool,
+    ) -> Result<(DeviceFinder, oneshot::Receiver<String>)> {
+        // opened even with nothing plugged in, r picks the device up later
+        let devices = listed(find_devices(all_ports)?);
         let (tx, rx) = oneshot::channel();
 
         Ok((
             Self {
                 devices,
+                filter,
+                filtering: !filter.is_empty(),
+                all_ports,
                 state: ListState::default(),
+                manual: None,
                 tx: Some(tx),
             },
             rx,
         ))
     }
+
+    fn visible(&self) -> Vec<&Listed> {
+        self.devices
+            .iter()
+            .filter(|d| !self.filtering || self.filter.matches(&d.info))
+            .collect()
+    }
+
+    fn selected_name(&self) -> Option<String> {
+        let selected = self.state.selected()?;
+        Some(self.visible().get(selected)?.info.port_name.clone())
+    }
+
+    // keeps the same device selected if it is still listed
+    fn reselect(&mut self, selected: Option<String>) {
+        let visible = self.visible();
+        let index = selected
+            .and_then(|name| visible.iter().position(|d| d.info.port_name == name))
+            .or_else(|| {
+                self.state
+                    .selected()
+                    .map(|i| i.min(visible.len().saturating_sub(1)))
+            });
+        let empty = visible.is_empty();
+        self.state.select(index.filter(|_| !empty));
+    }
+
+    fn refresh(&mut self) {
+        let selected = self.selected_name();
+        self.devices = listed(find_devices(self.all_ports).unwrap_or_default());
+        self.reselect(selected);
+    }
+
+    fn toggle_all_ports(&mut self) {
+        self.all_ports = !self.all_ports;
+        self.refresh();
+    }
+
+    fn toggle_filter(&mut self) {
+        if self.filter.is_empty() {
+            return;
+        }
+        let selected = self.selected_name();
+        self.filtering = !self.filtering;
+        self.reselect(selected);
+    }
+
+    fn listen_manual(&mut self, e: &GuiEvent) -> bool {
+        use GuiEvent::Crossterm;
+        use KeyCode::{Backspace, Char, Enter, Esc};
+        use crossterm::event::{Event::Key, KeyModifiers};
+        let Some(input) = self.manual.as_mut() else {
+            return false;
+        };
+        match e {
+            Crossterm(Key(KeyEvent {
+                code: Char(c),
+                modifiers,
+                ..
+            })) if modifiers.difference(KeyModifiers::SHIFT).is_empty() => input.push(*c),
+            // chorded keys aren't text, and mustn't fall through to the list's keys
+            Crossterm(Key(KeyEvent { code: Char(_), .. })) => {}
+            // backs out of typing, the finder stays open
+            Crossterm(Key(KeyEvent { code: Esc, .. })) => self.manual = None,
+            Crossterm(Key(KeyEvent {
+                code: Backspace, ..
+            })) => {
+                if input.pop().is_none() {
+                    self.manual = None;
+                }
+            }
+            Crossterm(Key(KeyEvent { code: Enter, .. })) => {
+                let path = input.trim();
+                if !path.is_empty()
+                    && let Some(tx) = self.tx.take()
+                {
+                    _ = tx.send(path.to_string());
+                    self.manual = None;
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
 }
 
 impl EventListener for DeviceFinder {
     fn listen(&mut self, e: &GuiEvent) -> bool {
         use GuiEvent::{Crossterm, SerialDone};
-        use KeyCode::{Down, Enter, Up};
+        use KeyCode::{Char, Down, Enter, Up};
         use crossterm::event::Event::Key;
+        if self.listen_manual(e) {
+            return true;
+        }
         match e {
+            Crossterm(Key(KeyEvent {
+                code: Char('r'), ..
+            })) => self.refresh(),
+            Crossterm(Key(KeyEvent {
+                code: Char('e'), ..
+            })) => self.manual = Some(String::new()),
+            Crossterm(Key(KeyEvent {
+                code: Char('v'), ..
+            })) => self.toggle_filter(),
+            Crossterm(Key(KeyEvent {
+                code: Char('a'), ..
+            })) => self.toggle_all_ports(),
             Crossterm(Key(KeyEvent { code: Up, .. })) => self.state.scroll_up_by(1),
             Crossterm(Key(KeyEvent { code: Down, .. })) => self.state.scroll_down_by(1),
             Crossterm(Key(KeyEvent { code: Enter, .. })) => {
-                if let Some(d) = self.state.selected().and_then(|i| self.devices.get(i))
+                if let Some(name) = self.selected_name()
                     && let Some(tx) = self.tx.take()
                 {
-                    _ = tx.send(d.port_name.clone());
+                    _ = tx.send(name);
                 };
             }
             SerialDone => {
@@ -132,7 +328,8 @@ impl EventListener for DeviceFinder {
     }
 }
 
-fn format_device_info(info: &SerialPortInfo) -> String {
+// the port, then a dim line with what tells identical adapters apart
+fn format_device_info(info: &SerialPortInfo) -> Text<'static> {
     use serialport::SerialPortType::{BluetoothPort, PciPort, Unknown, UsbPort};
     match info.port_type {
         UsbPort(ref usb) => {
@@ -140,36 +337,158 @@ fn format_device_info(info: &SerialPortInfo) -> String {
                 .iter()
                 .filter_map(|a| a.as_deref())
                 .collect();
-            format!("{} ({})", info.port_name, values.join(", "))
+            let mut ids = format!("{:04x}:{:04x}", usb.vid, usb.pid);
+            if let Some((location, interface)) = usb_location(&info.port_name) {
+                ids.push_str(&format!(" at {location}, interface {interface}"));
+            }
+            Text::from(vec![
+                Line::raw(format!("{} ({})", info.port_name, values.join(", "))),
+                Line::raw(format!("  {ids}")).dim(),
+            ])
         }
-        PciPort => format!("{} (PCI)", info.port_name),
-        BluetoothPort => format!("{} (Bluetooth)", info.port_name),
-        Unknown => info.port_name.clone(),
+        PciPort => Text::raw(format!("{} (PCI)", info.port_name)),
+        BluetoothPort => Text::raw(format!("{} (Bluetooth)", info.port_name)),
+        Unknown => Text::raw(info.port_name.clone()),
     }
 }
 
-impl Drawable for DeviceFinder {
-    fn draw(&mut self, area: Rect, frame: &mut Frame) {
-        let text: Vec<_> = self
-            .devices
+// The USB port chain and interface number, e.g. ("1-2.3", "0"), read from
+// sysfs. The device link of a tty leads through its interface directory,
+// which is named like 1-2.3:1.0. Nothing on other platforms.
+fn usb_location(port_name: &str) -> Option<(String, String)> {
+    let name = Path::new(port_name).file_name()?;
+    let device = Path::new("/sys/class/tty").join(name).join("device");
+    let device = std::fs::canonicalize(device).ok()?;
+    device.ancestors().find_map(|dir| {
+        let (chain, config) = dir.file_name()?.to_str()?.split_once(':')?;
+        let (_, interface) = config.split_once('.')?;
+        // PCI addresses like 0000:00:14.0 have a colon too
+        if !chain.contains('-') {
+            return None;
+        }
+        Some((chain.to_string(), interface.to_string()))
+    })
+}
+
+/// Everything known about a port, flattened out for printing.
+#[derive(Debug, Serialize)]
+pub struct PortDetails {
+    pub port: String,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub vid: Option<u16>,
+    pub pid: Option<u16>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    pub serial_number: Option<String>,
+}
+
+impl From<&SerialPortInfo> for PortDetails {
+    fn from(info: &SerialPortInfo) -> Self {
+        use serialport::SerialPortType::{BluetoothPort, PciPort, Unknown, UsbPort};
+        let mut details = PortDetails {
+            port: info.port_name.clone(),
+            kind: "unknown",
+            vid: None,
+            pid: None,
+            manufacturer: None,
+            product: None,
+            serial_number: None,
+        };
+        match &info.port_type {
+            UsbPort(usb) => {
+                details.kind = "usb";
+                details.vid = Some(usb.vid);
+                details.pid = Some(usb.pid);
+                details.manufacturer = usb.manufacturer.clone();
+                details.product = usb.product.clone();
+                details.serial_number = usb.serial_number.clone();
+            }
+            PciPort => details.kind = "pci",
+            BluetoothPort => details.kind = "bluetooth",
+            Unknown => {}
+        }
+        details
+    }
+}
+
+impl Display for PortDetails {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}\t{}", self.port, self.kind)?;
+        if let (Some(vid), Some(pid)) = (self.vid, self.pid) {
+            write!(f, "\t{vid:04x}:{pid:04x}")?;
+        }
+        let values: Vec<_> = [&self.manufacturer, &self.product, &self.serial_number]
             .iter()
-            .map(format_device_info)
-            .map(Text::raw)
+            .filter_map(|a| a.as_deref())
             .collect();
-        let highlight_style = Style::default().reversed();
-        let l = List::new(text)
-            .block(Block::bordered())
-            .highlight_style(highlight_style);
+        if !values.is_empty() {
+            write!(f, "\t{}", values.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Prints the available devices to stdout, one per line or as a JSON array.
+pub fn print_ports(json: bool, all: bool) -> Result<()> {
+    let ports: Vec<PortDetails> = find_devices(all)?.iter().map(PortDetails::from).collect();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&ports)?);
+    } else {
+        for p in ports {
+            println!("{p}");
+        }
+    }
+    Ok(())
+}
+
+impl Drawable for DeviceFinder {
+    fn draw(&mut self, area: Rect, frame: &mut Frame, theme: &Theme) {
+        let text: Vec<_> = self.visible().iter().map(|d| d.text.clone()).collect();
+        let highlight_style = theme.selected();
+        let hint = if self.filter.is_empty() {
+            "r to refresh, e to enter a path, a to show all ports"
+        } else {
+            "r to refresh, e to enter a path, a to show all ports, v to toggle filter"
+        };
+        let mut block = Block::bordered().title_bottom(Line::raw(hint).centered());
+        if self.all_ports {
+            block = block.title(Line::raw("All ports").right_aligned());
+        }
+        if self.filtering {
+            block = block.title(format!("Filter: {}", self.filter));
+        }
 
         frame.render_widget(Clear, area);
-        frame.render_stateful_widget(l, area, &mut self.state);
+        if text.is_empty() {
+            let msg = if self.filtering {
+                "No devices match the filter"
+            } else if self.all_ports {
+                "No serial devices found\nPlug one in and press r to refresh"
+            } else {
+                "No USB or Bluetooth serial devices found\nPlug one in and press r to refresh, or a to show all ports"
+            };
+            let p = Paragraph::new(msg)
+                .block(block)
+                .centered()
+                .wrap(Wrap { trim: true });
+            frame.render_widget(p, area);
+        } else {
+            let l = List::new(text)
+                .block(block)
+                .highlight_style(highlight_style);
+            frame.render_stateful_widget(l, area, &mut self.state);
+        }
+        if let Some(ref input) = self.manual {
+            render_text_entry(input, "Port path (Esc to cancel)", area, frame, theme);
+        }
     }
     fn alive(&self) -> bool {
         self.tx.is_some()
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct DeviceConfig {
     pub path: PathBuf,
     pub baud: Baud,
@@ -178,11 +497,106 @@ pub struct DeviceConfig {
     pub parity: Parity,
     pub stop: StopBits,
     pub dtr: bool,
+    pub read_buffer: usize,
+    pub tx_delay_ms: u64,
+    pub tx_pacing: TxPacing,
+    pub idle_timeout_secs: u64,
+    pub status_poll_ms: u64,
+    pub presence_poll_ms: u64,
+    pub reset: ResetSequence,
+    pub reset_pulse_ms: u64,
+    pub close_dtr: LineOnClose,
+    pub close_rts: LineOnClose,
+    pub close_break_ms: u64,
 }
 
+/// What `tx_delay_ms` is inserted between when sending.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum TxPacing {
+    #[default]
+    Line,
+    Byte,
+}
+
+/// How the control lines are driven to reset the board.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ResetSequence {
+    /// Assert DTR for the pulse, then release it
+    #[default]
+    Dtr,
+    /// Assert RTS for the pulse, then release it
+    Rts,
+    /// ESP32 auto-reset circuit, restarting into the firmware
+    Esp32,
+    /// ESP32 auto-reset circuit, restarting into the ROM bootloader
+    Esp32Boot,
+}
+
+/// Levels to set on DTR and RTS, left alone if None, and how long to hold
+/// them before the next step.
+pub type ResetStep = (Option<bool>, Option<bool>, Duration);
+
+impl ResetSequence {
+    pub fn steps(self, pulse: Duration) -> Vec<ResetStep> {
+        let none = Duration::ZERO;
+        match self {
+            ResetSequence::Dtr => vec![(Some(true), None, pulse), (Some(false), None, none)],
+            ResetSequence::Rts => vec![(None, Some(true), pulse), (None, Some(false), none)],
+            // RTS drives EN low while DTR keeps IO0 high
+            ResetSequence::Esp32 => vec![
+                (Some(false), Some(true), pulse),
+                (Some(false), Some(false), none),
+            ],
+            // IO0 is held low while EN comes back up, as esptool does
+            ResetSequence::Esp32Boot => vec![
+                (Some(false), Some(true), pulse),
+                (Some(true), Some(false), Duration::from_millis(50)),
+                (Some(false), Some(false), none),
+            ],
+        }
+    }
+
+    /// DTR and RTS once the sequence is done, starting from the given levels.
+    pub fn end_state(self, dtr: bool, rts: bool) -> (bool, bool) {
+        self.steps(Duration::ZERO)
+            .into_iter()
+            .fold((dtr, rts), |(d, r), (sd, sr, _)| {
+                (sd.unwrap_or(d), sr.unwrap_or(r))
+            })
+    }
+}
+
+/// Where a control line is left when the port is closed. Some boards are
+/// held in reset or reset again depending on it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum LineOnClose {
+    /// Leave the line as it is
+    #[default]
+    Keep,
+    /// Assert the line
+    High,
+    /// De-assert the line
+    Low,
+}
+
+impl LineOnClose {
+    pub fn level(self) -> Option<bool> {
+        match self {
+            LineOnClose::Keep => None,
+            LineOnClose::High => Some(true),
+            LineOnClose::Low => Some(false),
+        }
+    }
+}
+
+pub const DEFAULT_READ_BUFFER: usize = 4096;
+
 pub struct DeviceConfigurer {
     config: DeviceConfig,
     table_state: TableState,
+    custom_baud: Option<String>,
+    // what enter does, shown in the help below the table
+    confirm: &'static str,
     tx: Option<oneshot::Sender<DeviceConfig>>,
 }
 
@@ -202,18 +616,84 @@ impl DeviceConfig {
             parity: Parity::None,
             stop: StopBits::One,
             dtr: true,
+            read_buffer: DEFAULT_READ_BUFFER,
+            tx_delay_ms: 0,
+            tx_pacing: TxPacing::Line,
+            idle_timeout_secs: 0,
+            status_poll_ms: 500,
+            presence_poll_ms: 1000,
+            reset: ResetSequence::Dtr,
+            reset_pulse_ms: 100,
+            close_dtr: LineOnClose::Keep,
+            close_rts: LineOnClose::Keep,
+            close_break_ms: 0,
         }
     }
 
-    pub fn to_serial(self) -> Result<SerialStream> {
-        tokio_serial::new(self.path.to_string_lossy(), self.baud as u32)
+    /// Compact description such as `115200 8N1 / flow: none`.
+    pub fn summary(&self) -> String {
+        let parity = match self.parity {
+            Parity::None => 'N',
+            Parity::Odd => 'O',
+            Parity::Even => 'E',
+        };
+        format!(
+            "{} {}{}{} / flow: {}",
+            self.baud,
+            DATABIT_STRS[self.bits as usize],
+            parity,
+            STOPBIT_STRS[self.stop as usize],
+            FLOWCONTROL_STRS[self.flow as usize].to_lowercase()
+        )
+    }
+
+    /// The settings the driver actually applied to an opened port, which can
+    /// differ from the requested ones, e.g. a baud rate rounded to what the
+    /// hardware can divide down to.
+    pub fn negotiated(&self, port: &SerialStream) -> Result<DeviceConfig> {
+        Ok(DeviceConfig {
+            baud: Baud(port.baud_rate()?),
+            bits: port.data_bits()?,
+            flow: port.flow_control()?,
+            parity: port.parity()?,
+            stop: port.stop_bits()?,
+            ..self.clone()
+        })
+    }
+
+    /// Opens the port, turning the errors people actually run into into
+    /// something they can act on.
+    pub fn to_serial(self) -> Result<SerialStream, SerialError> {
+        tokio_serial::new(self.path.to_string_lossy(), self.baud.0)
             .data_bits(self.bits)
             .flow_control(self.flow)
             .parity(self.parity)
             .stop_bits(self.stop)
             .dtr_on_open(self.dtr)
             .open_native_async()
-            .map_err(|e| e.into())
+            .map_err(|e| open_error(&self.path, e))
+    }
+}
+
+fn open_error(path: &Path, e: serialport::Error) -> SerialError {
+    use serialport::ErrorKind;
+    use std::io::ErrorKind as Io;
+    let exists = path.exists();
+    let path = path.display();
+    match e.kind() {
+        ErrorKind::NoDevice | ErrorKind::Io(Io::NotFound) if !exists => {
+            SerialError::NotFound(format!("Port {} does not exist", path))
+        }
+        // serialport reports a port locked with TIOCEXCL as NoDevice
+        ErrorKind::NoDevice | ErrorKind::Io(Io::ResourceBusy) => {
+            SerialError::Busy(format!("Port {} is in use by another process", path))
+        }
+        ErrorKind::Io(Io::PermissionDenied) => SerialError::Permission(format!(
+            "No permission to open {} (is your user in the dialout or uucp group?)",
+            path
+        )),
+        ErrorKind::InvalidInput => SerialError::Config(format!("Could not open {}: {}", path, e)),
+        _ => SerialError::Io(format!("Could not open {}: {}", path, e)),
     }
 }
 
@@ -225,16 +705,27 @@ impl DeviceConfigurer {
             Self {
                 config: default,
                 table_state: TableState::new(),
+                custom_baud: None,
+                confirm: "Enter to connect",
                 tx,
             },
             rx,
         )
     }
 
+    /// For settings that are applied to a port that is already open.
+    pub fn for_open_port(mut self) -> Self {
+        self.confirm = "Enter to apply";
+        self
+    }
+
     fn select(&mut self, inc: isize) {
         let col = self.table_state.selected().unwrap_or(1) - 1;
+        if col == 0 {
+            self.config.baud = step_baud(self.config.baud, inc);
+            return;
+        }
         let index = match col {
-            0 => baud_idx(self.config.baud),
             1 => self.config.bits as isize,
             2 => self.config.flow as isize,
             3 => self.config.parity as isize,
@@ -249,7 +740,6 @@ impl DeviceConfigurer {
             _ => panic!("Invalid enum passed in"),
         };
         let max = match col {
-            0 => BAUDS.len(),
             1 => DATABITSS.len(),
             2 => FLOWCONTROLS.len(),
             3 => PARITYS.len(),
@@ -264,7 +754,6 @@ impl DeviceConfigurer {
             .unwrap();
 
         match col {
-            0 => self.config.baud = BAUDS[i],
             1 => self.config.bits = DATABITSS[i],
             2 => self.config.flow = FLOWCONTROLS[i],
             3 => self.config.parity = PARITYS[i],
@@ -273,14 +762,58 @@ impl DeviceConfigurer {
             _ => panic!("Invalid enum passed in"),
         }
     }
+
+    // baud rate row, which accepts typed custom rates
+    fn on_baud(&self) -> bool {
+        self.table_state.selected() == Some(1)
+    }
+
+    fn listen_custom_baud(&mut self, e: &GuiEvent) -> bool {
+        use GuiEvent::Crossterm;
+        use KeyCode::{Backspace, Char, Enter};
+        use crossterm::event::Event::Key;
+        let Some(input) = self.custom_baud.as_mut() else {
+            return false;
+        };
+        match e {
+            Crossterm(Key(KeyEvent {
+                code: Char(c @ '0'..='9'),
+                ..
+            })) => input.push(*c),
+            Crossterm(Key(KeyEvent {
+                code: Backspace, ..
+            })) => {
+                if input.pop().is_none() {
+                    self.custom_baud = None;
+                }
+            }
+            Crossterm(Key(KeyEvent { code: Enter, .. })) => {
+                if let Ok(b) = input.parse::<u32>()
+                    && b > 0
+                {
+                    self.config.baud = Baud(b);
+                }
+                self.custom_baud = None;
+            }
+            _ => return false,
+        }
+        true
+    }
 }
 
 impl EventListener for DeviceConfigurer {
     fn listen(&mut self, e: &GuiEvent) -> bool {
         use GuiEvent::{Crossterm, SerialDone};
-        use KeyCode::{Down, Enter, Left, Right, Up};
+        use KeyCode::{Char, Down, Enter, Left, Right, Up};
         use crossterm::event::Event::Key;
+        if self.listen_custom_baud(e) {
+            return true;
+        }
         match e {
+            Crossterm(Key(KeyEvent {
+                code: Char(c @ '0'..='9'),
+                ..
+            })) if self.on_baud() => self.custom_baud = Some(c.to_string()),
             Crossterm(Key(KeyEvent { code: Up, .. })) => {
                 if self.table_state.selected().unwrap_or(0) <= 1 {
                     self.table_state.select(Some(1))
@@ -309,15 +842,11 @@ impl EventListener for DeviceConfigurer {
 }
 
 impl Drawable for DeviceConfigurer {
-    fn draw(&mut self, area: Rect, frame: &mut Frame) {
+    fn draw(&mut self, area: Rect, frame: &mut Frame, theme: &Theme) {
         let [opt_area, desc_area] =
-            &*Layout::vertical([Constraint::Percentage(80), Constraint::Percentage(20)])
-                .split(area)
-        else {
-            panic!("Device configurer failed to configure");
-        };
+            Layout::vertical([Constraint::Fill(1), Constraint::Length(6)]).areas(area);
 
-        let bauds = format!("{}", self.config.baud as usize);
+        let bauds = self.config.baud.to_string();
         let dtr = format!("{}", self.config.dtr);
         let rows = [
             Row::new([
@@ -352,21 +881,49 @@ impl Drawable for DeviceConfigurer {
         let widths = [Constraint::Percentage(30), Constraint::Percentage(70)];
         let table = Table::new(rows, widths)
             .block(Block::new().borders(Borders::all().difference(Borders::BOTTOM)))
-            .row_highlight_style(Style::new().reversed());
+            .row_highlight_style(theme.selected());
 
         frame.render_widget(Clear, area);
-        frame.render_stateful_widget(table, *opt_area, &mut self.table_state);
+        frame.render_stateful_widget(table, opt_area, &mut self.table_state);
 
-        let description = Paragraph::new(
-            "Left/Right to change option\nUp/Down to select option\nEnter to connect\nEsc to exit",
-        )
+        let description = Paragraph::new(format!(
+            "Left/Right to change option\nUp/Down to select option\n\
+            Type digits on Baud Rate for a custom rate\n{}\nEsc to exit",
+            self.confirm
+        ))
         .block(Block::new().borders(Borders::all().difference(Borders::TOP)))
         .centered();
 
-        frame.render_widget(description, *desc_area);
+        frame.render_widget(description, desc_area);
+
+        if let Some(ref input) = self.custom_baud {
+            render_text_entry(input, "Custom baud rate", opt_area, frame, theme);
+        }
     }
 
     fn alive(&self) -> bool {
         self.tx.is_some()
     }
+
+    // the seven settings and the five lines of help, each with a border
+    fn preferred_size(&self) -> Option<(u16, u16)> {
+        let path = self.config.path.as_os_str().len() as u16;
+        // the path is centered in the wider column, which gets 70%
+        Some(((path * 10 / 7 + 4).max(60), 14))
+    }
+}
+
+/// Draws a single-line text box with a cursor over the middle of `area`.
+pub fn render_text_entry(input: &str, label: &str, area: Rect, frame: &mut Frame, theme: &Theme) {
+    let area = area.inner(ratatui::layout::Margin {
+        horizontal: area.width / 4,
+        vertical: area.height.saturating_sub(3) / 2,
+    });
+    let cursor = theme.cursor();
+    let line = Line::from(vec![Span::raw(input), cursor]);
+    let p = Paragraph::new(line)
+        .block(Block::bordered().title_bottom(Line::raw(label).centered()))
+        .left_aligned();
+    frame.render_widget(Clear, area);
+    frame.render_widget(p, area);
 }
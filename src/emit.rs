@@ -0,0 +1,215 @@
+use std::{
+    fs::File,
+    io::Write,
+    os::unix::net::UnixStream,
+    sync::mpsc::{self, Receiver, SyncSender, TrySendError},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use eyre::{Context, Result, eyre};
+use serde::Serialize;
+
+use crate::event::{FromSerialData, ModemStatus, ToSerialData};
+
+/// Where `--emit-json` writes one JSON object per line for everything sent
+/// and received. The terminal owns stdout, so this is a file, a named pipe,
+/// or `unix:PATH` for a listening Unix socket. Lines are written on a
+/// thread of their own, a slow reader must not hold up the terminal.
+pub struct JsonSink {
+    target: String,
+    lines: SyncSender<Vec<u8>>,
+    // why the writer thread stopped
+    failed: Receiver<std::io::Error>,
+}
+
+// lines waiting for the writer before a reader is given up on
+const BACKLOG: usize = 4096;
+// how long quitting waits for the last lines to be written
+const DRAIN: Duration = Duration::from_millis(500);
+
+impl std::fmt::Debug for JsonSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JsonSink")
+            .field("target", &self.target)
+            .finish()
+    }
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+    // seconds since the unix epoch
+    time: f64,
+    #[serde(flatten)]
+    origin: Origin<'a>,
+    #[serde(flatten)]
+    event: Event<'a>,
+}
+
+/// Which tab and port a record is about, so several open at once can be
+/// told apart.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct Origin<'a> {
+    pub session: usize,
+    // the port's path, None before one has been opened
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+enum Event<'a> {
+    Rx { hex: String },
+    Tx { hex: String },
+    Connect { port: &'a str },
+    Status(ModemStatus),
+    Idle { secs: u64 },
+    Gone,
+    Lost { error: String },
+}
+
+impl JsonSink {
+    pub fn open(target: &str) -> Result<Self> {
+        let out: Box<dyn Write + Send> = match target.strip_prefix("unix:") {
+            Some(path) => Box::new(
+                UnixStream::connect(path)
+                    .wrap_err_with(|| format!("Unable to connect to {}", path))?,
+            ),
+            None => Box::new(
+                File::options()
+                    .create(true)
+                    .append(true)
+                    .open(target)
+                    .wrap_err_with(|| format!("Unable to open {}", target))?,
+            ),
+        };
+        let (lines, queued) = mpsc::sync_channel(BACKLOG);
+        let (failure, failed) = mpsc::channel();
+        std::thread::spawn(move || write_lines(out, queued, failure));
+        Ok(Self {
+            target: target.into(),
+            lines,
+            failed,
+        })
+    }
+
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    pub fn received(&mut self, origin: Origin, d: &FromSerialData) -> Result<()> {
+        let event = match d {
+            FromSerialData::Data(b) => Event::Rx { hex: hex(b) },
+            FromSerialData::Connect(port) => Event::Connect { port },
+            FromSerialData::Status(s) => Event::Status(*s),
+            FromSerialData::Idle(d) => Event::Idle { secs: d.as_secs() },
+            FromSerialData::Gone => Event::Gone,
+            FromSerialData::Lost(e) => Event::Lost {
+                error: e.to_string(),
+            },
+            // progress of our own sends, the data itself is emitted as tx
+            FromSerialData::Sent(_)
+            | FromSerialData::SendComplete(_)
+            | FromSerialData::SendFailed(_)
+            | FromSerialData::XmodemProgress(..)
+            | FromSerialData::TxBlocked(_)
+            | FromSerialData::Configured(_)
+            | FromSerialData::Response { .. } => return Ok(()),
+        };
+        self.write(origin, event)
+    }
+
+    pub fn sent(&mut self, origin: Origin, d: &ToSerialData) -> Result<()> {
+        let bytes = match d {
+            ToSerialData::Data(s) => s.as_bytes(),
+            ToSerialData::RawBytes(b) | ToSerialData::Xmodem(b) | ToSerialData::Query(b, _) => b,
+            _ => return Ok(()),
+        };
+        self.write(origin, Event::Tx { hex: hex(bytes) })
+    }
+
+    fn write(&mut self, origin: Origin, event: Event) -> Result<()> {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        let mut line = serde_json::to_vec(&Record {
+            time,
+            origin,
+            event,
+        })?;
+        line.push(b'\n');
+        if let Ok(e) = self.failed.try_recv() {
+            return Err(e.into());
+        }
+        match self.lines.try_send(line) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => Err(eyre!("The reader is not keeping up")),
+            Err(TrySendError::Disconnected(_)) => Err(eyre!("The writer has stopped")),
+        }
+    }
+}
+
+impl Drop for JsonSink {
+    // the writer ends once the lines are all out and the sender is gone
+    fn drop(&mut self) {
+        let (closed, _) = mpsc::sync_channel(0);
+        drop(std::mem::replace(&mut self.lines, closed));
+        _ = self.failed.recv_timeout(DRAIN);
+    }
+}
+
+fn write_lines(
+    mut out: Box<dyn Write + Send>,
+    lines: Receiver<Vec<u8>>,
+    failure: mpsc::Sender<std::io::Error>,
+) {
+    for line in lines {
+        if let Err(e) = out.write_all(&line).and_then(|()| out.flush()) {
+            _ = failure.send(e);
+            return;
+        }
+    }
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_name_their_session_and_device() {
+        let path = std::env::temp_dir().join(format!("seterm-emit-{}.jsonl", std::process::id()));
+        let mut sink = JsonSink::open(path.to_str().unwrap()).unwrap();
+        let origin = Origin {
+            session: 2,
+            device: Some("/dev/ttyUSB0"),
+        };
+        sink.received(origin, &FromSerialData::Data(b"hi".to_vec()))
+            .unwrap();
+        sink.sent(
+            Origin {
+                device: None,
+                ..origin
+            },
+            &ToSerialData::RawBytes(vec![1]),
+        )
+        .unwrap();
+        drop(sink);
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let records: Vec<serde_json::Value> = text
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(records[0]["event"], "rx");
+        assert_eq!(records[0]["session"], 2);
+        assert_eq!(records[0]["device"], "/dev/ttyUSB0");
+        assert_eq!(records[0]["hex"], "6869");
+        assert_eq!(records[1]["event"], "tx");
+        assert!(records[1].get("device").is_none());
+    }
+}
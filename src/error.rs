@@ -0,0 +1,74 @@
+use std::{fmt, io};
+
+/// Why talking to the port failed, kept apart from other errors so the
+/// dashboard and auto-reconnect can tell a yanked cable from a bad setting.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SerialError {
+    // the device went away while it was open
+    Disconnected(String),
+    NotFound(String),
+    // opened by another process
+    Busy(String),
+    Permission(String),
+    Timeout(String),
+    // settings the port or driver rejected
+    Config(String),
+    // the other end aborted, e.g. a CAN during XMODEM
+    Cancelled(String),
+    Io(String),
+}
+
+// errno values a vanished USB adapter shows up as
+const EIO: i32 = 5;
+const ENXIO: i32 = 6;
+const ENODEV: i32 = 19;
+
+impl SerialError {
+    /// True if the device is gone rather than refusing us, so reconnecting
+    /// once it is back is worth trying.
+    pub fn is_disconnect(&self) -> bool {
+        matches!(self, Self::Disconnected(_) | Self::NotFound(_))
+    }
+
+    fn message(&self) -> &str {
+        match self {
+            Self::Disconnected(m)
+            | Self::NotFound(m)
+            | Self::Busy(m)
+            | Self::Permission(m)
+            | Self::Timeout(m)
+            | Self::Config(m)
+            | Self::Cancelled(m)
+            | Self::Io(m) => m,
+        }
+    }
+}
+
+impl fmt::Display for SerialError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl std::error::Error for SerialError {}
+
+impl From<io::Error> for SerialError {
+    fn from(e: io::Error) -> Self {
+        use io::ErrorKind as Kind;
+        let msg = e.to_string();
+        if matches!(e.raw_os_error(), Some(EIO | ENXIO | ENODEV)) {
+            return Self::Disconnected(msg);
+        }
+        match e.kind() {
+            Kind::BrokenPipe | Kind::NotConnected | Kind::UnexpectedEof | Kind::ConnectionReset => {
+                Self::Disconnected(msg)
+            }
+            Kind::NotFound => Self::NotFound(msg),
+            Kind::ResourceBusy => Self::Busy(msg),
+            Kind::PermissionDenied => Self::Permission(msg),
+            Kind::TimedOut => Self::Timeout(msg),
+            Kind::InvalidInput => Self::Config(msg),
+            _ => Self::Io(msg),
+        }
+    }
+}
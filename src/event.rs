@@ -1,25 +1,38 @@
-use std::{path::Path, time::Duration};
-
-use color_eyre::{
-    Report, Result,
-    eyre::{self, OptionExt},
+use std::{
+    collections::VecDeque,
+    fs::File,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
+
+use clap::ValueEnum;
+use color_eyre::{Result, eyre::OptionExt};
 use eyre::{Context, eyre};
 use futures::{FutureExt, StreamExt};
 use notify::{RecommendedWatcher, Watcher};
-use ratatui::{Frame, buffer::Buffer, crossterm::event::Event as CrosstermEvent, layout::Rect};
-use serialport::SerialPort;
+use ratatui::{Frame, crossterm::event::Event as CrosstermEvent, layout::Rect};
+use serde::{Deserialize, Serialize};
+use serialport::{FlowControl, SerialPort};
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
     select,
-    sync::{mpsc, oneshot},
+    sync::mpsc,
 };
 use tokio_serial::SerialStream;
 
 use tokio::time::sleep as tokio_sleep;
 use tracing::{Instrument, info_span, instrument, trace};
+use wildmatch::WildMatch;
 
-use crate::device_finder::DeviceConfig;
+use crate::{
+    cli::WatchOptions,
+    device_finder::{DeviceConfig, ResetSequence, TxPacing, port_present},
+    emit::hex,
+    error::SerialError,
+    theme::Theme,
+    xmodem,
+};
 
 pub trait EventListener {
     fn listen(&mut self, e: &GuiEvent) -> bool;
@@ -27,7 +40,12 @@ pub trait EventListener {
 
 pub trait Drawable {
     fn alive(&self) -> bool;
-    fn draw(&mut self, area: Rect, buf: &mut Frame);
+    fn draw(&mut self, area: Rect, buf: &mut Frame, theme: &Theme);
+    /// Columns and rows this would like when shown as a popup, None for
+    /// the usual share of the screen.
+    fn preferred_size(&self) -> Option<(u16, u16)> {
+        None
+    }
 }
 
 pub trait Reactive: EventListener + Drawable + Send {}
@@ -40,7 +58,8 @@ pub enum ToAppEvent {
     Gui(GuiEvent),
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Severity {
     Error,
     Info,
@@ -53,6 +72,14 @@ pub enum GuiEvent {
     Log(Severity, String),
     Serial(FromSerialData),
     SerialDone,
+    SerialConfig(DeviceConfig),
+    Capture(Option<PathBuf>),
+    /// A line to send as if it were typed, so it gets the current line ending
+    SendLine(String),
+    /// The port is closed for flashing, or false if reopening it failed
+    Reflashing(bool),
+    /// A port is being opened, None once that has finished either way
+    Connecting(Option<PathBuf>),
 }
 
 #[derive(Debug)]
@@ -61,31 +88,94 @@ pub enum AppEvent {
     SerialConnect(mpsc::UnboundedSender<ToSerialData>, DeviceConfig),
     SendSerial(ToSerialData),
     RequestUpload,
+    RequestSendFile,
+    SendFile(PathBuf),
+    // a text file of hex bytes such as `DE AD BE EF`, sent decoded
+    SendHexFile(PathBuf),
+    RequestXmodem,
+    XmodemSend(PathBuf),
+    ToggleCapture,
+    // a Query for the session's device
+    SendAndWait(Vec<u8>, Duration),
+    // written to the capture file, if there is one
+    Marker(String),
     SendUpload(mpsc::UnboundedSender<ToFileWatcher>),
     Watcher(FromFileWatcher),
     Leave,
+    RequestQuit,
     Quit,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum ToSerialData {
     Data(String),
+    RawBytes(Vec<u8>),
+    // sent with the XMODEM protocol instead of as is
+    Xmodem(Vec<u8>),
+    // sent as is, then what arrives for the duration is reported back
+    Query(Vec<u8>, Duration),
     RTS(bool),
     DTR(bool),
+    // drives DTR and RTS through the sequence to reset the board
+    ResetPulse(ResetSequence),
+    RequestStatus,
+    // applies new line settings to the open port, the path is ignored
+    Configure(DeviceConfig),
     Disconnect,
+    // the port is no longer listed, handled as if the read had failed
+    Unplugged,
+}
+
+/// Input control lines as reported by the device.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ModemStatus {
+    pub cts: bool,
+    pub dsr: bool,
+    pub ri: bool,
+    pub cd: bool,
 }
 
 #[derive(Clone, Debug)]
 pub enum FromSerialData {
     Connect(String),
     Data(Vec<u8>),
+    Status(ModemStatus),
+    // bytes that went out to the device, whichever way they were sent
+    Sent(usize),
+    SendComplete(usize),
+    SendFailed(SerialError),
+    // XMODEM block acknowledged, out of the total
+    XmodemProgress(usize, usize),
+    // nothing has been received for this long, the port is still open
+    Idle(Duration),
+    // a write has been waiting on the device, e.g. for CTS, or has gone out
+    TxBlocked(bool),
+    // what a Query sent and what came back in its time
+    Response { sent: Vec<u8>, received: Vec<u8> },
+    // the port settings after a Configure, as the driver took them
+    Configured(DeviceConfig),
     Gone,
+    // the port failed underneath us rather than being closed on request
+    Lost(SerialError),
 }
 
 #[derive(Clone, Debug)]
 pub enum FromFileWatcher {
     DisonnectRequest,
     ReconnectRequest,
+    // whether the upload command is currently running
+    Busy(bool),
+    Output(UploadRun),
+}
+
+/// Everything an upload command printed, kept around for the output popup.
+#[derive(Clone, Debug)]
+pub struct UploadRun {
+    pub file: PathBuf,
+    pub code: Option<i32>,
+    pub success: bool,
+    pub stdout: String,
+    pub stderr: String,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -94,38 +184,83 @@ pub enum ToFileWatcher {
     NoDevice,
 }
 
+/// Sends events to the app, tagged with the session (tab) they concern.
 #[derive(Clone, Debug)]
-pub struct Messenger(mpsc::UnboundedSender<ToAppEvent>);
+pub struct Messenger {
+    tx: mpsc::UnboundedSender<(usize, ToAppEvent)>,
+    // what serial handlers report, bounded so a fast device waits for the
+    // UI instead of queueing without limit
+    serial: mpsc::Sender<(usize, FromSerialData)>,
+    session: usize,
+}
+
+/// Session of the app's own messenger, which no tab is ever given.
+pub const APP_SESSION: usize = usize::MAX;
+
+/// Chunks of received data that can be waiting for the app before serial
+/// handlers stop reading, which leaves the rest to the driver and flow
+/// control.
+pub const SERIAL_QUEUE: usize = 64;
 
 impl Messenger {
-    pub fn new(m: mpsc::UnboundedSender<ToAppEvent>) -> Self {
-        Self(m)
+    pub fn new(
+        m: mpsc::UnboundedSender<(usize, ToAppEvent)>,
+        serial: mpsc::Sender<(usize, FromSerialData)>,
+    ) -> Self {
+        Self {
+            tx: m,
+            serial,
+            session: 0,
+        }
+    }
+
+    /// The same channel, with events routed to another session.
+    pub fn for_session(&self, session: usize) -> Self {
+        Self {
+            tx: self.tx.clone(),
+            serial: self.serial.clone(),
+            session,
+        }
+    }
+
+    fn send(&self, e: ToAppEvent) {
+        _ = self.tx.send((self.session, e));
     }
 
     pub fn send_term(&self, e: CrosstermEvent) {
-        _ = self.0.send(ToAppEvent::Gui(GuiEvent::Crossterm(e)));
+        self.send(ToAppEvent::Gui(GuiEvent::Crossterm(e)));
     }
     pub fn send_app(&self, e: AppEvent) {
-        _ = self.0.send(ToAppEvent::App(e));
+        self.send(ToAppEvent::App(e));
     }
     pub fn new_component(&self, c: Box<dyn Reactive>) {
-        _ = self.0.send(ToAppEvent::Popup(c));
+        self.send(ToAppEvent::Popup(c));
     }
     pub fn log(&self, s: Severity, e: String) {
-        _ = self.0.send(ToAppEvent::Gui(GuiEvent::Log(s, e)));
+        self.send(ToAppEvent::Gui(GuiEvent::Log(s, e)));
     }
     pub fn send_serial(&self, d: FromSerialData) {
-        _ = self.0.send(ToAppEvent::Gui(GuiEvent::Serial(d)));
+        self.send(ToAppEvent::Gui(GuiEvent::Serial(d)));
+    }
+    /// Waits while SERIAL_QUEUE events are already queued. A serial handler
+    /// sends everything through here so it arrives in order.
+    pub async fn forward_serial(&self, d: FromSerialData) {
+        _ = self.serial.send((self.session, d)).await;
+    }
+    /// forward_serial for callers that can't wait, the event is dropped if
+    /// the queue is full.
+    pub fn try_forward_serial(&self, d: FromSerialData) {
+        _ = self.serial.try_send((self.session, d));
     }
     pub fn send_notif(&self, d: GuiEvent) {
-        _ = self.0.send(ToAppEvent::Gui(d));
+        self.send(ToAppEvent::Gui(d));
     }
     pub fn send_file(&self, f: FromFileWatcher) {
-        _ = self.0.send(ToAppEvent::App(AppEvent::Watcher(f)));
+        self.send(ToAppEvent::App(AppEvent::Watcher(f)));
     }
 
     pub fn is_closed(&self) -> bool {
-        self.0.is_closed()
+        self.tx.is_closed()
     }
 }
 
@@ -144,80 +279,803 @@ pub fn crossterm_handler(to_app: Messenger) {
     });
 }
 
+/// How `--capture` files are written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum CaptureFormat {
+    /// Received bytes exactly as they arrived
+    #[default]
+    Raw,
+    /// One JSON object per chunk sent or received, which --replay can play back
+    Json,
+    /// One line per chunk with a UTC timestamp and direction
+    TextTs,
+}
+
+#[derive(Debug)]
+struct CaptureFile {
+    path: PathBuf,
+    file: File,
+    // mono in json records counts from here, unaffected by clock changes
+    started: Instant,
+}
+
+/// Sink that serial data is appended to. It is shared between successive
+/// serial handlers so reconnecting does not interrupt a capture. Raw
+/// captures only keep received data, the others record both directions.
+#[derive(Clone, Debug, Default)]
+pub struct Capture {
+    format: CaptureFormat,
+    file: Arc<Mutex<Option<CaptureFile>>>,
+}
+
+#[derive(Serialize)]
+struct CaptureRecord<'a> {
+    // seconds since the unix epoch, matching --emit-json
+    time: f64,
+    // seconds since the capture started
+    mono: f64,
+    event: &'a str,
+    hex: String,
+}
+
+impl Capture {
+    pub fn new(format: CaptureFormat) -> Self {
+        Self {
+            format,
+            file: Arc::default(),
+        }
+    }
+
+    pub fn start(&self, path: PathBuf) -> Result<()> {
+        let file = File::options()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .wrap_err_with(|| format!("Unable to open {}", path.display()))?;
+        *self.file.lock().unwrap() = Some(CaptureFile {
+            path,
+            file,
+            started: Instant::now(),
+        });
+        Ok(())
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.file.lock().unwrap().is_some()
+    }
+
+    pub fn stop(&self) -> Option<PathBuf> {
+        self.file.lock().unwrap().take().map(|c| c.path)
+    }
+
+    fn received(&self, data: &[u8]) -> std::io::Result<()> {
+        self.write("rx", data)
+    }
+
+    /// A marker the user dropped in. Raw captures are left alone so they stay
+    /// byte for byte what the device sent.
+    pub fn marker(&self, label: &str) -> std::io::Result<()> {
+        if self.format == CaptureFormat::Raw {
+            return Ok(());
+        }
+        self.write("marker", label.as_bytes())
+    }
+
+    fn sent(&self, data: &[u8]) -> std::io::Result<()> {
+        if self.format == CaptureFormat::Raw {
+            return Ok(());
+        }
+        self.write("tx", data)
+    }
+
+    fn write(&self, dir: &str, data: &[u8]) -> std::io::Result<()> {
+        use std::io::Write;
+        let mut guard = self.file.lock().unwrap();
+        let Some(c) = guard.as_mut() else {
+            return Ok(());
+        };
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        match self.format {
+            CaptureFormat::Raw => c.file.write_all(data)?,
+            CaptureFormat::Json => {
+                let record = CaptureRecord {
+                    time: now.as_secs_f64(),
+                    mono: c.started.elapsed().as_secs_f64(),
+                    event: dir,
+                    hex: hex(data),
+                };
+                let mut line = serde_json::to_vec(&record)?;
+                line.push(b'\n');
+                c.file.write_all(&line)?;
+            }
+            CaptureFormat::TextTs => {
+                writeln!(c.file, "{} {} {}", time_of_day(now), dir, escape_text(data))?;
+            }
+        }
+        c.file.flush()
+    }
+}
+
+/// Time of day in UTC as HH:MM:SS.mmm, from time since the unix epoch.
+pub fn time_of_day(now: Duration) -> String {
+    let secs = now.as_secs() % 86400;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        now.subsec_millis()
+    )
+}
+
+// keeps a chunk on one line, control bytes are written as escapes
+fn escape_text(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len());
+    for c in String::from_utf8_lossy(data).chars() {
+        match c {
+            '\r' => out.push_str("\\r"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\\' => out.push_str("\\\\"),
+            c if c.is_control() => out.push_str(&format!("\\x{:02x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
 #[derive(Debug)]
 struct SerialImpl {
     data_tx: Messenger,
     device: SerialStream,
+    events: mpsc::UnboundedReceiver<ToSerialData>,
+    capture: Capture,
     alive: bool,
+    // why the port failed, if it did
+    lost: Option<SerialError>,
+    // XON/XOFF from the device gate our writes
+    software_flow: bool,
+    paused: bool,
+    // sends that arrived while paused, in order
+    held: VecDeque<ToSerialData>,
+    tx_delay: Duration,
+    tx_pacing: TxPacing,
+    last_rx: tokio::time::Instant,
+    // no data for the idle timeout, and that has already been reported
+    idle: bool,
+    // a write is stuck and that has been reported
+    tx_blocked: bool,
+    // looked for when reads keep coming back empty
+    path: PathBuf,
+    // reads in a row that returned nothing
+    empty_reads: usize,
+    // cleared if the control lines could not be read on connect, so polling
+    // does not keep failing
+    control_lines: bool,
+    // the last status sent, polling only reports changes
+    status: Option<ModemStatus>,
+    reset_pulse: Duration,
+    // applied on disconnect, after the last write has gone out
+    close_dtr: Option<bool>,
+    close_rts: Option<bool>,
+    close_break: Duration,
+}
+
+// empty reads in a row before checking whether the port is still there
+const EMPTY_READS: usize = 5;
+// between empty reads, so a closed port is not read in a busy loop
+const EMPTY_READ_BACKOFF: Duration = Duration::from_millis(20);
+
+// a write taking longer than this is shown as blocked
+const TX_STALL: Duration = Duration::from_millis(500);
+// wait before retrying a write the driver turned away
+const TX_RETRY: Duration = Duration::from_millis(50);
+
+const XON: u8 = 0x11;
+const XOFF: u8 = 0x13;
+// a send the device stopped with XOFF gives up if no XON comes by then
+const XON_WAIT: Duration = Duration::from_secs(30);
+
+// Queues an event that came in during a send, true if it closes the port
+// and so cancels the send. It goes first so it runs straight after.
+fn hold(held: &mut VecDeque<ToSerialData>, e: Option<ToSerialData>) -> bool {
+    match e {
+        Some(e @ (ToSerialData::Disconnect | ToSerialData::Unplugged)) => held.push_front(e),
+        Some(e) => {
+            held.push_back(e);
+            return false;
+        }
+        None => held.push_front(ToSerialData::Disconnect),
+    }
+    true
+}
+
+// sends that XOFF holds back, everything else still goes through
+fn held_by_xoff(d: &ToSerialData) -> bool {
+    matches!(
+        d,
+        ToSerialData::Data(_)
+            | ToSerialData::RawBytes(_)
+            | ToSerialData::Xmodem(_)
+            | ToSerialData::Query(..)
+    )
 }
 
 impl SerialImpl {
-    fn read(&mut self, data: &[u8]) {
+    fn new(
+        data_tx: Messenger,
+        device: SerialStream,
+        events: mpsc::UnboundedReceiver<ToSerialData>,
+        capture: Capture,
+        config: &DeviceConfig,
+    ) -> Self {
+        Self {
+            data_tx,
+            device,
+            events,
+            capture,
+            alive: true,
+            lost: None,
+            software_flow: config.flow == FlowControl::Software,
+            paused: false,
+            held: VecDeque::new(),
+            tx_delay: Duration::from_millis(config.tx_delay_ms),
+            tx_pacing: config.tx_pacing,
+            last_rx: tokio::time::Instant::now(),
+            idle: false,
+            tx_blocked: false,
+            path: config.path.clone(),
+            empty_reads: 0,
+            control_lines: true,
+            status: None,
+            reset_pulse: Duration::from_millis(config.reset_pulse_ms),
+            close_dtr: config.close_dtr.level(),
+            close_rts: config.close_rts.level(),
+            close_break: Duration::from_millis(config.close_break_ms),
+        }
+    }
+
+    async fn read(&mut self, data: &[u8]) {
         trace!("Sending data");
+        let data = if self.software_flow {
+            self.strip_flow_bytes(data)
+        } else {
+            data.to_vec()
+        };
+        if data.is_empty() {
+            return;
+        }
+        self.last_rx = tokio::time::Instant::now();
+        self.idle = false;
+        if let Err(e) = self.capture.received(&data) {
+            self.data_tx
+                .log(Severity::Error, format!("Unable to write capture: {}", e));
+        }
         self.data_tx
-            .send_serial(FromSerialData::Data(Vec::from(data)));
+            .forward_serial(FromSerialData::Data(data))
+            .await;
+    }
+
+    fn capture_sent(&self, data: &[u8]) {
+        if let Err(e) = self.capture.sent(data) {
+            self.data_tx
+                .log(Severity::Error, format!("Unable to write capture: {}", e));
+        }
+    }
+
+    fn strip_flow_bytes(&mut self, data: &[u8]) -> Vec<u8> {
+        let mut kept = Vec::with_capacity(data.len());
+        for &b in data {
+            match b {
+                XOFF => {
+                    trace!("XOFF received");
+                    self.paused = true;
+                }
+                XON => {
+                    trace!("XON received");
+                    self.paused = false;
+                }
+                b => kept.push(b),
+            }
+        }
+        kept
+    }
+
+    fn configure(&mut self, config: &DeviceConfig) -> Result<()> {
+        self.device
+            .set_baud_rate(config.baud.0)
+            .wrap_err("Unable to set baud rate")?;
+        self.device
+            .set_data_bits(config.bits)
+            .wrap_err("Unable to set data bits")?;
+        self.device
+            .set_parity(config.parity)
+            .wrap_err("Unable to set parity")?;
+        self.device
+            .set_stop_bits(config.stop)
+            .wrap_err("Unable to set stop bits")?;
+        self.device
+            .set_flow_control(config.flow)
+            .wrap_err("Unable to set flow control")?;
+        Ok(())
+    }
+
+    // writes held back by XOFF go out once the device sends XON
+    async fn release_held(&mut self) {
+        while self.alive
+            && let Some(i) = self
+                .held
+                .iter()
+                .position(|d| !self.paused || !held_by_xoff(d))
+        {
+            let d = self.held.remove(i).unwrap();
+            if let Err(e) = self.write(Some(d)).await {
+                self.data_tx.log(Severity::Error, format!("{}", e));
+            }
+        }
+    }
+
+    // Events keep arriving while a send waits on the device. Closing the
+    // port gives up on the send and goes next, anything else is held until
+    // the send is done.
+    fn hold_or_cancel(&mut self, e: Option<ToSerialData>) -> std::io::Result<()> {
+        if hold(&mut self.held, e) {
+            return Err(std::io::Error::other("Send cancelled, the port is closing"));
+        }
+        Ok(())
+    }
+
+    // Reads whatever is already waiting so an XOFF sent mid-transfer is
+    // noticed, then waits for XON if the device asked us to stop.
+    async fn wait_for_xon(&mut self) -> std::io::Result<()> {
+        let mut buf = [0; 64];
+        let deadline = tokio::time::Instant::now() + XON_WAIT;
+        loop {
+            if !self.paused {
+                match self.device.read(&mut buf).now_or_never() {
+                    None | Some(Ok(0)) => return Ok(()),
+                    Some(Ok(n)) => self.read(&buf[..n]).await,
+                    Some(Err(e)) => return Err(e),
+                }
+                continue;
+            }
+            select! {
+                r = self.device.read(&mut buf) => match r {
+                    Ok(0) => return Ok(()),
+                    Ok(n) => self.read(&buf[..n]).await,
+                    Err(e) => return Err(e),
+                },
+                e = self.events.recv() => self.hold_or_cancel(e)?,
+                _ = tokio::time::sleep_until(deadline) => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        "Device sent XOFF and no XON",
+                    ));
+                }
+            }
+        }
     }
     #[instrument]
     async fn write(&mut self, event: Option<ToSerialData>) -> Result<()> {
-        let Some(data) = event else {
-            self.alive = false;
-            return Ok(());
-        };
+        // closing a tab drops the channel, the port is closed the same way
+        let data = event.unwrap_or(ToSerialData::Disconnect);
         match data {
-            ToSerialData::Data(d) => self.device.write_all(d.as_bytes()).await?,
+            data if self.paused && held_by_xoff(&data) => self.held.push_back(data),
+            ToSerialData::Data(d) => {
+                self.capture_sent(d.as_bytes());
+                if self.software_flow || !self.tx_delay.is_zero() {
+                    self.write_paced(d.as_bytes()).await?
+                } else {
+                    self.write_device(d.as_bytes()).await?
+                }
+                self.data_tx
+                    .forward_serial(FromSerialData::Sent(d.len()))
+                    .await;
+            }
+            ToSerialData::RawBytes(b) => {
+                self.capture_sent(&b);
+                match self.write_paced(&b).await {
+                    Ok(()) => {
+                        self.data_tx
+                            .forward_serial(FromSerialData::Sent(b.len()))
+                            .await;
+                        self.data_tx
+                            .forward_serial(FromSerialData::SendComplete(b.len()))
+                            .await;
+                    }
+                    Err(e) => {
+                        self.data_tx
+                            .forward_serial(FromSerialData::SendFailed(e.into()))
+                            .await
+                    }
+                }
+            }
+            ToSerialData::Query(b, timeout) => {
+                self.capture_sent(&b);
+                if let Err(e) = self.write_paced(&b).await {
+                    self.data_tx
+                        .forward_serial(FromSerialData::SendFailed(e.into()))
+                        .await;
+                    return Ok(());
+                }
+                self.data_tx
+                    .forward_serial(FromSerialData::Sent(b.len()))
+                    .await;
+                // the response is logged instead of a send completing
+                let received = match self.collect_for(timeout).await {
+                    Ok(received) => received,
+                    Err(e) => {
+                        self.data_tx
+                            .forward_serial(FromSerialData::SendFailed(e.into()))
+                            .await;
+                        return Ok(());
+                    }
+                };
+                self.data_tx
+                    .forward_serial(FromSerialData::Response { sent: b, received })
+                    .await;
+            }
+            ToSerialData::Xmodem(b) => {
+                self.capture_sent(&b);
+                let to_dash = self.data_tx.clone();
+                // a transfer can wait on the receiver for minutes, closing
+                // the port must not
+                let sent = {
+                    let send = xmodem::send(&mut self.device, &b, |n, total| {
+                        to_dash.try_forward_serial(FromSerialData::XmodemProgress(n, total))
+                    });
+                    tokio::pin!(send);
+                    loop {
+                        select! {
+                            sent = &mut send => break Some(sent),
+                            e = self.events.recv() => if hold(&mut self.held, e) {
+                                break None;
+                            },
+                        }
+                    }
+                };
+                let sent = match sent {
+                    Some(sent) => sent,
+                    None => {
+                        // the receiver is told to stop rather than left waiting
+                        _ = tokio::time::timeout(TX_STALL, xmodem::cancel(&mut self.device)).await;
+                        Err(SerialError::Cancelled(
+                            "Transfer cancelled, the port is closing".into(),
+                        ))
+                    }
+                };
+                match sent {
+                    Ok(()) => {
+                        self.data_tx
+                            .forward_serial(FromSerialData::Sent(b.len()))
+                            .await;
+                        self.data_tx
+                            .forward_serial(FromSerialData::SendComplete(b.len()))
+                            .await;
+                    }
+                    Err(e) => {
+                        self.data_tx
+                            .forward_serial(FromSerialData::SendFailed(e))
+                            .await
+                    }
+                }
+            }
             ToSerialData::RTS(b) => {
                 trace!("Writing RTS = {}", b);
                 self.device.write_request_to_send(b)?;
+                self.send_status().await?;
             }
             ToSerialData::DTR(b) => {
                 trace!("Writing DTR = {}", b);
                 self.device.write_data_terminal_ready(b)?;
+                self.send_status().await?;
+            }
+            ToSerialData::ResetPulse(seq) => {
+                trace!("Resetting with {:?}", seq);
+                for (dtr, rts, hold) in seq.steps(self.reset_pulse) {
+                    if let Some(d) = dtr {
+                        self.device.write_data_terminal_ready(d)?;
+                    }
+                    if let Some(r) = rts {
+                        self.device.write_request_to_send(r)?;
+                    }
+                    self.idle_for(hold).await?;
+                }
+                self.send_status().await?;
+            }
+            ToSerialData::Configure(config) => {
+                let applied = self.configure(&config);
+                let actual = config.negotiated(&self.device).unwrap_or(config);
+                self.software_flow = actual.flow == FlowControl::Software;
+                // an XOFF can't hold us up once XON/XOFF is off
+                if !self.software_flow {
+                    self.paused = false;
+                }
+                self.data_tx
+                    .forward_serial(FromSerialData::Configured(actual))
+                    .await;
+                applied?;
+            }
+            ToSerialData::RequestStatus if self.control_lines => self.send_status().await?,
+            ToSerialData::RequestStatus => {}
+            ToSerialData::Unplugged => {
+                self.alive = false;
+                self.lost = Some(SerialError::Disconnected(
+                    "Device is no longer present".into(),
+                ));
+            }
+            // Whatever the OS still has buffered goes out before the port
+            // closes, unless the device has stopped taking data.
+            ToSerialData::Disconnect => {
+                self.alive = false;
+                if let Ok(flushed) = tokio::time::timeout(TX_STALL, self.device.flush()).await {
+                    flushed?;
+                }
+                if !self.close_break.is_zero() {
+                    self.device.set_break()?;
+                    tokio::time::sleep(self.close_break).await;
+                    self.device.clear_break()?;
+                }
+                if let Some(d) = self.close_dtr {
+                    self.device.write_data_terminal_ready(d)?;
+                }
+                if let Some(r) = self.close_rts {
+                    self.device.write_request_to_send(r)?;
+                }
             }
-            ToSerialData::Disconnect => self.alive = false,
         };
 
         Ok(())
     }
+
+    // serialport has no way to wait for a line to change, so edges are
+    // found by comparing each read with the one before
+    async fn send_status(&mut self) -> Result<()> {
+        let status = ModemStatus {
+            cts: self.device.read_clear_to_send()?,
+            dsr: self.device.read_data_set_ready()?,
+            ri: self.device.read_ring_indicator()?,
+            cd: self.device.read_carrier_detect()?,
+        };
+        if self.status.replace(status) != Some(status) {
+            self.data_tx
+                .forward_serial(FromSerialData::Status(status))
+                .await;
+        }
+        Ok(())
+    }
+
+    // Splits the data into lines or bytes with the configured delay after
+    // each. Without a delay this is just a chunked write.
+    async fn write_paced(&mut self, data: &[u8]) -> std::io::Result<()> {
+        if self.tx_delay.is_zero() {
+            return self.write_chunked(data).await;
+        }
+        let pieces: Vec<&[u8]> = match self.tx_pacing {
+            TxPacing::Line => data.split_inclusive(|b| *b == b'\n').collect(),
+            TxPacing::Byte => data.chunks(1).collect(),
+        };
+        for piece in pieces {
+            self.write_chunked(piece).await?;
+            self.idle_for(self.tx_delay).await?;
+        }
+        Ok(())
+    }
+
+    // sleeps without starving the read side
+    async fn idle_for(&mut self, duration: Duration) -> std::io::Result<()> {
+        self.collect_for(duration).await.map(drop)
+    }
+
+    // Reads for the duration, everything is passed on as usual and a copy
+    // is returned. Other requests wait until it is over, closing the port
+    // ends it early.
+    async fn collect_for(&mut self, duration: Duration) -> std::io::Result<Vec<u8>> {
+        let deadline = tokio::time::Instant::now() + duration;
+        let mut buf = [0; 256];
+        let mut received = Vec::new();
+        let mut eof = false;
+        loop {
+            select! {
+                _ = tokio::time::sleep_until(deadline) => return Ok(received),
+                e = self.events.recv() => self.hold_or_cancel(e)?,
+                r = self.device.read(&mut buf), if !eof => match r {
+                    // nothing more will come, the wait still runs out
+                    Ok(0) => eof = true,
+                    Ok(n) => {
+                        received.extend_from_slice(&buf[..n]);
+                        self.read(&buf[..n]).await;
+                    }
+                    Err(e) => return Err(e),
+                },
+            }
+        }
+    }
+
+    // Like write_all, but a device that stops taking data, such as one
+    // holding CTS low, is reported as blocked instead of failing the write.
+    // Only a real error or closing the port gives up.
+    async fn write_device(&mut self, data: &[u8]) -> std::io::Result<()> {
+        use std::io::ErrorKind;
+        let mut written = 0;
+        while written < data.len() {
+            let write = tokio::time::timeout(TX_STALL, self.device.write(&data[written..]));
+            let wrote = select! {
+                w = write => w,
+                e = self.events.recv() => {
+                    if let Err(e) = self.hold_or_cancel(e) {
+                        self.set_tx_blocked(false).await;
+                        return Err(e);
+                    }
+                    continue;
+                }
+            };
+            match wrote {
+                Ok(Ok(0)) => return Err(ErrorKind::WriteZero.into()),
+                Ok(Ok(n)) => {
+                    written += n;
+                    self.set_tx_blocked(false).await;
+                }
+                Ok(Err(e))
+                    if matches!(
+                        e.kind(),
+                        ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted
+                    ) =>
+                {
+                    self.set_tx_blocked(true).await;
+                    tokio_sleep(TX_RETRY).await;
+                }
+                Ok(Err(e)) => return Err(e),
+                Err(_) => self.set_tx_blocked(true).await,
+            }
+        }
+        Ok(())
+    }
+
+    // Some drivers return an empty read now and then without the port having
+    // closed, so only a run of them with the port also gone ends the session.
+    async fn empty_read(&mut self) {
+        self.empty_reads += 1;
+        if self.empty_reads >= EMPTY_READS {
+            self.empty_reads = 0;
+            let path = self.path.clone();
+            let present = tokio::task::spawn_blocking(move || port_present(&path))
+                .await
+                .unwrap_or(false);
+            if !present {
+                self.alive = false;
+                self.lost = Some(SerialError::Disconnected("Port closed".into()));
+                return;
+            }
+            trace!("Empty reads but the port is still present");
+        }
+        tokio_sleep(EMPTY_READ_BACKOFF).await;
+    }
+
+    async fn set_tx_blocked(&mut self, blocked: bool) {
+        if self.tx_blocked != blocked {
+            self.tx_blocked = blocked;
+            self.data_tx
+                .forward_serial(FromSerialData::TxBlocked(blocked))
+                .await;
+        }
+    }
+
+    // large sends are split up so other tasks get a chance to run in between
+    async fn write_chunked(&mut self, data: &[u8]) -> std::io::Result<()> {
+        const CHUNK_SIZE: usize = 256;
+        for chunk in data.chunks(CHUNK_SIZE) {
+            self.write_device(chunk).await?;
+            if self.software_flow {
+                self.wait_for_xon().await?;
+            }
+            tokio::task::yield_now().await;
+        }
+        Ok(())
+    }
 }
 
 pub fn serial_handler(
     device: SerialStream,
     data_tx: Messenger,
+    capture: Capture,
+    config: &DeviceConfig,
 ) -> mpsc::UnboundedSender<ToSerialData> {
     use Severity::Error;
-    let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+    let (event_tx, event_rx) = mpsc::unbounded_channel();
+    let requested = config.clone();
+    let read_buffer = config.read_buffer;
+    let idle_timeout = Some(Duration::from_secs(config.idle_timeout_secs)).filter(|d| !d.is_zero());
     tokio::spawn(
         async move {
-            data_tx.send_serial(FromSerialData::Connect(
-                device.name().unwrap_or("Virtual".into()),
-            ));
-            let mut buf = [0; 128];
-            let mut se = SerialImpl {
-                data_tx,
-                device,
-                alive: true,
-            };
+            data_tx
+                .forward_serial(FromSerialData::Connect(
+                    device.name().unwrap_or("Virtual".into()),
+                ))
+                .await;
+            match requested.negotiated(&device) {
+                Ok(actual) if actual.summary() != requested.summary() => data_tx.log(
+                    Error,
+                    format!(
+                        "Requested {} but the driver set {}",
+                        requested.summary(),
+                        actual.summary()
+                    ),
+                ),
+                Ok(actual) => {
+                    data_tx.log(Severity::Info, format!("Port set to {}", actual.summary()))
+                }
+                Err(e) => data_tx.log(
+                    Severity::Debug,
+                    format!("Unable to read back port settings: {}", e),
+                ),
+            }
+            let mut buf = vec![0; read_buffer];
+            let mut se = SerialImpl::new(data_tx, device, event_rx, capture, &requested);
+            // virtual ports have no control lines, so this is not worth an error
+            if let Err(err) = se.send_status().await {
+                se.control_lines = false;
+                se.data_tx.log(
+                    Severity::Debug,
+                    format!("Unable to read control lines: {}", err),
+                );
+            }
 
             while se.alive {
                 trace!("Serial waiting");
-                let read = se.device.read(&mut buf);
-                let write = event_rx.recv();
+                // once idle has been reported, wait for data without a deadline
+                let deadline = idle_timeout.filter(|_| !se.idle).map(|d| se.last_rx + d);
+                let device = &mut se.device;
+                let read_buf = &mut buf;
+                let read = async move {
+                    match deadline {
+                        Some(at) => tokio::time::timeout_at(at, device.read(read_buf))
+                            .await
+                            .ok(),
+                        None => Some(device.read(read_buf).await),
+                    }
+                };
+                let write = se.events.recv();
                 select!(
                     e = read => {
                         match e {
-                            Ok(bytes) => se.read(&buf[0..bytes]),
-                            Err(err) => se.data_tx.log(Error, format!("{}", err)),
+                            None => {
+                                se.idle = true;
+                                se.data_tx
+                                    .forward_serial(FromSerialData::Idle(
+                                        idle_timeout.unwrap_or_default(),
+                                    ))
+                                    .await;
+                            }
+                            Some(Ok(0)) => se.empty_read().await,
+                            Some(Ok(bytes)) => {
+                                se.empty_reads = 0;
+                                se.read(&buf[0..bytes]).await;
+                                se.release_held().await;
+                            }
+                            Some(Err(err)) => {
+                                se.alive = false;
+                                se.lost = Some(err.into());
+                            }
                         }
                     }
                     e = write => {
                         if let Err(err) = se.write(e).await {
                             se.data_tx.log(Error, format!("{}", err));
                         }
+                        se.release_held().await;
                     }
                 )
             }
 
-            se.data_tx.send_serial(FromSerialData::Gone);
+            se.data_tx
+                .forward_serial(match se.lost {
+                    Some(e) => FromSerialData::Lost(e),
+                    None => FromSerialData::Gone,
+                })
+                .await;
         }
         .instrument(info_span!("Serial")),
     );
@@ -267,12 +1125,77 @@ impl notify::EventHandler for WatcherImpl {
     }
 }
 
+/// What a watcher reacts to: one file, anything under a directory, or files in
+/// a directory whose names match a glob such as `build/*.hex`.
+#[derive(Debug)]
+enum WatchTarget {
+    File(PathBuf),
+    Dir(PathBuf),
+    Glob(PathBuf, WildMatch),
+}
+
+impl WatchTarget {
+    fn new(path: &Path) -> Self {
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+        if path.is_dir() {
+            WatchTarget::Dir(path.to_path_buf())
+        } else if name.contains(['*', '?']) {
+            let dir = match path.parent() {
+                Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+                _ => PathBuf::from("."),
+            };
+            WatchTarget::Glob(dir, WildMatch::new(name))
+        } else {
+            WatchTarget::File(path.to_path_buf())
+        }
+    }
+
+    fn root(&self) -> &Path {
+        match self {
+            WatchTarget::File(p) | WatchTarget::Dir(p) | WatchTarget::Glob(p, _) => p,
+        }
+    }
+
+    fn mode(&self) -> notify::RecursiveMode {
+        match self {
+            WatchTarget::Glob(..) => notify::RecursiveMode::NonRecursive,
+            _ => notify::RecursiveMode::Recursive,
+        }
+    }
+
+    // the file that changed, if any of the event's paths are of interest
+    fn changed(&self, paths: &[PathBuf]) -> Option<PathBuf> {
+        match self {
+            WatchTarget::File(p) => Some(p.clone()),
+            WatchTarget::Dir(_) => paths.iter().find(|p| p.is_file()).cloned(),
+            WatchTarget::Glob(_, pattern) => paths
+                .iter()
+                .find(|p| {
+                    p.file_name()
+                        .and_then(|n| n.to_str())
+                        .is_some_and(|n| pattern.matches(n))
+                })
+                .cloned(),
+        }
+    }
+}
+
 struct UploaderImpl {
     _watcher: RecommendedWatcher,
     events: mpsc::UnboundedReceiver<notify::Result<notify::Event>>,
     to_dash: Messenger,
     from_app: mpsc::UnboundedReceiver<ToFileWatcher>,
-    cmd: Vec<String>,
+    target: WatchTarget,
+    // #BIN# is replaced with the changed file on every run
+    cmd: String,
+    // quiet period before acting on a change, so bursts of writes upload once
+    debounce: Duration,
+    // a change to the uploaded file seen while the command was running
+    pending: Option<PathBuf>,
+    // the last file uploaded, as the command left it
+    uploaded: Option<(PathBuf, Stamp)>,
+    // run the command with the port still open, for tools that share it
+    keep_connected: bool,
     alive: bool,
 }
 
@@ -281,27 +1204,47 @@ pub fn new_filewatcher(
     cmd: String,
     events: Messenger,
     autorun: bool,
+    opts: WatchOptions,
 ) -> Result<mpsc::UnboundedSender<ToFileWatcher>> {
     let (tx, rx) = mpsc::unbounded_channel();
     let (to_watcher, from_app) = mpsc::unbounded_channel();
+    let target = WatchTarget::new(file);
+    // catch a bad command now rather than on the first change
+    let warnings = check_upload(&target, &cmd)?;
     let mut watcher = notify::recommended_watcher(WatcherImpl(tx))?;
-    watcher.watch(file, notify::RecursiveMode::Recursive)?;
-    let cmd = cmd.replace(
-        "#BIN#",
-        file.to_str().ok_or_eyre("Unable to parse binary path")?,
-    );
-    let cmd = shlex::split(&cmd).ok_or_eyre("Unable to parse command")?;
+    watcher.watch(target.root(), target.mode())?;
     tokio::spawn(async move {
         let mut u = UploaderImpl {
             _watcher: watcher,
             events: rx,
             to_dash: events,
+            target,
             cmd,
             from_app,
+            debounce: Duration::from_millis(opts.debounce_ms),
+            pending: None,
+            uploaded: None,
+            keep_connected: opts.no_disconnect,
             alive: true,
         };
-        if autorun {
-            u.exec();
+        let mode = if u.keep_connected {
+            "serial port stays open during upload"
+        } else {
+            "serial port is closed during upload"
+        };
+        u.to_dash.log(
+            Severity::Info,
+            format!("Watching {} ({})", u.target.root().display(), mode),
+        );
+        for w in warnings {
+            u.to_dash.log(Severity::Info, w);
+        }
+        // there is nothing to upload yet when watching several files
+        if autorun
+            && let WatchTarget::File(ref f) = u.target
+            && let Err(e) = u.exec(&f.clone()).await
+        {
+            u.to_dash.log(Severity::Error, e.to_string());
         }
         while u.alive {
             u.run().await;
@@ -311,27 +1254,177 @@ pub fn new_filewatcher(
     Ok(to_watcher)
 }
 
+/// Checks an upload command before anything is watched: the watched path
+/// exists, the command parses and its program can be found. Returns
+/// warnings about things that are allowed but probably a mistake.
+fn check_upload(target: &WatchTarget, template: &str) -> Result<Vec<String>> {
+    let root = target.root();
+    if !root.exists() {
+        return Err(eyre!("{} does not exist", root.display()));
+    }
+    let cmd = build_command(template, root)?;
+    if !is_executable(&cmd[0]) {
+        return Err(eyre!("Could not find program {} on PATH", cmd[0]));
+    }
+    let mut warnings = Vec::new();
+    if !template.contains("#BIN#") {
+        warnings.push(format!(
+            "Upload command has no #BIN#, {} will not be passed to it",
+            root.display()
+        ));
+    }
+    Ok(warnings)
+}
+
+// a program with a slash in its name is a path, anything else is searched
+// for in $PATH like the shell does
+fn is_executable(program: &str) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    let runnable = |p: &Path| {
+        p.metadata()
+            .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+    };
+    if program.contains('/') {
+        return runnable(Path::new(program));
+    }
+    std::env::var_os("PATH")
+        .is_some_and(|paths| std::env::split_paths(&paths).any(|dir| runnable(&dir.join(program))))
+}
+
+/// The `check-upload` subcommand, prints what would be run or why it can't.
+pub fn print_upload_check(file: &Path, template: &str) -> Result<()> {
+    let target = WatchTarget::new(file);
+    for w in check_upload(&target, template)? {
+        println!("warning: {}", w);
+    }
+    let cmd = build_command(template, target.root())?;
+    println!("{}", shlex::try_join(cmd.iter().map(String::as_str))?);
+    Ok(())
+}
+
+fn build_command(template: &str, file: &Path) -> Result<Vec<String>> {
+    let cmd = template.replace(
+        "#BIN#",
+        file.to_str().ok_or_eyre("Unable to parse binary path")?,
+    );
+    let cmd = shlex::split(&cmd).ok_or_eyre("Unable to parse command")?;
+    if cmd.is_empty() {
+        return Err(eyre!("Upload command is empty"));
+    }
+    Ok(cmd)
+}
+
+// Reads and opens show up as events too, reacting to them would upload again
+// every time the command looks at the file.
+fn is_change(e: &notify::Event) -> bool {
+    use notify::EventKind::{Any, Create, Modify};
+    matches!(e.kind, Any | Create(..) | Modify(..))
+}
+
+// when a file was last written and its size, to tell a new build apart from
+// events about a file that has not changed since
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Stamp {
+    modified: SystemTime,
+    len: u64,
+}
+
+fn stamp(path: &Path) -> Option<Stamp> {
+    let meta = path.metadata().ok()?;
+    Some(Stamp {
+        modified: meta.modified().ok()?,
+        len: meta.len(),
+    })
+}
+
 impl UploaderImpl {
     async fn run(&mut self) {
-        use notify::EventKind::{Any, Create, Modify};
         if self.from_app.is_closed() {
+            self.alive = false;
             return;
         }
-        match self.events.recv().await.unwrap() {
-            Ok(notify::Event {
-                kind: Any | Create(..) | Modify(..),
-                ..
-            }) => {
-                self.upload().await;
-            }
-            Err(e) => self
-                .to_dash
-                .log(Severity::Error, format!("Error watching file: {}", e)),
-            _ => {}
+        let changed = match self.pending.take() {
+            Some(p) => p,
+            None => match self.events.recv().await.unwrap() {
+                Ok(e) if is_change(&e) => match self.target.changed(&e.paths) {
+                    Some(p) => p,
+                    None => return,
+                },
+                Err(e) => {
+                    self.to_dash
+                        .log(Severity::Error, format!("Error watching file: {}", e));
+                    return;
+                }
+                _ => return,
+            },
         };
+        let changed = self.settle(changed).await;
+        if self.already_uploaded(&changed) {
+            return;
+        }
+        self.upload(&changed).await;
+    }
+
+    fn already_uploaded(&self, file: &Path) -> bool {
+        self.uploaded
+            .as_ref()
+            .is_some_and(|(path, uploaded)| path == file && stamp(file) == Some(*uploaded))
     }
 
-    async fn upload(&mut self) {
+    // Swallows events until none have arrived for the debounce period. Changes
+    // made while a command was running are already queued, so they collapse
+    // into a single follow-up run here.
+    async fn settle(&mut self, mut latest: PathBuf) -> PathBuf {
+        loop {
+            select! {
+                e = self.events.recv() => match e {
+                    Some(Err(e)) => self
+                        .to_dash
+                        .log(Severity::Error, format!("Error watching file: {}", e)),
+                    Some(Ok(e)) => {
+                        if is_change(&e)
+                            && let Some(p) = self.target.changed(&e.paths)
+                        {
+                            latest = p;
+                        }
+                    }
+                    None => return latest,
+                },
+                _ = tokio_sleep(self.debounce) => return latest,
+            }
+        }
+    }
+
+    // Anything that changed while the command ran is assumed to have been
+    // written by the command itself, and reacting to it would loop forever.
+    // Its events can arrive well after it exits, so they are swallowed until
+    // none come for the debounce period. Only a file that has changed since
+    // the command left it is uploaded again.
+    async fn skip_own_writes(&mut self, uploaded: &Path) {
+        let left = stamp(uploaded);
+        loop {
+            select! {
+                e = self.events.recv() => if e.is_none() {
+                    break;
+                },
+                _ = tokio_sleep(self.debounce) => break,
+            }
+        }
+        self.uploaded = left.map(|s| (uploaded.to_path_buf(), s));
+        if stamp(uploaded) != left {
+            self.pending = Some(uploaded.to_path_buf());
+        }
+    }
+
+    async fn upload(&mut self, file: &Path) {
+        if self.keep_connected {
+            // received data keeps flowing to the terminal and capture meanwhile
+            if let Err(e) = self.exec(file).await {
+                self.to_dash.log(Severity::Error, e.to_string())
+            }
+            self.skip_own_writes(file).await;
+            return;
+        }
         self.to_dash.send_file(FromFileWatcher::DisonnectRequest);
         // await for disconnect to finish;
         match self.from_app.recv().await {
@@ -344,15 +1437,24 @@ impl UploaderImpl {
                 return;
             }
         }
-        if let Err(e) = self.exec().await {
+        if let Err(e) = self.exec(file).await {
             self.to_dash.log(Severity::Error, e.to_string())
         }
+        self.skip_own_writes(file).await;
         self.to_dash.send_file(FromFileWatcher::ReconnectRequest);
     }
 
-    async fn exec(&self) -> Result<()> {
-        let out = tokio::process::Command::new(&self.cmd[0])
-            .args(&self.cmd[1..])
+    async fn exec(&self, file: &Path) -> Result<()> {
+        self.to_dash.send_file(FromFileWatcher::Busy(true));
+        let r = self.run_command(file).await;
+        self.to_dash.send_file(FromFileWatcher::Busy(false));
+        r
+    }
+
+    async fn run_command(&self, file: &Path) -> Result<()> {
+        let cmd = build_command(&self.cmd, file)?;
+        let out = tokio::process::Command::new(&cmd[0])
+            .args(&cmd[1..])
             .output()
             .await
             .wrap_err("Unable to execute command")?;
@@ -364,17 +1466,391 @@ impl UploaderImpl {
         self.to_dash.log(
             severity,
             format!(
-                "UPLOAD {}: {}",
+                "UPLOAD {}: {} (ctrl+o for output)",
                 out.status.code().unwrap_or(0),
-                String::from_utf8_lossy(&out.stdout)
+                file.display()
             ),
         );
-        if !out.stderr.is_empty() {
-            self.to_dash.log(
-                Severity::Error,
-                String::from_utf8_lossy(&out.stderr).to_string(),
-            );
-        }
+        self.to_dash.send_file(FromFileWatcher::Output(UploadRun {
+            file: file.to_path_buf(),
+            code: out.status.code(),
+            success: out.status.success(),
+            stdout: String::from_utf8_lossy(&out.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&out.stderr).into_owned(),
+        }));
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a serial handler on one end of a pty, the other end plays the device
+    fn open_pty(
+        config: &DeviceConfig,
+    ) -> (
+        SerialStream,
+        mpsc::UnboundedSender<ToSerialData>,
+        mpsc::Receiver<(usize, FromSerialData)>,
+    ) {
+        let (computer, device) = SerialStream::pair().unwrap();
+        let (app_tx, _) = mpsc::unbounded_channel();
+        let (serial_tx, serial_rx) = mpsc::channel(SERIAL_QUEUE);
+        let to_serial = serial_handler(
+            computer,
+            Messenger::new(app_tx, serial_tx),
+            Capture::default(),
+            config,
+        );
+        (device, to_serial, serial_rx)
+    }
+
+    // an app that stops reading makes the handler stop reading too, so the
+    // device is held up instead of the queue growing
+    #[tokio::test]
+    async fn full_queue_holds_up_the_device() {
+        let (mut device, _to_serial, mut serial_rx) = open_pty(&DeviceConfig::default());
+        let payload = vec![b'x'; 16 << 20];
+        let len = payload.len();
+        let writer = tokio::spawn(async move {
+            device.write_all(&payload).await.unwrap();
+            device
+        });
+
+        tokio_sleep(Duration::from_millis(500)).await;
+        assert_eq!(serial_rx.len(), SERIAL_QUEUE);
+        assert!(!writer.is_finished());
+
+        let mut received = 0;
+        while received < len {
+            let next = tokio::time::timeout(Duration::from_secs(10), serial_rx.recv());
+            if let FromSerialData::Data(d) = next.await.expect("data stopped arriving").unwrap().1 {
+                received += d.len();
+            }
+        }
+        assert_eq!(received, len);
+        let _device = writer.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn closing_the_device_ends_the_session() {
+        let (device, _to_serial, mut serial_rx) = open_pty(&DeviceConfig::default());
+        drop(device);
+        let lost = tokio::time::timeout(Duration::from_secs(5), async {
+            while let Some((_, d)) = serial_rx.recv().await {
+                if let FromSerialData::Lost(_) = d {
+                    return true;
+                }
+            }
+            false
+        });
+        assert!(lost.await.expect("the closed port was not noticed"));
+    }
+
+    fn software_flow() -> DeviceConfig {
+        DeviceConfig {
+            flow: FlowControl::Software,
+            ..Default::default()
+        }
+    }
+
+    // reads from the device side, None if nothing arrives in time
+    async fn read_device(device: &mut SerialStream, wait: Duration) -> Option<Vec<u8>> {
+        let mut buf = [0; 256];
+        match tokio::time::timeout(wait, device.read(&mut buf)).await {
+            Ok(Ok(n)) => Some(buf[..n].to_vec()),
+            _ => None,
+        }
+    }
+
+    // the device stops reading, as if it held CTS low
+    #[tokio::test]
+    async fn disconnect_cancels_a_blocked_send() {
+        let (_device, to_serial, mut serial_rx) = open_pty(&DeviceConfig::default());
+        to_serial
+            .send(ToSerialData::RawBytes(vec![b'x'; 1 << 20]))
+            .unwrap();
+        tokio_sleep(TX_STALL + Duration::from_millis(200)).await;
+        to_serial.send(ToSerialData::Disconnect).unwrap();
+
+        let mut seen = Vec::new();
+        let ended = tokio::time::timeout(Duration::from_secs(5), async {
+            while let Some((_, d)) = serial_rx.recv().await {
+                match d {
+                    FromSerialData::Gone => return,
+                    d => seen.push(d),
+                }
+            }
+        });
+        ended.await.expect("the blocked send kept the port open");
+        assert!(
+            seen.iter()
+                .any(|d| matches!(d, FromSerialData::TxBlocked(true)))
+        );
+        assert!(
+            seen.iter()
+                .any(|d| matches!(d, FromSerialData::SendFailed(_)))
+        );
+    }
+
+    #[tokio::test]
+    async fn xoff_holds_sends_until_xon() {
+        let (mut device, to_serial, _serial_rx) = open_pty(&software_flow());
+        device.write_all(&[XOFF]).await.unwrap();
+        tokio_sleep(Duration::from_millis(100)).await;
+        to_serial
+            .send(ToSerialData::RawBytes(b"hello".to_vec()))
+            .unwrap();
+        assert_eq!(
+            read_device(&mut device, Duration::from_millis(300)).await,
+            None
+        );
+
+        device.write_all(&[XON]).await.unwrap();
+        let sent = read_device(&mut device, Duration::from_secs(2)).await;
+        assert_eq!(sent.as_deref(), Some(&b"hello"[..]));
+    }
+
+    // an XOFF in the middle of a send leaves it waiting, closing the port
+    // must still get through
+    #[tokio::test]
+    async fn disconnect_cancels_a_send_waiting_for_xon() {
+        let (device, to_serial, mut serial_rx) = open_pty(&software_flow());
+        let (mut reader, mut writer) = tokio::io::split(device);
+        let drain = tokio::spawn(async move {
+            let mut buf = [0; 256];
+            let mut total = 0;
+            while let Ok(n @ 1..) = reader.read(&mut buf).await {
+                if total == 0 {
+                    writer.write_all(&[XOFF]).await.unwrap();
+                }
+                total += n;
+            }
+            total
+        });
+        let len = 1 << 20;
+        to_serial
+            .send(ToSerialData::RawBytes(vec![b'x'; len]))
+            .unwrap();
+        tokio_sleep(Duration::from_millis(300)).await;
+        to_serial.send(ToSerialData::Disconnect).unwrap();
+
+        let mut failed = false;
+        let ended = tokio::time::timeout(Duration::from_secs(5), async {
+            while let Some((_, d)) = serial_rx.recv().await {
+                match d {
+                    FromSerialData::SendFailed(_) => failed = true,
+                    FromSerialData::SendComplete(_) => panic!("the send was not held up"),
+                    FromSerialData::Gone => return,
+                    _ => {}
+                }
+            }
+        });
+        ended.await.expect("the waiting send kept the port open");
+        assert!(failed);
+        drain.abort();
+    }
+
+    // text and raw bytes are counted the same way, once they have gone out
+    #[tokio::test]
+    async fn every_send_is_counted_once_written() {
+        let (mut device, to_serial, mut serial_rx) = open_pty(&DeviceConfig::default());
+        to_serial.send(ToSerialData::Data("hi".into())).unwrap();
+        to_serial
+            .send(ToSerialData::RawBytes(b"abc".to_vec()))
+            .unwrap();
+        let mut sent = Vec::new();
+        tokio::time::timeout(Duration::from_secs(2), async {
+            while sent.len() < 2 {
+                if let Some((_, FromSerialData::Sent(n))) = serial_rx.recv().await {
+                    sent.push(n);
+                }
+            }
+        })
+        .await
+        .expect("the sends were not counted");
+        assert_eq!(sent, [2, 3]);
+        let mut written = Vec::new();
+        while let Some(b) = read_device(&mut device, Duration::from_millis(200)).await {
+            written.extend(b);
+        }
+        assert_eq!(written, b"hiabc");
+    }
+
+    // a query waiting out a long response timeout must not keep the port open
+    #[tokio::test]
+    async fn disconnect_ends_a_query_early() {
+        let (_device, to_serial, mut serial_rx) = open_pty(&DeviceConfig::default());
+        to_serial
+            .send(ToSerialData::Query(b"?".to_vec(), Duration::from_secs(30)))
+            .unwrap();
+        tokio_sleep(Duration::from_millis(100)).await;
+        to_serial.send(ToSerialData::Disconnect).unwrap();
+        let mut failed = false;
+        let ended = tokio::time::timeout(Duration::from_secs(5), async {
+            while let Some((_, d)) = serial_rx.recv().await {
+                match d {
+                    FromSerialData::SendFailed(_) => failed = true,
+                    FromSerialData::SendComplete(_) => panic!("queries are not logged as sent"),
+                    FromSerialData::Response { .. } => panic!("the query was not cut short"),
+                    FromSerialData::Gone => return,
+                    _ => {}
+                }
+            }
+        });
+        ended.await.expect("the query kept the port open");
+        assert!(failed);
+    }
+
+    #[tokio::test]
+    async fn configure_reports_the_applied_settings() {
+        let (_device, to_serial, mut serial_rx) = open_pty(&DeviceConfig::default());
+        let mut config = DeviceConfig::default();
+        config.baud.0 = 9600;
+        to_serial.send(ToSerialData::Configure(config)).unwrap();
+        let configured = tokio::time::timeout(Duration::from_secs(2), async {
+            while let Some((_, d)) = serial_rx.recv().await {
+                if let FromSerialData::Configured(c) = d {
+                    return c;
+                }
+            }
+            panic!("the handler ended without answering");
+        });
+        let configured = configured.await.expect("no answer to Configure");
+        assert_eq!(configured.baud.0, 9600);
+        assert_eq!(configured.bits, serialport::DataBits::Eight);
+    }
+
+    // the reply is what arrives before the timeout, nothing after it
+    #[tokio::test]
+    async fn query_collects_the_reply() {
+        let (mut device, to_serial, mut serial_rx) = open_pty(&DeviceConfig::default());
+        let timeout = Duration::from_millis(300);
+        to_serial
+            .send(ToSerialData::Query(b"AT\r\n".to_vec(), timeout))
+            .unwrap();
+        let sent = read_device(&mut device, Duration::from_secs(2)).await;
+        assert_eq!(sent.as_deref(), Some(&b"AT\r\n"[..]));
+        device.write_all(b"OK\r\n").await.unwrap();
+        tokio_sleep(timeout + Duration::from_millis(200)).await;
+        device.write_all(b"late\r\n").await.unwrap();
+
+        let response = tokio::time::timeout(Duration::from_secs(2), async {
+            while let Some((_, d)) = serial_rx.recv().await {
+                if let FromSerialData::Response { sent, received } = d {
+                    return (sent, received);
+                }
+            }
+            panic!("the handler ended without a response");
+        });
+        let (sent, received) = response.await.expect("no response to the query");
+        assert_eq!(sent, b"AT\r\n");
+        assert_eq!(received, b"OK\r\n");
+    }
+
+    fn serial_impl(
+        path: &str,
+    ) -> (
+        SerialImpl,
+        SerialStream,
+        mpsc::UnboundedSender<ToSerialData>,
+    ) {
+        let (computer, device) = SerialStream::pair().unwrap();
+        let (to_serial, events) = mpsc::unbounded_channel();
+        let (app_tx, _) = mpsc::unbounded_channel();
+        let (serial_tx, _) = mpsc::channel(SERIAL_QUEUE);
+        let config = DeviceConfig {
+            path: path.into(),
+            ..Default::default()
+        };
+        let se = SerialImpl::new(
+            Messenger::new(app_tx, serial_tx),
+            computer,
+            events,
+            Capture::default(),
+            &config,
+        );
+        (se, device, to_serial)
+    }
+
+    #[tokio::test]
+    async fn empty_reads_end_the_session_once_the_port_is_gone() {
+        let (mut se, _device, _to_serial) = serial_impl("/dev/seterm-test-missing");
+        for _ in 1..EMPTY_READS {
+            se.empty_read().await;
+            assert!(se.alive);
+        }
+        se.empty_read().await;
+        assert!(!se.alive);
+        assert!(matches!(se.lost, Some(SerialError::Disconnected(_))));
+    }
+
+    #[tokio::test]
+    async fn empty_reads_are_ignored_while_the_port_is_there() {
+        let (mut se, _device, _to_serial) = serial_impl("/dev/null");
+        for _ in 0..EMPTY_READS * 2 {
+            se.empty_read().await;
+        }
+        assert!(se.alive);
+        assert!(se.lost.is_none());
+    }
+
+    // the receiver never starts, closing the port still ends the transfer
+    #[tokio::test]
+    async fn disconnect_cancels_an_xmodem_transfer() {
+        let (mut se, mut device, to_serial) = serial_impl("/dev/null");
+        to_serial.send(ToSerialData::Disconnect).unwrap();
+        let transfer = se.write(Some(ToSerialData::Xmodem(b"firmware".to_vec())));
+        tokio::time::timeout(Duration::from_secs(2), transfer)
+            .await
+            .expect("the transfer kept waiting")
+            .unwrap();
+        assert!(matches!(se.held.front(), Some(ToSerialData::Disconnect)));
+        let sent = read_device(&mut device, Duration::from_secs(1)).await;
+        assert_eq!(sent.as_deref(), Some(&[0x18, 0x18][..]));
+    }
+
+    // a flasher that writes to the file it was given must not set off
+    // another upload, a new build still does
+    #[tokio::test]
+    async fn command_writing_the_file_uploads_once() {
+        let dir = std::env::temp_dir().join(format!("seterm-watch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("fw.bin");
+        std::fs::write(&file, b"build 1").unwrap();
+        let (app_tx, mut app_rx) = mpsc::unbounded_channel();
+        let (serial_tx, _) = mpsc::channel(SERIAL_QUEUE);
+        let opts = WatchOptions {
+            debounce_ms: 100,
+            no_disconnect: true,
+            ..Default::default()
+        };
+        let _to_watcher = new_filewatcher(
+            &file,
+            "sh -c 'echo flashed >> #BIN#'".into(),
+            Messenger::new(app_tx, serial_tx),
+            false,
+            opts,
+        )
+        .unwrap();
+        // counts the uploads started within the window
+        let mut uploads = async |window| {
+            let mut runs = 0;
+            let deadline = tokio::time::Instant::now() + window;
+            while let Ok(Some((_, e))) = tokio::time::timeout_at(deadline, app_rx.recv()).await {
+                if let ToAppEvent::App(AppEvent::Watcher(FromFileWatcher::Busy(true))) = e {
+                    runs += 1;
+                }
+            }
+            runs
+        };
+
+        tokio_sleep(Duration::from_millis(200)).await;
+        std::fs::write(&file, b"build 2").unwrap();
+        assert_eq!(uploads(Duration::from_secs(2)).await, 1);
+        std::fs::write(&file, b"build 3").unwrap();
+        assert_eq!(uploads(Duration::from_secs(2)).await, 1);
+        _ = std::fs::remove_dir_all(&dir);
+    }
+}
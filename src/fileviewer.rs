@@ -1,22 +1,189 @@
-use std::{fs::DirEntry, mem::take, path::PathBuf};
+use std::{
+    collections::BTreeMap,
+    mem::take,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::SystemTime,
+};
 
-use crate::event::{Drawable, EventListener, GuiEvent, Messenger, Severity, ToAppEvent};
+use crate::{
+    device_finder::render_text_entry,
+    event::{Drawable, EventListener, GuiEvent, Messenger, Severity},
+    theme::Theme,
+};
 
 use eyre::{OptionExt, Result, eyre};
 use ratatui::{
     Frame,
-    style::{Modifier, Style, Stylize},
+    layout::{Constraint, Layout, Rect},
+    style::Stylize,
     text::{Line, Span, Text},
-    widgets::{Block, List, ListState, Paragraph, StatefulWidget, Widget},
+    widgets::{Block, Cell, List, ListState, Paragraph, Row, Table, TableState},
 };
-use tokio::sync::{mpsc, oneshot};
+use tokio::sync::oneshot;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum SortBy {
+    #[default]
+    Name,
+    // newest first
+    Modified,
+    // largest first
+    Size,
+}
+
+impl SortBy {
+    fn next(self) -> Self {
+        match self {
+            SortBy::Name => SortBy::Modified,
+            SortBy::Modified => SortBy::Size,
+            SortBy::Size => SortBy::Name,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            SortBy::Name => "name",
+            SortBy::Modified => "modified",
+            SortBy::Size => "size",
+        }
+    }
+}
+
+struct Entry {
+    path: PathBuf,
+    name: String,
+    is_dir: bool,
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+impl Entry {
+    fn new(path: PathBuf) -> Self {
+        // follows symlinks, falling back to the link itself if it is broken
+        let meta = std::fs::metadata(&path).or_else(|_| std::fs::symlink_metadata(&path));
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Self {
+            is_dir: meta.as_ref().is_ok_and(|m| m.is_dir()),
+            size: meta.as_ref().map(|m| m.len()).unwrap_or(0),
+            modified: meta.ok().and_then(|m| m.modified().ok()),
+            name,
+            path,
+        }
+    }
+
+    fn row(&self) -> Row<'_> {
+        let name = if self.is_dir {
+            format!("🗀 {}", self.name)
+        } else {
+            self.name.clone()
+        };
+        let size = if self.is_dir {
+            String::new()
+        } else {
+            human_size(self.size)
+        };
+        let age = self.modified.map(age).unwrap_or_default();
+        Row::new([
+            Cell::from(name),
+            Cell::from(Line::raw(size).right_aligned()),
+            Cell::from(Line::raw(age).right_aligned()),
+        ])
+    }
+}
+
+fn human_size(b: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "K", "M", "G"];
+    let mut size = b as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{}{}", b, UNITS[0])
+    } else {
+        format!("{:.1}{}", size, UNITS[unit])
+    }
+}
+
+// how long ago, which is what matters when picking the latest build
+fn age(t: SystemTime) -> String {
+    let secs = SystemTime::now()
+        .duration_since(t)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    match secs {
+        0..60 => format!("{}s ago", secs),
+        60..3600 => format!("{}m ago", secs / 60),
+        3600..86400 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
+
+// which entries are listed; directories ignore the extension filter so
+// navigation always works
+#[derive(Default)]
+struct Filter {
+    show_hidden: bool,
+    // lowercase, without the leading dot
+    extensions: Vec<String>,
+}
+
+impl Filter {
+    fn shows(&self, e: &Entry) -> bool {
+        if !self.show_hidden && e.name.starts_with('.') {
+            return false;
+        }
+        if e.is_dir || self.extensions.is_empty() {
+            return true;
+        }
+        e.path
+            .extension()
+            .map(|x| x.to_string_lossy().to_lowercase())
+            .is_some_and(|x| self.extensions.contains(&x))
+    }
+}
+
+fn read_entries(dir: &Path, sort: SortBy, filter: &Filter) -> Result<Vec<Entry>> {
+    let mut entries: Vec<_> = dir
+        .read_dir()?
+        .filter_map(|r| r.ok())
+        .map(|e| Entry::new(e.path()))
+        .filter(|e| filter.shows(e))
+        .collect();
+    entries.sort_by(|a, b| {
+        // directories always come first
+        b.is_dir.cmp(&a.is_dir).then_with(|| match sort {
+            SortBy::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+            SortBy::Modified => b.modified.cmp(&a.modified),
+            SortBy::Size => b.size.cmp(&a.size),
+        })
+    });
+    Ok(entries)
+}
 
 pub struct FileViewer {
     title: String,
     cur_dir: PathBuf,
-    contents: Vec<DirEntry>,
-    list_contents: Vec<String>,
-    list_state: ListState,
+    contents: Vec<Entry>,
+    // why cur_dir could not be listed
+    unreadable: Option<String>,
+    sort: SortBy,
+    filter: Filter,
+    list_state: TableState,
+    // path being typed after pressing 'g'
+    typed: Option<String>,
+    // whether the current directory itself can be chosen
+    pick_dirs: bool,
+    // set while the picked file is to be read as hex text, toggled with 'x'
+    hex: Option<Arc<AtomicBool>>,
     tx: Option<oneshot::Sender<PathBuf>>,
     to_app: Messenger,
 }
@@ -38,61 +205,144 @@ impl FileViewer {
         } else {
             std::env::current_dir().map_err(|e| eyre!("Error reading current directory: {}", e))?
         };
-        let contents: Vec<DirEntry> = cur_dir.read_dir()?.filter_map(|r| r.ok()).collect();
-        let mut selection = None;
+        let (tx, rx) = oneshot::channel();
+        let tx = Some(tx);
+        let mut viewer = Self {
+            title,
+            cur_dir: PathBuf::new(),
+            contents: Vec::new(),
+            unreadable: None,
+            sort: SortBy::default(),
+            filter: Filter::default(),
+            list_state: TableState::default(),
+            typed: None,
+            pick_dirs: false,
+            hex: None,
+            tx,
+            to_app,
+        };
+        viewer.update_dir(cur_dir);
         if let Some(file) = default_select
-            && file.metadata().unwrap().is_file()
+            && file.is_file()
         {
-            selection = contents
-                .iter()
-                .enumerate()
-                .skip_while(|(_, f)| f.path() != file)
-                .next()
-                .map(|(i, _)| i);
-        }
-        let list_contents = contents
+            let selection = viewer.contents.iter().position(|e| e.path == file);
+            viewer.list_state.select(selection);
+        }
+        Ok((viewer, rx))
+    }
+
+    /// Only lists files with one of these extensions (given with or without
+    /// the dot). Directories are always listed.
+    pub fn with_extensions(mut self, extensions: &[String]) -> Self {
+        self.filter.extensions = extensions
             .iter()
-            .map(|e| {
-                if e.metadata().unwrap().is_dir() {
-                    format!("🗀 {}", e.file_name().display())
-                } else {
-                    format!("{}", e.file_name().display())
-                }
-            })
+            .map(|x| x.trim_start_matches('.').to_lowercase())
             .collect();
+        self.reload();
+        self
+    }
 
-        let (tx, rx) = oneshot::channel();
-        let tx = Some(tx);
-        Ok((
-            Self {
-                title,
-                cur_dir,
-                contents,
-                list_contents,
-                list_state: ListState::default().with_selected(selection),
-                tx,
-                to_app,
-            },
-            rx,
-        ))
+    // re-reads the current directory, keeping the same entry selected
+    fn reload(&mut self) {
+        let selected = self
+            .list_state
+            .selected()
+            .and_then(|i| self.contents.get(i))
+            .map(|e| e.path.clone());
+        self.contents = self.read_dir(&self.cur_dir.clone());
+        self.list_state
+            .select(selected.and_then(|p| self.contents.iter().position(|e| e.path == p)));
+    }
+
+    // An unreadable directory is shown empty with the reason, rather than
+    // refusing to go there, so Left still leads back out.
+    fn read_dir(&mut self, dir: &Path) -> Vec<Entry> {
+        match read_entries(dir, self.sort, &self.filter) {
+            Ok(entries) => {
+                self.unreadable = None;
+                entries
+            }
+            Err(e) => {
+                self.to_app.log(
+                    Severity::Error,
+                    format!("Could not read {}: {}", dir.display(), e),
+                );
+                self.unreadable = Some(e.to_string());
+                Vec::new()
+            }
+        }
+    }
+
+    fn cycle_sort(&mut self) -> Result<()> {
+        self.sort = self.sort.next();
+        self.reload();
+        Ok(())
+    }
+
+    fn toggle_hidden(&mut self) -> Result<()> {
+        self.filter.show_hidden = !self.filter.show_hidden;
+        self.reload();
+        Ok(())
+    }
+
+    /// Lets '.' pick the directory being shown instead of a file in it.
+    pub fn allow_dirs(mut self) -> Self {
+        self.pick_dirs = true;
+        self
+    }
+
+    /// Lets 'x' switch between sending the file as is and decoding its text
+    /// as hex bytes. The caller reads the flag once a file is picked.
+    pub fn with_hex_toggle(mut self, hex: Arc<AtomicBool>) -> Self {
+        self.hex = Some(hex);
+        self
+    }
+
+    fn toggle_hex(&mut self) -> Result<()> {
+        if let Some(ref hex) = self.hex {
+            hex.fetch_xor(true, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    fn pick_current_dir(&mut self) -> Result<()> {
+        if !self.pick_dirs {
+            return Ok(());
+        }
+        self.tx
+            .take()
+            .map(|t| {
+                t.send(self.cur_dir.clone())
+                    .map_err(|_| eyre!("Could not send directory"))
+            })
+            .ok_or_eyre("File handling error")
+            .flatten()
     }
 
     fn go_parent(&mut self) -> Result<()> {
-        self.update_dir(
-            self.cur_dir
-                .parent()
-                .ok_or(eyre!("Directory has no parent!"))?
-                .to_path_buf(),
-        )
+        let parent = self
+            .cur_dir
+            .parent()
+            .ok_or(eyre!("Directory has no parent!"))?
+            .to_path_buf();
+        self.update_dir(parent);
+        Ok(())
     }
 
     fn handle_file(&mut self) -> Result<()> {
-        let sel = self.list_state.selected().ok_or_eyre("No item selected")?;
-        let f = self.contents[sel].path();
+        let Some(sel) = self.list_state.selected() else {
+            return Ok(());
+        };
+        // the listing changed since this was selected
+        let Some(entry) = self.contents.get(sel) else {
+            self.list_state.select(None);
+            return Ok(());
+        };
+        let f = entry.path.clone();
         // specifically chooses to traverse symlinks
-        let m = std::fs::metadata(&f)?;
+        let m = std::fs::metadata(&f).map_err(|e| eyre!("{}: {}", f.display(), e))?;
         if m.is_dir() {
-            self.update_dir(f)?;
+            self.update_dir(f);
             Ok(())
         } else {
             self.tx
@@ -103,31 +353,143 @@ impl FileViewer {
         }
     }
 
-    fn update_dir(&mut self, path: PathBuf) -> Result<()> {
-        self.contents = path.read_dir()?.filter_map(|r| r.ok()).collect();
-        self.list_contents = self
-            .contents
-            .iter()
-            .map(|e| {
-                if e.metadata().unwrap().is_dir() {
-                    format!("🗀 {}", e.file_name().display())
-                } else {
-                    format!("{}", e.file_name().display())
+    // relative paths are taken from the directory being shown
+    fn go_to(&mut self, typed: &str) -> Result<()> {
+        let path = match typed.strip_prefix('~') {
+            Some(rest) if rest.is_empty() || rest.starts_with('/') => dirs::home_dir()
+                .ok_or_eyre("Could not find home directory")?
+                .join(rest.trim_start_matches('/')),
+            _ => PathBuf::from(typed),
+        };
+        let path = self.cur_dir.join(path);
+        let m = std::fs::metadata(&path).map_err(|e| eyre!("{}: {}", path.display(), e))?;
+        if m.is_dir() {
+            self.update_dir(path);
+            Ok(())
+        } else {
+            self.tx
+                .take()
+                .map(|t| t.send(path).map_err(|_| eyre!("Could not send file")))
+                .ok_or_eyre("File handling error")
+                .flatten()
+        }
+    }
+
+    fn listen_typed(&mut self, e: &GuiEvent) -> bool {
+        use GuiEvent::Crossterm;
+        use crossterm::event::{
+            Event::{Key, Paste},
+            KeyCode::{Backspace, Char, Enter, Esc},
+            KeyEvent, KeyModifiers,
+        };
+        let Some(input) = self.typed.as_mut() else {
+            return false;
+        };
+        match e {
+            Crossterm(Paste(s)) => input.extend(s.chars().filter(|c| !c.is_control())),
+            Crossterm(Key(KeyEvent {
+                code: Char(c),
+                modifiers,
+                ..
+            })) if modifiers.difference(KeyModifiers::SHIFT).is_empty() => input.push(*c),
+            // chorded keys aren't text, and mustn't fall through to the list's keys
+            Crossterm(Key(KeyEvent { code: Char(_), .. })) => {}
+            // backs out of typing, the viewer stays open
+            Crossterm(Key(KeyEvent { code: Esc, .. })) => self.typed = None,
+            Crossterm(Key(KeyEvent {
+                code: Backspace, ..
+            })) => {
+                if input.pop().is_none() {
+                    self.typed = None;
                 }
-            })
-            .collect();
+            }
+            Crossterm(Key(KeyEvent { code: Enter, .. })) => {
+                let typed = input.trim().to_string();
+                if typed.is_empty() {
+                    return true;
+                }
+                match self.go_to(&typed) {
+                    Ok(()) => self.typed = None,
+                    Err(e) => self
+                        .to_app
+                        .log(Severity::Error, format!("Could not open path: {}", e)),
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn update_dir(&mut self, path: PathBuf) {
+        self.contents = self.read_dir(&path);
+        self.list_state.select(None);
         self.cur_dir = path;
-        Ok(())
     }
 }
 
 impl Drawable for FileViewer {
-    fn draw(&mut self, area: ratatui::prelude::Rect, frame: &mut Frame) {
-        let text = self.list_contents.iter().map(Text::raw);
-        let list = List::new(text)
-            .highlight_style(Style::default().reversed())
-            .block(Block::bordered().title_bottom(Line::raw(&self.title).centered()));
-        frame.render_stateful_widget(list, area, &mut self.list_state);
+    fn draw(&mut self, area: ratatui::prelude::Rect, frame: &mut Frame, theme: &Theme) {
+        let mut block = Block::bordered()
+            .title(
+                Line::raw(format!("sorted by {} (s to change)", self.sort.label())).right_aligned(),
+            )
+            .title_bottom(Line::raw(&self.title).centered());
+        let hidden = if self.filter.show_hidden {
+            "h to hide dotfiles, g to type a path"
+        } else {
+            "h to show dotfiles, g to type a path"
+        };
+        block = block.title_bottom(Line::raw(hidden).left_aligned());
+        if !self.filter.extensions.is_empty() {
+            block = block.title(Line::raw(format!(
+                "*.{}",
+                self.filter.extensions.join(", *.")
+            )));
+        }
+        if self.pick_dirs {
+            block = block.title_bottom(Line::raw(". to pick this directory").right_aligned());
+        }
+        if let Some(ref hex) = self.hex {
+            let mode = if hex.load(Ordering::Relaxed) {
+                "x: decoding text as hex bytes"
+            } else {
+                "x: sending bytes as is"
+            };
+            block = block.title_bottom(Line::raw(mode).right_aligned());
+        }
+        if self.contents.is_empty() {
+            let reason = match self.unreadable {
+                Some(ref e) => format!("Cannot read this directory: {}", e),
+                None if self.filter.extensions.is_empty() => "Empty directory".into(),
+                None => "No matching files".into(),
+            };
+            let text = Text::from(vec![
+                Line::raw(reason),
+                Line::raw("Left goes to the parent directory"),
+            ])
+            .dim();
+            frame.render_widget(Paragraph::new(text).centered().block(block), area);
+        } else {
+            let rows = self.contents.iter().map(Entry::row);
+            let widths = [
+                Constraint::Fill(1),
+                Constraint::Length(8),
+                Constraint::Length(8),
+            ];
+            let table = Table::new(rows, widths)
+                .row_highlight_style(theme.selected())
+                .block(block);
+            frame.render_stateful_widget(table, area, &mut self.list_state);
+        }
+        if let Some(ref input) = self.typed {
+            render_text_entry(
+                input,
+                "Go to path (~ for home, Esc to cancel)",
+                area,
+                frame,
+                theme,
+            );
+        }
     }
 
     fn alive(&self) -> bool {
@@ -140,15 +502,40 @@ impl EventListener for FileViewer {
         use GuiEvent::Crossterm;
         use crossterm::event::{
             Event::Key,
-            KeyCode::{Down, Enter, Left, Right, Up},
+            KeyCode::{Char, Down, Enter, Left, Right, Up},
             KeyEvent,
         };
+        if self.listen_typed(e) {
+            return true;
+        }
         let r = match e {
             Crossterm(Key(KeyEvent { code: Left, .. })) => self.go_parent(),
             Crossterm(Key(KeyEvent {
                 code: Right | Enter,
                 ..
             })) => self.handle_file(),
+            Crossterm(Key(KeyEvent {
+                code: Char('.'), ..
+            })) => self.pick_current_dir(),
+            Crossterm(Key(KeyEvent {
+                code: Char('s'), ..
+            })) => self.cycle_sort(),
+            Crossterm(Key(KeyEvent {
+                code: Char('h'), ..
+            })) => self.toggle_hidden(),
+            Crossterm(Key(KeyEvent {
+                code: Char('x'), ..
+            })) if self.hex.is_some() => self.toggle_hex(),
+            Crossterm(Key(KeyEvent {
+                code: Char('g'), ..
+            })) => {
+                self.typed = Some(String::new());
+                Ok(())
+            }
+            // nothing to move between, and a selection would go stale
+            Crossterm(Key(KeyEvent {
+                code: Up | Down, ..
+            })) if self.contents.is_empty() => Ok(()),
             Crossterm(Key(KeyEvent { code: Up, .. })) => {
                 self.list_state.select_previous();
                 Ok(())
@@ -163,13 +550,30 @@ impl EventListener for FileViewer {
             self.to_app
                 .log(Severity::Error, format!("Could not select file: {}", e));
         }
-        return true;
+        true
     }
 }
 
+// (name, command) pairs offered by CmdInput when picking an upload command
+const TEMPLATES: &[(&str, &str)] = &[
+    ("st-flash", "st-flash --reset write #BIN# 0x8000000"),
+    ("esptool", "esptool.py write_flash 0x0 #BIN#"),
+    ("avrdude", "avrdude -p m328p -c arduino -U flash:w:#BIN#"),
+    ("picotool", "picotool load -x #BIN#"),
+    (
+        "openocd",
+        "openocd -f board.cfg -c \"program #BIN# verify reset exit\"",
+    ),
+];
+
 pub struct CmdInput {
     title: String,
     contents: String,
+    // previous commands, oldest first
+    history: Vec<String>,
+    history_pos: Option<usize>,
+    templates: Vec<(String, String)>,
+    template_state: ListState,
     tx: Option<oneshot::Sender<String>>,
 }
 
@@ -180,21 +584,95 @@ impl CmdInput {
             Self {
                 title,
                 contents: default,
+                history: Vec::new(),
+                history_pos: None,
+                templates: Vec::new(),
+                template_state: ListState::default(),
                 tx: Some(tx),
             },
             rx,
         )
     }
+
+    /// Lets Up/Down recall these, newest last.
+    pub fn with_history(mut self, history: &[String]) -> Self {
+        self.history = history.to_vec();
+        self
+    }
+
+    /// Offers the built-in upload templates plus `extra`, which replace
+    /// built-ins of the same name. Tab cycles through them.
+    pub fn with_templates(mut self, extra: &BTreeMap<String, String>) -> Self {
+        self.templates = TEMPLATES
+            .iter()
+            .filter(|(name, _)| !extra.contains_key(*name))
+            .map(|(name, cmd)| (name.to_string(), cmd.to_string()))
+            .chain(extra.iter().map(|(n, c)| (n.clone(), c.clone())))
+            .collect();
+        self
+    }
+
+    fn recall(&mut self, older: bool) {
+        if self.history.is_empty() {
+            return;
+        }
+        let last = self.history.len() - 1;
+        self.history_pos = match (self.history_pos, older) {
+            (None, true) => Some(last),
+            (None, false) => return,
+            (Some(i), true) => Some(i.saturating_sub(1)),
+            (Some(i), false) if i < last => Some(i + 1),
+            (Some(_), false) => None,
+        };
+        self.contents = self
+            .history_pos
+            .map(|i| self.history[i].clone())
+            .unwrap_or_default();
+    }
+
+    fn next_template(&mut self) {
+        if self.templates.is_empty() {
+            return;
+        }
+        let i = self
+            .template_state
+            .selected()
+            .map_or(0, |i| (i + 1) % self.templates.len());
+        self.template_state.select(Some(i));
+        self.contents = self.templates[i].1.clone();
+        self.history_pos = None;
+    }
 }
 
 impl Drawable for CmdInput {
-    fn draw(&mut self, area: ratatui::prelude::Rect, frame: &mut Frame) {
-        let cursor = Span::raw("█").style(Style::default().add_modifier(Modifier::SLOW_BLINK));
+    fn draw(&mut self, area: ratatui::prelude::Rect, frame: &mut Frame, theme: &Theme) {
+        let [input_area, list_area] = if self.templates.is_empty() {
+            [area, Rect::default()]
+        } else {
+            Layout::vertical([Constraint::Length(3), Constraint::Fill(1)]).areas(area)
+        };
+        let cursor = theme.cursor();
         let line = Line::from(vec![Span::raw(&self.contents), cursor]);
         let p = Paragraph::new(Text::from(line))
             .block(Block::bordered().title_bottom(Line::raw(&self.title).centered()))
             .left_aligned();
-        frame.render_widget(p, area);
+        frame.render_widget(p, input_area);
+        if !self.templates.is_empty() {
+            let items = self.templates.iter().map(|(name, cmd)| {
+                Line::from(vec![
+                    Span::raw(name).bold(),
+                    Span::raw(": "),
+                    Span::raw(cmd),
+                ])
+            });
+            let list =
+                List::new(items)
+                    .highlight_style(theme.selected())
+                    .block(Block::bordered().title_bottom(
+                        Line::raw("tab: next template, up/down: history").centered(),
+                    ));
+            frame.render_stateful_widget(list, list_area, &mut self.template_state);
+        }
     }
 
     fn alive(&self) -> bool {
@@ -206,11 +684,15 @@ impl EventListener for CmdInput {
     fn listen(&mut self, e: &GuiEvent) -> bool {
         use GuiEvent::Crossterm;
         use crossterm::event::{
-            Event::Key,
-            KeyCode::{Backspace, Char, Enter},
+            Event::{Key, Paste},
+            KeyCode::{Backspace, Char, Down, Enter, Tab, Up},
             KeyEvent, KeyModifiers,
         };
         match e {
+            Crossterm(Paste(s)) => {
+                self.contents.extend(s.chars().filter(|c| !c.is_control()));
+                true
+            }
             Crossterm(Key(KeyEvent {
                 code: Char(c),
                 modifiers,
@@ -219,6 +701,18 @@ impl EventListener for CmdInput {
                 self.contents.push(*c);
                 true
             }
+            Crossterm(Key(KeyEvent { code: Up, .. })) => {
+                self.recall(true);
+                true
+            }
+            Crossterm(Key(KeyEvent { code: Down, .. })) => {
+                self.recall(false);
+                true
+            }
+            Crossterm(Key(KeyEvent { code: Tab, .. })) => {
+                self.next_template();
+                true
+            }
             Crossterm(Key(KeyEvent {
                 code: Enter,
                 modifiers: KeyModifiers::NONE,
@@ -228,7 +722,7 @@ impl EventListener for CmdInput {
                     return false;
                 }
                 if let Some(tx) = self.tx.take() {
-                    tx.send(take(&mut self.contents));
+                    _ = tx.send(take(&mut self.contents));
                 }
                 true
             }
@@ -0,0 +1,206 @@
+use std::str::FromStr;
+
+use eyre::{Result, eyre};
+
+/// Where one received line ends and the next begins, picked with
+/// `--framing`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Framing {
+    #[default]
+    Lf,
+    Cr,
+    Crlf,
+    // any ASCII byte, e.g. a protocol's end of frame sentinel
+    Byte(u8),
+    // every this many characters, for protocols without a terminator
+    Fixed(usize),
+}
+
+impl FromStr for Framing {
+    type Err = eyre::Report;
+
+    // lf, cr, crlf, byte:N and fixed:N, N in decimal or 0x hex
+    fn from_str(s: &str) -> Result<Self> {
+        let number = |n: &str| match n.strip_prefix("0x") {
+            Some(hex) => usize::from_str_radix(hex, 16).ok(),
+            None => n.parse().ok(),
+        };
+        let s = s.to_ascii_lowercase();
+        match s.split_once(':') {
+            None if s == "lf" => Ok(Framing::Lf),
+            None if s == "cr" => Ok(Framing::Cr),
+            None if s == "crlf" => Ok(Framing::Crlf),
+            Some(("byte", n)) => match number(n) {
+                Some(b @ 0..0x80) => Ok(Framing::Byte(b as u8)),
+                _ => Err(eyre!("byte:N needs an ASCII byte, e.g. byte:0x7e")),
+            },
+            Some(("fixed", n)) => match number(n) {
+                Some(len @ 1..) => Ok(Framing::Fixed(len)),
+                _ => Err(eyre!("fixed:N needs a length above 0, e.g. fixed:16")),
+            },
+            _ => Err(eyre!(
+                "Unknown framing {:?}, expected lf, cr, crlf, byte:N or fixed:N",
+                s
+            )),
+        }
+    }
+}
+
+/// Cuts received text into lines by a framing. A line can arrive over
+/// several reads, so what is left of it carries over to the next call.
+#[derive(Debug, Default)]
+pub struct LineSplitter {
+    framing: Framing,
+    // characters so far in the current line, for fixed framing
+    len: usize,
+    // the last text ended with \r, which may be the start of a CRLF
+    pending_cr: bool,
+}
+
+impl LineSplitter {
+    pub fn new(framing: Framing) -> Self {
+        Self {
+            framing,
+            ..Self::default()
+        }
+    }
+
+    /// Pieces of `text` with whether each one ends a line. Terminators are
+    /// left out.
+    pub fn split<'a>(&mut self, text: &'a str) -> Vec<(&'a str, bool)> {
+        let mut pieces = Vec::new();
+        let mut rest = text;
+        match self.framing {
+            Framing::Lf => split_on(rest, '\n', &mut pieces),
+            Framing::Cr => split_on(rest, '\r', &mut pieces),
+            Framing::Byte(b) => split_on(rest, b as char, &mut pieces),
+            Framing::Crlf => {
+                // an empty read leaves the CR waiting for the next one
+                if self.pending_cr && !rest.is_empty() {
+                    self.pending_cr = false;
+                    match rest.strip_prefix('\n') {
+                        Some(r) => {
+                            pieces.push(("", true));
+                            rest = r;
+                        }
+                        None => pieces.push(("\r", false)),
+                    }
+                }
+                while let Some(i) = rest.find("\r\n") {
+                    pieces.push((&rest[..i], true));
+                    rest = &rest[i + 2..];
+                }
+                if let Some(r) = rest.strip_suffix('\r') {
+                    self.pending_cr = true;
+                    rest = r;
+                }
+                if !rest.is_empty() {
+                    pieces.push((rest, false));
+                }
+            }
+            Framing::Fixed(len) => {
+                while !rest.is_empty() {
+                    let room = len - self.len;
+                    let cut = rest.char_indices().nth(room).map_or(rest.len(), |(i, _)| i);
+                    let (now, later) = rest.split_at(cut);
+                    self.len += now.chars().count();
+                    let done = self.len == len;
+                    if done {
+                        self.len = 0;
+                    }
+                    pieces.push((now, done));
+                    rest = later;
+                }
+            }
+        }
+        pieces
+    }
+}
+
+fn split_on<'a>(text: &'a str, end: char, pieces: &mut Vec<(&'a str, bool)>) {
+    let mut rest = text;
+    while let Some(i) = rest.find(end) {
+        pieces.push((&rest[..i], true));
+        rest = &rest[i + end.len_utf8()..];
+    }
+    if !rest.is_empty() {
+        pieces.push((rest, false));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::take;
+
+    use super::*;
+
+    // feeds each read in turn and collects the finished lines
+    fn lines(framing: Framing, reads: &[&str]) -> Vec<String> {
+        let mut splitter = LineSplitter::new(framing);
+        let mut done = Vec::new();
+        let mut current = String::new();
+        for read in reads {
+            for (piece, ends) in splitter.split(read) {
+                current.push_str(piece);
+                if ends {
+                    done.push(take(&mut current));
+                }
+            }
+        }
+        done
+    }
+
+    #[test]
+    fn lf() {
+        assert_eq!(lines(Framing::Lf, &["a\nb", "c\n\nd"]), ["a", "bc", ""]);
+    }
+
+    #[test]
+    fn cr() {
+        assert_eq!(lines(Framing::Cr, &["a\rb\n", "c\r"]), ["a", "b\nc"]);
+    }
+
+    #[test]
+    fn crlf() {
+        assert_eq!(
+            lines(Framing::Crlf, &["a\r\nb\rc\r\n", "d\r", "\ne"]),
+            ["a", "b\rc", "d"]
+        );
+    }
+
+    #[test]
+    fn crlf_lone_cr_is_kept() {
+        assert_eq!(lines(Framing::Crlf, &["a\r", "b\r\n"]), ["a\rb"]);
+    }
+
+    #[test]
+    fn crlf_cr_survives_an_empty_read() {
+        assert_eq!(lines(Framing::Crlf, &["a\r", "", "\nb\r\n"]), ["a", "b"]);
+        assert_eq!(lines(Framing::Crlf, &["a\r", "", "b\r\n"]), ["a\rb"]);
+    }
+
+    #[test]
+    fn byte() {
+        assert_eq!(
+            lines(Framing::Byte(0x7e), &["one~tw", "o~three"]),
+            ["one", "two"]
+        );
+    }
+
+    #[test]
+    fn fixed() {
+        assert_eq!(
+            lines(Framing::Fixed(3), &["abcd", "éfgh", "i"]),
+            ["abc", "déf", "ghi"]
+        );
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!("CRLF".parse::<Framing>().unwrap(), Framing::Crlf);
+        assert_eq!("byte:0x7e".parse::<Framing>().unwrap(), Framing::Byte(0x7e));
+        assert_eq!("fixed:16".parse::<Framing>().unwrap(), Framing::Fixed(16));
+        assert!("byte:200".parse::<Framing>().is_err());
+        assert!("fixed:0".parse::<Framing>().is_err());
+    }
+}
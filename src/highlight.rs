@@ -0,0 +1,110 @@
+use std::ops::Range;
+
+use eyre::{Result, eyre};
+use ratatui::{
+    style::{Color, Style},
+    text::{Line, Span},
+};
+use regex::Regex;
+use serde::Deserialize;
+
+/// A `[[highlight]]` entry in the config file. Exactly one of `text` or
+/// `regex` is given.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct HighlightConfig {
+    text: Option<String>,
+    regex: Option<String>,
+    color: String,
+    // color the whole line instead of just the match
+    #[serde(default)]
+    whole_line: bool,
+}
+
+/// Colors parts of received lines matching a pattern.
+#[derive(Clone, Debug)]
+pub struct HighlightRule {
+    pattern: Regex,
+    color: Color,
+    whole_line: bool,
+}
+
+impl TryFrom<HighlightConfig> for HighlightRule {
+    type Error = eyre::Report;
+
+    fn try_from(c: HighlightConfig) -> Result<Self> {
+        let pattern = pattern(c.text, c.regex)?;
+        let color = c
+            .color
+            .parse()
+            .map_err(|_| eyre!("Unknown color {:?}", c.color))?;
+        Ok(Self {
+            pattern,
+            color,
+            whole_line: c.whole_line,
+        })
+    }
+}
+
+/// The regex for a config entry that gives either literal `text` or a
+/// `regex`, but not both.
+pub fn pattern(text: Option<String>, regex: Option<String>) -> Result<Regex> {
+    match (text, regex) {
+        (Some(t), None) => Ok(Regex::new(&regex::escape(&t))?),
+        (None, Some(r)) => Ok(Regex::new(&r)?),
+        _ => Err(eyre!("Needs one of text or regex")),
+    }
+}
+
+/// Applies every matching rule to the line. Later rules win where they
+/// overlap, and colors from the device are replaced only where a rule hits.
+pub fn highlight(rules: &[HighlightRule], line: &Line) -> Line<'static> {
+    let text = line_text(line);
+    let mut ranges = Vec::new();
+    for rule in rules {
+        let style = Style::new().fg(rule.color);
+        if rule.whole_line {
+            if rule.pattern.is_match(&text) {
+                ranges.push((0..text.len(), style));
+            }
+        } else {
+            ranges.extend(rule.pattern.find_iter(&text).map(|m| (m.range(), style)));
+        }
+    }
+    restyle(line, &ranges)
+}
+
+pub fn line_text(line: &Line) -> String {
+    line.spans.iter().map(|s| s.content.as_ref()).collect()
+}
+
+/// Splits spans at the edges of each byte range of the line's text and
+/// patches the range's style onto the pieces inside it.
+pub fn restyle(line: &Line, ranges: &[(Range<usize>, Style)]) -> Line<'static> {
+    let mut out = Line::default().style(line.style);
+    let mut offset = 0;
+    for span in &line.spans {
+        let content = span.content.as_ref();
+        let end = offset + content.len();
+        let mut cuts = vec![0, content.len()];
+        for (r, _) in ranges
+            .iter()
+            .filter(|(r, _)| r.start < end && r.end > offset)
+        {
+            cuts.push(r.start.saturating_sub(offset).min(content.len()));
+            cuts.push(r.end.saturating_sub(offset).min(content.len()));
+        }
+        cuts.sort_unstable();
+        cuts.dedup();
+        for w in cuts.windows(2) {
+            let start = offset + w[0];
+            let style = ranges
+                .iter()
+                .filter(|(r, _)| r.start <= start && start < r.end)
+                .fold(span.style, |s, (_, patch)| s.patch(*patch));
+            out.push_span(Span::styled(content[w[0]..w[1]].to_string(), style));
+        }
+        offset = end;
+    }
+    out
+}
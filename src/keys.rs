@@ -0,0 +1,489 @@
+use std::{collections::HashMap, fmt::Display, str::FromStr};
+
+use clap::ValueEnum;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use eyre::{Result, eyre};
+use serde::Deserialize;
+
+use crate::ui::LineEnding;
+
+/// Commands that can be bound to a key. Navigation and editing keys (esc,
+/// enter, arrows, page keys, backspace) are fixed and not listed here.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+    Help,
+    Quit,
+    FindDevice,
+    NewTab,
+    CloseTab,
+    NextTab,
+    PrevTab,
+    Disconnect,
+    Reconnect,
+    Upload,
+    SendFile,
+    XmodemSend,
+    UploadOutput,
+    ToggleDtr,
+    ToggleRts,
+    ResetPulse,
+    ToggleHexView,
+    ToggleHexInput,
+    ToggleRaw,
+    CycleLineEnding,
+    ToggleCapture,
+    ClearScrollback,
+    CycleLogLevel,
+    ExportScrollback,
+    // was a pause that froze the output, which following replaced
+    #[serde(alias = "toggle-pause")]
+    ToggleFollow,
+    CycleDirection,
+    ToggleControls,
+    ToggleWrap,
+    Search,
+    Select,
+    ToggleHeartbeat,
+    InsertMarker,
+    PortSettings,
+    SendAndWait,
+}
+
+impl Action {
+    pub const ALL: [Action; 34] = [
+        Action::Help,
+        Action::Quit,
+        Action::FindDevice,
+        Action::NewTab,
+        Action::CloseTab,
+        Action::NextTab,
+        Action::PrevTab,
+        Action::Disconnect,
+        Action::Reconnect,
+        Action::Upload,
+        Action::SendFile,
+        Action::XmodemSend,
+        Action::UploadOutput,
+        Action::ToggleDtr,
+        Action::ToggleRts,
+        Action::ResetPulse,
+        Action::ToggleHexView,
+        Action::ToggleHexInput,
+        Action::ToggleRaw,
+        Action::CycleLineEnding,
+        Action::ToggleCapture,
+        Action::ClearScrollback,
+        Action::CycleLogLevel,
+        Action::ExportScrollback,
+        Action::ToggleFollow,
+        Action::CycleDirection,
+        Action::ToggleControls,
+        Action::ToggleWrap,
+        Action::Search,
+        Action::Select,
+        Action::ToggleHeartbeat,
+        Action::InsertMarker,
+        Action::PortSettings,
+        Action::SendAndWait,
+    ];
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Action::Help => "Show/hide this help",
+            Action::Quit => "Exit application",
+            Action::FindDevice => "Find and connect a device",
+            Action::NewTab => "Open a device in a new tab",
+            Action::CloseTab => "Close the current tab",
+            Action::NextTab => "Next tab",
+            Action::PrevTab => "Previous tab",
+            Action::Disconnect => "Disconnect the device",
+            Action::Reconnect => "Reconnect the last device",
+            Action::Upload => "Upload file",
+            Action::SendFile => "Send file",
+            Action::XmodemSend => "Send file with XMODEM",
+            Action::UploadOutput => "Upload output",
+            Action::ToggleDtr => "Toggle DTR",
+            Action::ToggleRts => "Toggle RTS",
+            Action::ResetPulse => "Reset the board",
+            Action::ToggleHexView => "Toggle hex view",
+            Action::ToggleHexInput => "Toggle hex input",
+            Action::ToggleRaw => "Send every key as it is typed",
+            Action::CycleLineEnding => "Cycle line ending",
+            Action::ToggleCapture => "Start/stop capture",
+            Action::ClearScrollback => "Clear scrollback",
+            Action::CycleLogLevel => "Show all/info/error log messages",
+            Action::ExportScrollback => "Save scrollback to a file",
+            Action::ToggleFollow => "Follow new output on/off",
+            Action::CycleDirection => "Show all/received/sent lines",
+            Action::ToggleControls => "Show control characters",
+            Action::ToggleWrap => "Wrap long lines",
+            Action::Search => "Search (n/N step, alt+c case)",
+            Action::Select => "Select lines (v mark, y copy)",
+            Action::ToggleHeartbeat => "Pause/resume the heartbeat",
+            Action::InsertMarker => "Insert a labelled marker",
+            Action::PortSettings => "Change settings of the open port",
+            Action::SendAndWait => "Send the input and log the response",
+        }
+    }
+
+    fn default_key(self) -> &'static str {
+        match self {
+            Action::Help => "alt+?",
+            Action::Quit => "ctrl+c",
+            Action::FindDevice => "ctrl+f",
+            Action::NewTab => "alt+t",
+            Action::CloseTab => "alt+q",
+            Action::NextTab => "alt+.",
+            Action::PrevTab => "alt+,",
+            Action::Disconnect => "alt+x",
+            Action::Reconnect => "alt+r",
+            Action::Upload => "ctrl+u",
+            Action::SendFile => "ctrl+t",
+            Action::XmodemSend => "ctrl+x",
+            Action::UploadOutput => "ctrl+o",
+            Action::ToggleDtr => "ctrl+d",
+            Action::ToggleRts => "ctrl+r",
+            Action::ResetPulse => "alt+b",
+            Action::ToggleHexView => "ctrl+g",
+            Action::ToggleHexInput => "ctrl+b",
+            Action::ToggleRaw => "alt+i",
+            Action::CycleLineEnding => "ctrl+e",
+            Action::ToggleCapture => "ctrl+s",
+            Action::ClearScrollback => "ctrl+l",
+            Action::CycleLogLevel => "alt+l",
+            Action::ExportScrollback => "ctrl+w",
+            Action::ToggleFollow => "ctrl+p",
+            Action::CycleDirection => "alt+d",
+            Action::ToggleControls => "alt+v",
+            Action::ToggleWrap => "alt+w",
+            Action::Search => "alt+/",
+            Action::Select => "alt+s",
+            Action::ToggleHeartbeat => "alt+h",
+            Action::InsertMarker => "alt+m",
+            Action::PortSettings => "alt+p",
+            Action::SendAndWait => "alt+a",
+        }
+    }
+}
+
+// keys with a fixed meaning, shown in the help alongside the bindable ones
+const FIXED: &[(&str, &str)] = &[
+    ("esc", "Close popup/exit application"),
+    ("enter", "Send line"),
+    ("up/down", "Input history"),
+    ("pgup/pgdn", "Scroll terminal"),
+    ("home/end", "Oldest/newest output"),
+    ("alt+1..9", "Switch to tab"),
+];
+
+/// A key plus modifiers, written like `ctrl+p`, `alt+/` or `f2`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct KeyCombo {
+    modifiers: KeyModifiers,
+    code: KeyCode,
+}
+
+impl KeyCombo {
+    fn from_event(e: &KeyEvent) -> Self {
+        let mut modifiers = e.modifiers;
+        // shift is already reflected in the character itself
+        if let KeyCode::Char(_) = e.code {
+            modifiers.remove(KeyModifiers::SHIFT);
+        }
+        Self {
+            modifiers,
+            code: e.code,
+        }
+    }
+
+    // plain characters are typed into the input line, alt+1..9 pick a tab,
+    // and the navigation keys are taken. Many terminals send ctrl+h, ctrl+i
+    // and ctrl+m for backspace, tab and enter, so those can't be told apart.
+    fn is_reserved(&self) -> bool {
+        use KeyCode::{Backspace, Down, End, Enter, Esc, Home, PageDown, PageUp, Up};
+        match self.code {
+            KeyCode::Char('1'..='9') if self.modifiers == KeyModifiers::ALT => true,
+            KeyCode::Char('h' | 'i' | 'm') if self.modifiers == KeyModifiers::CONTROL => true,
+            KeyCode::Char(_) => self.modifiers.is_empty(),
+            Esc => true,
+            Backspace | Enter | Up | Down | PageUp | PageDown | Home | End => {
+                self.modifiers.is_empty()
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for KeyCombo {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (mods, key) = match s.rsplit_once('+') {
+            // a lone "+" or something like "ctrl++"
+            Some((m, "")) => (m.strip_suffix('+').unwrap_or(m), "+"),
+            Some((m, k)) => (m, k),
+            None => ("", s),
+        };
+        let mut modifiers = KeyModifiers::NONE;
+        for m in mods.split('+').filter(|m| !m.is_empty()) {
+            modifiers |= match m.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return Err(eyre!("Unknown modifier {:?} in {:?}", m, s)),
+            };
+        }
+        let mut chars = key.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match key.to_ascii_lowercase().as_str() {
+                "esc" => KeyCode::Esc,
+                "enter" => KeyCode::Enter,
+                "tab" => KeyCode::Tab,
+                "space" => KeyCode::Char(' '),
+                "backspace" => KeyCode::Backspace,
+                "delete" => KeyCode::Delete,
+                "insert" => KeyCode::Insert,
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pgup" => KeyCode::PageUp,
+                "pgdn" => KeyCode::PageDown,
+                k => match k.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+                    Some(n @ 1..=12) => KeyCode::F(n),
+                    _ => return Err(eyre!("Unknown key {:?} in {:?}", key, s)),
+                },
+            },
+        };
+        if let KeyCode::Char(_) = code {
+            modifiers.remove(KeyModifiers::SHIFT);
+        }
+        Ok(Self { modifiers, code })
+    }
+}
+
+impl Display for KeyCombo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "ctrl+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "alt+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            write!(f, "shift+")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "space"),
+            KeyCode::Char(c) => write!(f, "{c}"),
+            KeyCode::F(n) => write!(f, "f{n}"),
+            KeyCode::PageUp => write!(f, "pgup"),
+            KeyCode::PageDown => write!(f, "pgdn"),
+            code => write!(f, "{}", code.to_string().to_lowercase()),
+        }
+    }
+}
+
+/// Which key triggers each action.
+#[derive(Clone, Debug)]
+pub struct KeyMap {
+    keys: HashMap<KeyCombo, Action>,
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        Self::with_overrides(HashMap::new()).expect("default keybindings conflict")
+    }
+}
+
+impl KeyMap {
+    /// Defaults with the given actions rebound. Fails if two actions end up
+    /// on the same key or a key is one seterm already uses for navigation.
+    pub fn with_overrides(overrides: HashMap<Action, String>) -> Result<Self> {
+        let mut keys = HashMap::new();
+        for action in Action::ALL {
+            let key = overrides
+                .get(&action)
+                .map(String::as_str)
+                .unwrap_or(action.default_key());
+            let combo: KeyCombo = key.parse()?;
+            if combo.is_reserved() {
+                return Err(eyre!("{} cannot be rebound", combo));
+            }
+            if let Some(other) = keys.insert(combo, action) {
+                return Err(eyre!(
+                    "{} is bound to both {:?} and {:?}",
+                    combo,
+                    other,
+                    action
+                ));
+            }
+        }
+        Ok(Self { keys })
+    }
+
+    pub fn is_bound(&self, code: KeyCode) -> bool {
+        self.keys.contains_key(&KeyCombo {
+            modifiers: KeyModifiers::NONE,
+            code,
+        })
+    }
+
+    pub fn lookup(&self, e: &KeyEvent) -> Option<Action> {
+        self.keys.get(&KeyCombo::from_event(e)).copied()
+    }
+
+    /// Key and description pairs for the help overlay.
+    pub fn help_rows(&self) -> Vec<(String, &'static str)> {
+        let mut rows: Vec<_> = FIXED.iter().map(|(k, d)| (k.to_string(), *d)).collect();
+        for action in Action::ALL {
+            if let Some((combo, _)) = self.keys.iter().find(|(_, a)| **a == action) {
+                rows.push((combo.to_string(), action.description()));
+            }
+        }
+        rows
+    }
+}
+
+/// What a terminal would send for the key, for raw mode. None for keys
+/// with no usual encoding.
+pub fn encode_key(e: &KeyEvent) -> Option<String> {
+    const ESC: char = '\x1b';
+    let ctrl = e.modifiers.contains(KeyModifiers::CONTROL);
+    let alt = e.modifiers.contains(KeyModifiers::ALT);
+    let key = match e.code {
+        KeyCode::Char(c) if ctrl => match c.to_ascii_uppercase() {
+            c @ ('@'..='_') => char::from(c as u8 - b'@'),
+            ' ' => '\0',
+            '?' => '\x7f',
+            _ => return None,
+        }
+        .to_string(),
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::Enter => "\r".into(),
+        KeyCode::Tab => "\t".into(),
+        KeyCode::BackTab => format!("{ESC}[Z"),
+        KeyCode::Backspace => "\x7f".into(),
+        KeyCode::Esc => ESC.to_string(),
+        KeyCode::Up => format!("{ESC}[A"),
+        KeyCode::Down => format!("{ESC}[B"),
+        KeyCode::Right => format!("{ESC}[C"),
+        KeyCode::Left => format!("{ESC}[D"),
+        KeyCode::Home => format!("{ESC}[H"),
+        KeyCode::End => format!("{ESC}[F"),
+        KeyCode::Insert => format!("{ESC}[2~"),
+        KeyCode::Delete => format!("{ESC}[3~"),
+        KeyCode::PageUp => format!("{ESC}[5~"),
+        KeyCode::PageDown => format!("{ESC}[6~"),
+        KeyCode::F(n @ 1..=4) => format!("{ESC}O{}", char::from(b'P' + n - 1)),
+        KeyCode::F(n @ 5..=12) => {
+            let code = [15, 17, 18, 19, 20, 21, 23, 24][n as usize - 5];
+            format!("{ESC}[{code}~")
+        }
+        _ => return None,
+    };
+    // alt is sent as a leading escape
+    Some(if alt { format!("{ESC}{key}") } else { key })
+}
+
+/// A `[macros]` entry, either just the text or a table that also picks the
+/// line ending.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum MacroConfig {
+    Text(String),
+    #[serde(rename_all = "kebab-case")]
+    Full {
+        text: String,
+        line_ending: Option<String>,
+    },
+}
+
+/// Bytes sent when a function key is pressed.
+#[derive(Clone, Debug)]
+pub struct Macro {
+    pub bytes: Vec<u8>,
+    // the current line ending is used if this is not set
+    pub line_ending: Option<LineEnding>,
+}
+
+impl TryFrom<MacroConfig> for Macro {
+    type Error = eyre::Report;
+
+    fn try_from(c: MacroConfig) -> Result<Self> {
+        let (text, line_ending) = match c {
+            MacroConfig::Text(t) => (t, None),
+            MacroConfig::Full { text, line_ending } => (text, line_ending),
+        };
+        let line_ending = line_ending
+            .map(|e| LineEnding::from_str(&e, true).map_err(|e| eyre!(e)))
+            .transpose()?;
+        Ok(Self {
+            bytes: unescape(&text)?,
+            line_ending,
+        })
+    }
+}
+
+/// Function key number for names like `f5`.
+pub fn parse_function_key(name: &str) -> Result<u8> {
+    match name.to_ascii_lowercase().strip_prefix('f').map(str::parse) {
+        Some(Ok(n @ 1..=12)) => Ok(n),
+        _ => Err(eyre!(
+            "Macros can only be bound to f1 to f12, not {:?}",
+            name
+        )),
+    }
+}
+
+// \r, \n, \t, \e, \0, \\ and \xNN
+pub fn unescape(text: &str) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        let b = match chars.next() {
+            Some('r') => b'\r',
+            Some('n') => b'\n',
+            Some('t') => b'\t',
+            Some('e') => 0x1b,
+            Some('0') => 0,
+            Some('\\') => b'\\',
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                u8::from_str_radix(&hex, 16)
+                    .map_err(|_| eyre!("Invalid escape \\x{} in {:?}", hex, text))?
+            }
+            Some(c) => return Err(eyre!("Unknown escape \\{} in {:?}", c, text)),
+            None => return Err(eyre!("Trailing backslash in {:?}", text)),
+        };
+        out.push(b);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_terminals_send_for_editing_are_refused() {
+        let map = KeyMap::default();
+        assert_eq!(
+            map.lookup(&KeyEvent::new(KeyCode::Char('g'), KeyModifiers::CONTROL)),
+            Some(Action::ToggleHexView)
+        );
+        for key in ["ctrl+h", "ctrl+i", "ctrl+m"] {
+            let overrides = HashMap::from([(Action::ToggleHexView, key.to_string())]);
+            assert!(KeyMap::with_overrides(overrides).is_err(), "{key}");
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// The diagnostic log at `LOG_PATH`. Older logs are moved to `LOG_PATH.1`,
+/// `LOG_PATH.2` and so on, once when the program starts and again whenever
+/// the current one grows past the size limit. With nothing to keep and no
+/// limit this is a single file, as before.
+#[derive(Debug)]
+pub struct LogFile {
+    path: PathBuf,
+    // 0 means no limit
+    max_size: u64,
+    keep: usize,
+    file: File,
+    written: u64,
+}
+
+impl LogFile {
+    pub fn create(path: PathBuf, max_size: u64, keep: usize) -> io::Result<Self> {
+        if keep > 0 && path.exists() {
+            rotate(&path, keep)?;
+        }
+        Ok(Self {
+            file: File::create(&path)?,
+            path,
+            max_size,
+            keep,
+            written: 0,
+        })
+    }
+}
+
+// path.keep is dropped, and every other file moves up one
+fn rotate(path: &Path, keep: usize) -> io::Result<()> {
+    let numbered = |n: usize| {
+        let mut p = path.as_os_str().to_owned();
+        p.push(format!(".{}", n));
+        PathBuf::from(p)
+    };
+    for n in (1..keep).rev() {
+        let from = numbered(n);
+        if from.exists() {
+            std::fs::rename(from, numbered(n + 1))?;
+        }
+    }
+    std::fs::rename(path, numbered(1))
+}
+
+impl Write for LogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // a record is never split across files
+        if self.max_size > 0 && self.written > 0 && self.written + buf.len() as u64 > self.max_size
+        {
+            self.file.flush()?;
+            if self.keep > 0 {
+                rotate(&self.path, self.keep)?;
+            }
+            self.file = File::create(&self.path)?;
+            self.written = 0;
+        }
+        let n = self.file.write(buf)?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
@@ -1,40 +1,97 @@
-use clap::Parser;
-use eyre::eyre;
+use std::{io::stdout, sync::Mutex};
+
+use crossterm::{
+    event::{DisableBracketedPaste, DisableFocusChange, EnableBracketedPaste, EnableFocusChange},
+    execute,
+};
 use tracing_subscriber::{EnvFilter, fmt};
 
-use crate::{app::App, cli::CliConfiguration};
+use crate::{
+    app::App,
+    cli::{CliConfiguration, Command},
+    logfile::LogFile,
+};
 
+pub mod ansi;
 pub mod app;
 pub mod cli;
+pub mod clipboard;
 pub mod device_finder;
+pub mod emit;
+pub mod error;
 pub mod event;
 pub mod fileviewer;
-pub mod ui;
+pub mod framing;
+pub mod highlight;
+pub mod keys;
+pub mod logfile;
 pub mod notif;
+pub mod pipe;
+pub mod replay;
+pub mod state;
+pub mod theme;
+pub mod trigger;
+pub mod ui;
+pub mod xmodem;
 
 #[tokio::main]
 async fn main() -> color_eyre::Result<()> {
+    color_eyre::install()?;
+    let (flags, matches) = CliConfiguration::parse_flags();
+
+    // set up first so reading the config file is logged too
     if let Some(path) = std::env::var_os("LOG_PATH") {
-        let log = std::fs::File::create(path)?;
+        let log = LogFile::create(path.into(), flags.log_max_size, flags.log_keep)?;
         fmt::Subscriber::builder()
             .with_env_filter(EnvFilter::from_default_env())
-            .with_writer(log)
+            .with_writer(Mutex::new(log))
             .compact()
             .init();
     }
+    let args = flags.load(&matches)?;
+    match args.command {
+        Some(Command::Ports { json, all }) => return device_finder::print_ports(json, all),
+        Some(Command::CheckUpload { file, cmd }) => return event::print_upload_check(&file, &cmd),
+        None => {}
+    }
+    if args.pipe {
+        let capture = event::Capture::new(args.terminal.capture_format);
+        if let Some(path) = args.capture {
+            capture.start(path)?;
+        }
+        return pipe::run(args.device, args.terminal.line_ending, capture).await;
+    }
 
-    let args = CliConfiguration::parse();
+    // opened before the terminal is taken over so a bad target is reported plainly
+    let sink = args
+        .emit_json
+        .as_deref()
+        .map(emit::JsonSink::open)
+        .transpose()?;
 
-    color_eyre::install()?;
     let terminal = ratatui::init();
-    let result = App::new()
+    // paste arrives as a single event instead of a stream of key presses
+    execute!(stdout(), EnableBracketedPaste)?;
+    // focus changes are only reported when something uses them
+    let focus = args.terminal.dim_unfocused;
+    if focus {
+        execute!(stdout(), EnableFocusChange)?;
+    }
+    let result = App::new(args.terminal, args.watch)
+        .emit_json(sink)
+        .replay(args.replay)
         .run(
             terminal,
             args.device,
-            args.default_cmd.unwrap_or_default(),
-            None,
+            args.default_cmd,
+            args.watch_path,
+            args.capture,
         )
         .await;
+    _ = execute!(stdout(), DisableBracketedPaste);
+    if focus {
+        _ = execute!(stdout(), DisableFocusChange);
+    }
     ratatui::restore();
     result
 }
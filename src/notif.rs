@@ -1,6 +1,19 @@
-use ratatui::widgets::{Block, Clear, Paragraph};
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
 
-use crate::event::{Drawable, EventListener};
+use ratatui::{
+    layout::Constraint,
+    style::{Color, Style, Stylize},
+    text::{Line, Text},
+    widgets::{Block, Clear, Paragraph, Row, Table},
+};
+
+use crate::{
+    event::{AppEvent, Drawable, EventListener, GuiEvent, Messenger, UploadRun},
+    theme::Theme,
+};
 
 pub struct Notification {
     content: String,
@@ -17,7 +30,7 @@ impl Drawable for Notification {
         true
     }
 
-    fn draw(&mut self, area: ratatui::prelude::Rect, frame: &mut ratatui::Frame) {
+    fn draw(&mut self, area: ratatui::prelude::Rect, frame: &mut ratatui::Frame, _theme: &Theme) {
         frame.render_widget(Clear, area);
         let p = Paragraph::new(self.content.clone())
             .block(Block::bordered())
@@ -25,10 +38,211 @@ impl Drawable for Notification {
             .wrap(ratatui::widgets::Wrap { trim: false });
         frame.render_widget(p, area);
     }
+
+    // just big enough for the message, wrapping lines past NOTIFICATION_WIDTH
+    fn preferred_size(&self) -> Option<(u16, u16)> {
+        let widths: Vec<_> = self.content.lines().map(|l| Line::raw(l).width()).collect();
+        let inner = widths
+            .iter()
+            .copied()
+            .max()
+            .unwrap_or(0)
+            .clamp(20, NOTIFICATION_WIDTH);
+        let rows: usize = widths.iter().map(|w| w.div_ceil(inner).max(1)).sum();
+        Some((inner as u16 + 4, rows.max(1) as u16 + 2))
+    }
 }
 
+// widest a notification gets before its lines wrap, not counting the border
+const NOTIFICATION_WIDTH: usize = 76;
+
 impl EventListener for Notification {
     fn listen(&mut self, _: &crate::event::GuiEvent) -> bool {
         false
     }
 }
+
+/// Scrollable view of upload command output, with left/right stepping
+/// through the last few runs.
+pub struct UploadOutput {
+    runs: Vec<UploadRun>,
+    index: usize,
+    scroll: u16,
+}
+
+impl UploadOutput {
+    pub fn new(runs: Vec<UploadRun>) -> Self {
+        Self {
+            index: runs.len().saturating_sub(1),
+            runs,
+            scroll: 0,
+        }
+    }
+
+    fn step(&mut self, newer: bool) {
+        self.index = if newer {
+            (self.index + 1).min(self.runs.len().saturating_sub(1))
+        } else {
+            self.index.saturating_sub(1)
+        };
+        self.scroll = 0;
+    }
+}
+
+impl Drawable for UploadOutput {
+    fn alive(&self) -> bool {
+        true
+    }
+
+    fn draw(&mut self, area: ratatui::prelude::Rect, frame: &mut ratatui::Frame, theme: &Theme) {
+        frame.render_widget(Clear, area);
+        let Some(run) = self.runs.get(self.index) else {
+            return;
+        };
+        let color = if run.success {
+            Color::Green
+        } else {
+            Color::Red
+        };
+        let code = run.code.map(|c| c.to_string()).unwrap_or("killed".into());
+        let block = Block::bordered()
+            .border_style(Style::new().fg(color))
+            .title(format!(
+                "Upload {}/{}: {}",
+                self.index + 1,
+                self.runs.len(),
+                run.file.display()
+            ))
+            .title_bottom(Line::raw(format!("exit {}", code)).left_aligned())
+            .title_bottom(Line::raw("←/→ other runs, ↑/↓ scroll").right_aligned());
+        let mut text = Text::raw(run.stdout.as_str());
+        if !run.stderr.is_empty() {
+            text.push_line(Line::raw("--- stderr ---").dim());
+            for line in run.stderr.lines() {
+                text.push_line(Line::raw(line).fg(theme.error));
+            }
+        }
+        let p = Paragraph::new(text)
+            .block(block)
+            .scroll((self.scroll, 0))
+            .wrap(ratatui::widgets::Wrap { trim: false });
+        frame.render_widget(p, area);
+    }
+}
+
+impl EventListener for UploadOutput {
+    fn listen(&mut self, e: &GuiEvent) -> bool {
+        use crossterm::event::{
+            Event::Key,
+            KeyCode::{Down, Left, PageDown, PageUp, Right, Up},
+            KeyEvent,
+        };
+        let GuiEvent::Crossterm(Key(KeyEvent { code, .. })) = e else {
+            return false;
+        };
+        match code {
+            Left => self.step(false),
+            Right => self.step(true),
+            Up => self.scroll = self.scroll.saturating_sub(1),
+            Down => self.scroll = self.scroll.saturating_add(1),
+            PageUp => self.scroll = self.scroll.saturating_sub(10),
+            PageDown => self.scroll = self.scroll.saturating_add(10),
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// Yes/no prompt shown before quitting with work in flight.
+pub struct ConfirmQuit {
+    question: String,
+    to_app: Messenger,
+    alive: bool,
+}
+
+impl ConfirmQuit {
+    pub fn new(question: String, to_app: Messenger) -> Self {
+        Self {
+            question,
+            to_app,
+            alive: true,
+        }
+    }
+}
+
+impl Drawable for ConfirmQuit {
+    fn alive(&self) -> bool {
+        self.alive
+    }
+
+    fn draw(&mut self, area: ratatui::prelude::Rect, frame: &mut ratatui::Frame, _theme: &Theme) {
+        frame.render_widget(Clear, area);
+        let p = Paragraph::new(self.question.as_str())
+            .block(Block::bordered().title_bottom(Line::raw("y to quit, n to stay").centered()))
+            .centered()
+            .wrap(ratatui::widgets::Wrap { trim: false });
+        frame.render_widget(p, area);
+    }
+}
+
+impl EventListener for ConfirmQuit {
+    fn listen(&mut self, e: &GuiEvent) -> bool {
+        use crossterm::event::{
+            Event::Key,
+            KeyCode::{Char, Enter},
+            KeyEvent,
+        };
+        let GuiEvent::Crossterm(Key(KeyEvent { code, .. })) = e else {
+            return false;
+        };
+        match code {
+            Char('y') | Enter => self.to_app.send_app(AppEvent::Quit),
+            Char('n') => self.alive = false,
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// Keybinding overlay, generated from the active key map.
+pub struct Help {
+    open: Arc<AtomicBool>,
+    rows: Vec<(String, &'static str)>,
+}
+
+impl Help {
+    pub fn new(open: Arc<AtomicBool>, rows: Vec<(String, &'static str)>) -> Self {
+        Self { open, rows }
+    }
+}
+
+impl Drop for Help {
+    fn drop(&mut self) {
+        self.open.store(false, Ordering::Relaxed);
+    }
+}
+
+impl Drawable for Help {
+    fn alive(&self) -> bool {
+        self.open.load(Ordering::Relaxed)
+    }
+
+    fn draw(&mut self, area: ratatui::prelude::Rect, frame: &mut ratatui::Frame, _theme: &Theme) {
+        frame.render_widget(Clear, area);
+        let rows = self
+            .rows
+            .iter()
+            .map(|(k, d)| Row::new([k.as_str().bold(), (*d).into()]));
+        let width = self.rows.iter().map(|(k, _)| k.len()).max().unwrap_or(0) as u16;
+        let table = Table::new(rows, [Constraint::Length(width), Constraint::Fill(1)])
+            .column_spacing(2)
+            .block(Block::bordered().title(Line::raw("Keybindings").centered()));
+        frame.render_widget(table, area);
+    }
+}
+
+impl EventListener for Help {
+    fn listen(&mut self, _: &GuiEvent) -> bool {
+        false
+    }
+}
@@ -0,0 +1,106 @@
+use std::io::BufRead;
+
+use eyre::{Context, Result, eyre};
+use tokio::{
+    io::AsyncWriteExt,
+    select,
+    sync::{mpsc, oneshot},
+};
+
+use crate::{
+    cli::DeviceOptions,
+    event::{
+        Capture, FromSerialData, GuiEvent, Messenger, SERIAL_QUEUE, Severity, ToAppEvent,
+        ToSerialData, serial_handler,
+    },
+    ui::LineEnding,
+};
+
+/// Bridges stdin to the port and the port to stdout without the TUI, for
+/// `--pipe`. Ends once stdin closes and what was read from it has gone out,
+/// or with an error if the port is lost.
+pub async fn run(
+    mut device: DeviceOptions,
+    line_ending: LineEnding,
+    capture: Capture,
+) -> Result<()> {
+    let config = device
+        .to_config()
+        .ok_or_else(|| eyre!("--pipe needs a device, pass it with --path"))?;
+    let path = config.path.clone();
+    let opening = config.clone();
+    let port = tokio::task::spawn_blocking(move || opening.to_serial())
+        .await?
+        .wrap_err_with(|| format!("Unable to open {}", path.display()))?;
+
+    let (app_tx, mut app_rx) = mpsc::unbounded_channel();
+    let (serial_tx, mut serial_rx) = mpsc::channel(SERIAL_QUEUE);
+    let to_serial = serial_handler(port, Messenger::new(app_tx, serial_tx), capture, &config);
+
+    // Read on a plain thread: tokio's stdin reads on a blocking task the
+    // runtime waits for at shutdown, so a lost port would hang until stdin
+    // closed too.
+    let (done_tx, mut done) = oneshot::channel();
+    std::thread::spawn(move || {
+        let mut stdin = std::io::stdin().lock();
+        let mut line = Vec::new();
+        let result = loop {
+            line.clear();
+            match stdin.read_until(b'\n', &mut line) {
+                Ok(0) => {
+                    // queued behind the writes, so they are flushed before the port closes
+                    _ = to_serial.send(ToSerialData::Disconnect);
+                    break Ok(());
+                }
+                Ok(_) => {}
+                Err(e) => break Err(e),
+            }
+            // a last line without a newline goes out as is
+            if line.ends_with(b"\n") {
+                line.pop();
+                if line.ends_with(b"\r") {
+                    line.pop();
+                }
+                line.extend_from_slice(line_ending.as_str().as_bytes());
+            }
+            if to_serial
+                .send(ToSerialData::RawBytes(line.clone()))
+                .is_err()
+            {
+                break Ok(());
+            }
+        };
+        _ = done_tx.send(result);
+    });
+    let mut reading = true;
+    let mut stdout = tokio::io::stdout();
+
+    loop {
+        select! {
+            r = &mut done, if reading => {
+                reading = false;
+                if let Ok(r) = r {
+                    r.wrap_err("Unable to read stdin")?;
+                }
+            }
+            Some((_, d)) = serial_rx.recv() => match d {
+                FromSerialData::Data(d) => {
+                    stdout.write_all(&d).await?;
+                    stdout.flush().await?;
+                }
+                FromSerialData::SendFailed(e) => return Err(eyre!("Unable to send: {}", e)),
+                FromSerialData::Lost(e) => {
+                    return Err(eyre!("Connection to {} lost: {}", path.display(), e));
+                }
+                FromSerialData::Gone => return Ok(()),
+                _ => {}
+            },
+            Some((_, e)) = app_rx.recv() => {
+                if let ToAppEvent::Gui(GuiEvent::Log(Severity::Error, msg)) = e {
+                    eprintln!("{}", msg);
+                }
+            }
+            else => return Ok(()),
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use std::{path::PathBuf, time::Duration};
+
+use eyre::{Result, eyre};
+use serde::Deserialize;
+use tracing::{Instrument, info_span};
+
+use crate::{
+    device_finder::DEFAULT_READ_BUFFER,
+    event::{FromSerialData, Messenger, Severity},
+    ui::parse_hex,
+};
+
+/// One line of an `--emit-json` session or a json capture. Only received
+/// data is replayed.
+#[derive(Debug, Deserialize)]
+struct Recorded {
+    time: f64,
+    event: String,
+    hex: Option<String>,
+}
+
+/// Feeds a previous session to the dashboard as if it were being received.
+/// Raw captures are played back at once, `--emit-json` output keeps the
+/// original gaps between chunks.
+pub fn start(path: PathBuf, to_dash: Messenger) {
+    tokio::spawn(
+        async move {
+            to_dash
+                .forward_serial(FromSerialData::Connect(format!(
+                    "Replay of {}",
+                    path.display()
+                )))
+                .await;
+            let result = match tokio::fs::read(&path).await {
+                Ok(bytes) => play(&bytes, &to_dash).await,
+                Err(e) => Err(eyre!("Unable to read {}: {}", path.display(), e)),
+            };
+            match result {
+                Ok(()) => to_dash.log(Severity::Info, "Replay finished".into()),
+                Err(e) => to_dash.log(Severity::Error, format!("Replay failed: {}", e)),
+            }
+        }
+        .instrument(info_span!("Replay")),
+    );
+}
+
+async fn play(bytes: &[u8], to_dash: &Messenger) -> Result<()> {
+    let Some(records) = parse_json(bytes) else {
+        for chunk in bytes.chunks(DEFAULT_READ_BUFFER) {
+            to_dash
+                .forward_serial(FromSerialData::Data(chunk.to_vec()))
+                .await;
+        }
+        return Ok(());
+    };
+    let mut last = None;
+    for r in records {
+        if r.event != "rx" {
+            continue;
+        }
+        let data = parse_hex(r.hex.as_deref().unwrap_or_default())?;
+        if let Some(prev) = last {
+            let gap = Duration::try_from_secs_f64(r.time - prev).unwrap_or_default();
+            tokio::time::sleep(gap).await;
+        }
+        last = Some(r.time);
+        if to_dash.is_closed() {
+            break;
+        }
+        to_dash.forward_serial(FromSerialData::Data(data)).await;
+    }
+    Ok(())
+}
+
+// None unless every line is a record, so a raw capture that happens to
+// start with a brace is still played as raw
+fn parse_json(bytes: &[u8]) -> Option<Vec<Recorded>> {
+    let text = std::str::from_utf8(bytes).ok()?;
+    if !text.trim_start().starts_with('{') {
+        return None;
+    }
+    text.lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| serde_json::from_str(l).ok())
+        .collect()
+}
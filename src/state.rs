@@ -0,0 +1,226 @@
+use std::{fs, path::PathBuf};
+
+use color_eyre::Result;
+use eyre::{Context, OptionExt};
+use serde::{Deserialize, Serialize};
+use serialport::{DataBits, FlowControl, Parity, StopBits};
+
+use crate::{
+    cli::{DeviceOptions, config_dir, parse_data, parse_flow, parse_parity, parse_stop},
+    device_finder::{Baud, DeviceConfig, port_present},
+    event::Severity,
+    ui::{Dir, DisplayMode, LineEnding},
+};
+
+/// The most recently connected device, saved so `--last` can reopen it.
+/// Settings are stored the way they are written on the commandline.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LastDevice {
+    path: PathBuf,
+    baud: u32,
+    bits: String,
+    flow: String,
+    parity: String,
+    stop: String,
+    dtr: bool,
+}
+
+fn last_device_path() -> Result<PathBuf> {
+    Ok(config_dir()
+        .ok_or_eyre("No config directory")?
+        .join("last-device.toml"))
+}
+
+impl LastDevice {
+    pub fn save(config: &DeviceConfig) -> Result<()> {
+        let last = LastDevice {
+            path: config.path.clone(),
+            baud: config.baud.0,
+            bits: match config.bits {
+                DataBits::Five => "5",
+                DataBits::Six => "6",
+                DataBits::Seven => "7",
+                DataBits::Eight => "8",
+            }
+            .into(),
+            flow: match config.flow {
+                FlowControl::None => "none",
+                FlowControl::Software => "software",
+                FlowControl::Hardware => "hardware",
+            }
+            .into(),
+            parity: match config.parity {
+                Parity::None => "none",
+                Parity::Odd => "odd",
+                Parity::Even => "even",
+            }
+            .into(),
+            stop: match config.stop {
+                StopBits::One => "1",
+                StopBits::Two => "2",
+            }
+            .into(),
+            dtr: config.dtr,
+        };
+        let path = last_device_path()?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, toml::to_string(&last)?)
+            .wrap_err_with(|| format!("Unable to write {}", path.display()))
+    }
+
+    /// `None` if nothing has been saved yet.
+    pub fn load() -> Result<Option<LastDevice>> {
+        let path = last_device_path()?;
+        if !path.exists() {
+            return Ok(None);
+        }
+        let text = fs::read_to_string(&path)?;
+        let last = toml::from_str(&text)
+            .wrap_err_with(|| format!("Malformed state file {}", path.display()))?;
+        Ok(Some(last))
+    }
+
+    /// The saved settings on top of everything else from the commandline.
+    pub fn to_config(&self, opts: &DeviceOptions) -> Result<DeviceConfig> {
+        let mut config = opts.to_config_path(self.path.clone());
+        config.baud = Baud(self.baud);
+        config.bits = parse_data(&self.bits)?;
+        config.flow = parse_flow(&self.flow)?;
+        config.parity = parse_parity(&self.parity)?;
+        config.stop = parse_stop(&self.stop)?;
+        config.dtr = self.dtr;
+        Ok(config)
+    }
+
+    pub fn is_present(&self) -> bool {
+        port_present(&self.path)
+    }
+
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+}
+
+/// Upload commands that have been run before, oldest first.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UploadHistory {
+    commands: Vec<String>,
+}
+
+const HISTORY_LEN: usize = 50;
+
+fn upload_history_path() -> Result<PathBuf> {
+    Ok(config_dir()
+        .ok_or_eyre("No config directory")?
+        .join("upload-history.toml"))
+}
+
+impl UploadHistory {
+    /// Empty if nothing has been saved yet.
+    pub fn load() -> Result<UploadHistory> {
+        let path = upload_history_path()?;
+        if !path.exists() {
+            return Ok(UploadHistory::default());
+        }
+        let text = fs::read_to_string(&path)?;
+        toml::from_str(&text).wrap_err_with(|| format!("Malformed state file {}", path.display()))
+    }
+
+    /// Records `cmd` as the most recent command and writes the history out.
+    pub fn save(mut self, cmd: &str) -> Result<()> {
+        self.commands.retain(|c| c != cmd);
+        self.commands.push(cmd.to_string());
+        let excess = self.commands.len().saturating_sub(HISTORY_LEN);
+        self.commands.drain(..excess);
+        let path = upload_history_path()?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, toml::to_string(&self)?)
+            .wrap_err_with(|| format!("Unable to write {}", path.display()))
+    }
+
+    pub fn commands(&self) -> &[String] {
+        &self.commands
+    }
+}
+
+/// How the dashboard was last set to show things, restored on the next
+/// start. Anything missing from the file keeps its default.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ViewPrefs {
+    pub display_mode: DisplayMode,
+    pub line_ending: LineEnding,
+    pub hex_input: bool,
+    pub wrap: bool,
+    pub show_controls: bool,
+    // only this direction is shown, None for both
+    pub direction: Option<Dir>,
+    // least severe log entry shown, None for everything
+    pub log_level: Option<Severity>,
+}
+
+fn view_prefs_path() -> Result<PathBuf> {
+    Ok(config_dir()
+        .ok_or_eyre("No config directory")?
+        .join("view.toml"))
+}
+
+impl ViewPrefs {
+    /// `None` if nothing has been saved yet.
+    pub fn load() -> Result<Option<ViewPrefs>> {
+        let path = view_prefs_path()?;
+        if !path.exists() {
+            return Ok(None);
+        }
+        let text = fs::read_to_string(&path)?;
+        let prefs = toml::from_str(&text)
+            .wrap_err_with(|| format!("Malformed state file {}", path.display()))?;
+        Ok(Some(prefs))
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = view_prefs_path()?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, toml::to_string(self)?)
+            .wrap_err_with(|| format!("Unable to write {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn view_prefs_round_trip() {
+        let view = ViewPrefs {
+            display_mode: DisplayMode::Hex,
+            line_ending: LineEnding::Crlf,
+            hex_input: true,
+            wrap: true,
+            show_controls: true,
+            direction: Some(Dir::Tx),
+            log_level: Some(Severity::Error),
+        };
+        let text = toml::to_string(&view).unwrap();
+        assert_eq!(toml::from_str::<ViewPrefs>(&text).unwrap(), view);
+    }
+
+    #[test]
+    fn view_prefs_fill_in_missing_keys() {
+        let view: ViewPrefs = toml::from_str("wrap = true\nline-ending = \"cr\"").unwrap();
+        assert_eq!(
+            view,
+            ViewPrefs {
+                wrap: true,
+                line_ending: LineEnding::Cr,
+                ..Default::default()
+            }
+        );
+    }
+}
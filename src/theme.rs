@@ -0,0 +1,228 @@
+use clap::ValueEnum;
+use eyre::{Result, eyre};
+use ratatui::{
+    style::{Color, Modifier, Style, Stylize},
+    text::Span,
+};
+use serde::Deserialize;
+
+/// Built-in color schemes, picked with `--theme`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ThemePreset {
+    #[default]
+    Dark,
+    Light,
+    HighContrast,
+}
+
+/// Colors and glyphs used when drawing.
+#[derive(Clone, Debug)]
+pub struct Theme {
+    pub fg: Color,
+    pub bg: Color,
+    // search matches, reversed video if unset
+    pub highlight: Option<(Color, Color)>,
+    // the chosen item of lists, tables and tabs, kept off reversed video
+    // since some terminals don't draw it
+    pub selected: Style,
+    pub error: Color,
+    pub info: Color,
+    pub debug: Color,
+    // lines we sent
+    pub sent: Color,
+    pub scrollbar_thumb: String,
+    pub scrollbar_track: Option<String>,
+    // end of the text being typed
+    pub cursor: String,
+    // modem lines in the status pane
+    pub led_on: String,
+    pub led_off: String,
+}
+
+impl Theme {
+    pub fn preset(preset: ThemePreset) -> Self {
+        let dark = Theme {
+            fg: Color::Reset,
+            bg: Color::Reset,
+            highlight: None,
+            selected: Style::new().bold().bg(Color::DarkGray),
+            error: Color::Red,
+            info: Color::Reset,
+            debug: Color::LightGreen,
+            sent: Color::Cyan,
+            scrollbar_thumb: "#".into(),
+            scrollbar_track: None,
+            cursor: "█".into(),
+            led_on: "●".into(),
+            led_off: "○".into(),
+        };
+        match preset {
+            ThemePreset::Dark => dark,
+            // dark text on white whatever the terminal's own colors, the
+            // light variants are unreadable on a white background
+            ThemePreset::Light => Theme {
+                fg: Color::Black,
+                bg: Color::White,
+                highlight: Some((Color::White, Color::Blue)),
+                selected: Style::new().bold().fg(Color::Black).bg(Color::Gray),
+                info: Color::Black,
+                debug: Color::Green,
+                sent: Color::Blue,
+                ..dark
+            },
+            ThemePreset::HighContrast => Theme {
+                fg: Color::White,
+                bg: Color::Black,
+                highlight: Some((Color::Black, Color::Yellow)),
+                selected: Style::new().bold().fg(Color::Black).bg(Color::Yellow),
+                error: Color::LightRed,
+                info: Color::White,
+                debug: Color::LightCyan,
+                sent: Color::Yellow,
+                scrollbar_thumb: "█".into(),
+                scrollbar_track: Some("│".into()),
+                ..dark
+            },
+        }
+    }
+
+    /// The preset with anything set in the config file's [colors] table on top.
+    pub fn new(preset: ThemePreset, colors: &ThemeColors) -> Self {
+        let mut theme = Self::preset(preset);
+        let c = colors;
+        theme.fg = c.fg.unwrap_or(theme.fg);
+        theme.bg = c.bg.unwrap_or(theme.bg);
+        if let Some(fg) = c.highlight_fg {
+            theme.highlight = Some((fg, c.highlight_bg.unwrap_or(theme.fg)));
+        } else if let Some(bg) = c.highlight_bg {
+            theme.highlight = Some((theme.bg, bg));
+        }
+        if let Some(fg) = c.selected_fg {
+            theme.selected = theme.selected.fg(fg);
+        }
+        if let Some(bg) = c.selected_bg {
+            theme.selected = theme.selected.bg(bg);
+        }
+        match c.selected_bold {
+            Some(true) => theme.selected = theme.selected.bold(),
+            Some(false) => theme.selected = theme.selected.not_bold(),
+            None => {}
+        }
+        theme.error = c.error.unwrap_or(theme.error);
+        theme.info = c.info.unwrap_or(theme.info);
+        theme.debug = c.debug.unwrap_or(theme.debug);
+        theme.sent = c.sent.unwrap_or(theme.sent);
+        if let Some(ref t) = c.scrollbar_thumb {
+            theme.scrollbar_thumb = t.clone();
+        }
+        if let Some(ref t) = c.scrollbar_track {
+            theme.scrollbar_track = Some(t.clone());
+        }
+        if let Some(ref t) = c.cursor {
+            theme.cursor = t.clone();
+        }
+        if let Some(ref t) = c.led_on {
+            theme.led_on = t.clone();
+        }
+        if let Some(ref t) = c.led_off {
+            theme.led_off = t.clone();
+        }
+        theme
+    }
+
+    pub fn base(&self) -> Style {
+        Style::new().fg(self.fg).bg(self.bg)
+    }
+
+    pub fn highlight(&self) -> Style {
+        match self.highlight {
+            Some((fg, bg)) => Style::new().fg(fg).bg(bg),
+            None => Style::new().reversed(),
+        }
+    }
+
+    pub fn selected(&self) -> Style {
+        self.selected
+    }
+
+    pub fn cursor(&self) -> Span<'_> {
+        Span::raw(self.cursor.as_str()).style(Style::new().add_modifier(Modifier::SLOW_BLINK))
+    }
+
+    pub fn led(&self, on: bool) -> &str {
+        if on { &self.led_on } else { &self.led_off }
+    }
+}
+
+/// The `[colors]` table of the config file, already parsed.
+#[derive(Clone, Debug, Default)]
+pub struct ThemeColors {
+    fg: Option<Color>,
+    bg: Option<Color>,
+    highlight_fg: Option<Color>,
+    highlight_bg: Option<Color>,
+    selected_fg: Option<Color>,
+    selected_bg: Option<Color>,
+    selected_bold: Option<bool>,
+    error: Option<Color>,
+    info: Option<Color>,
+    debug: Option<Color>,
+    sent: Option<Color>,
+    scrollbar_thumb: Option<String>,
+    scrollbar_track: Option<String>,
+    cursor: Option<String>,
+    led_on: Option<String>,
+    led_off: Option<String>,
+}
+
+/// How the `[colors]` table is written, colors as names like "red" or
+/// "#ff8000".
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ColorsConfig {
+    fg: Option<String>,
+    bg: Option<String>,
+    highlight_fg: Option<String>,
+    highlight_bg: Option<String>,
+    selected_fg: Option<String>,
+    selected_bg: Option<String>,
+    selected_bold: Option<bool>,
+    error: Option<String>,
+    info: Option<String>,
+    debug: Option<String>,
+    sent: Option<String>,
+    scrollbar_thumb: Option<String>,
+    scrollbar_track: Option<String>,
+    cursor: Option<String>,
+    led_on: Option<String>,
+    led_off: Option<String>,
+}
+
+impl TryFrom<ColorsConfig> for ThemeColors {
+    type Error = eyre::Report;
+
+    fn try_from(c: ColorsConfig) -> Result<Self> {
+        let color = |name: Option<String>| {
+            name.map(|n| n.parse().map_err(|_| eyre!("Unknown color {:?}", n)))
+                .transpose()
+        };
+        Ok(Self {
+            fg: color(c.fg)?,
+            bg: color(c.bg)?,
+            highlight_fg: color(c.highlight_fg)?,
+            highlight_bg: color(c.highlight_bg)?,
+            selected_fg: color(c.selected_fg)?,
+            selected_bg: color(c.selected_bg)?,
+            selected_bold: c.selected_bold,
+            error: color(c.error)?,
+            info: color(c.info)?,
+            debug: color(c.debug)?,
+            sent: color(c.sent)?,
+            scrollbar_thumb: c.scrollbar_thumb,
+            scrollbar_track: c.scrollbar_track,
+            cursor: c.cursor,
+            led_on: c.led_on,
+            led_off: c.led_off,
+        })
+    }
+}
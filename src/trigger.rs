@@ -0,0 +1,175 @@
+use eyre::{Result, eyre};
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::{
+    event::{Messenger, Severity},
+    highlight::pattern,
+    ui::decode_utf8,
+};
+
+/// A `[[trigger]]` entry in the config file. Exactly one of `text` or
+/// `regex` is given.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct TriggerConfig {
+    text: Option<String>,
+    regex: Option<String>,
+    #[serde(default = "yes")]
+    bell: bool,
+    #[serde(default = "yes")]
+    flash: bool,
+    // run with the matching line in $SETERM_LINE
+    command: Option<String>,
+}
+
+fn yes() -> bool {
+    true
+}
+
+/// Something to do when received text matches a pattern.
+#[derive(Clone, Debug)]
+pub struct Trigger {
+    pattern: Regex,
+    pub bell: bool,
+    pub flash: bool,
+    command: Option<Vec<String>>,
+}
+
+impl TryFrom<TriggerConfig> for Trigger {
+    type Error = eyre::Report;
+
+    fn try_from(c: TriggerConfig) -> Result<Self> {
+        let command = match c.command {
+            Some(cmd) => match shlex::split(&cmd) {
+                Some(words) if !words.is_empty() => Some(words),
+                _ => return Err(eyre!("Unable to parse trigger command {:?}", cmd)),
+            },
+            None => None,
+        };
+        Ok(Self {
+            pattern: pattern(c.text, c.regex)?,
+            bell: c.bell,
+            flash: c.flash,
+            command,
+        })
+    }
+}
+
+impl Trigger {
+    pub fn pattern(&self) -> &str {
+        self.pattern.as_str()
+    }
+
+    pub fn run_command(&self, line: &str, to_app: &Messenger) {
+        let Some(cmd) = self.command.clone() else {
+            return;
+        };
+        let to_app = to_app.clone();
+        let line = line.to_string();
+        tokio::spawn(async move {
+            let status = tokio::process::Command::new(&cmd[0])
+                .args(&cmd[1..])
+                .env("SETERM_LINE", line)
+                .status()
+                .await;
+            match status {
+                Ok(s) if s.success() => {}
+                Ok(s) => to_app.log(
+                    Severity::Error,
+                    format!("Trigger command {} exited with {}", cmd[0], s),
+                ),
+                Err(e) => to_app.log(
+                    Severity::Error,
+                    format!("Unable to run trigger command {}: {}", cmd[0], e),
+                ),
+            }
+        });
+    }
+}
+
+// received lines are cut down to this so a stream without newlines can't
+// grow the buffer forever
+const MAX_LINE: usize = 4096;
+
+/// Matches triggers against received text line by line, so a match split
+/// across two reads is still found. Each trigger fires at most once a line.
+#[derive(Debug, Default)]
+pub struct Triggers {
+    rules: Vec<Trigger>,
+    // received since the last newline
+    line: String,
+    // the start of a character split across reads
+    partial_char: Vec<u8>,
+    // which rules already fired on line
+    fired: Vec<bool>,
+}
+
+impl Triggers {
+    pub fn new(rules: Vec<Trigger>) -> Self {
+        Self {
+            fired: vec![false; rules.len()],
+            rules,
+            line: String::new(),
+            partial_char: Vec::new(),
+        }
+    }
+
+    /// The triggers that fired, with the line each one matched as far as
+    /// it had arrived.
+    pub fn feed(&mut self, data: &[u8]) -> Vec<(&Trigger, String)> {
+        if self.rules.is_empty() {
+            return Vec::new();
+        }
+        let mut hits = Vec::new();
+        let text = decode_utf8(&mut self.partial_char, data);
+        for piece in text.split_inclusive('\n') {
+            self.line.push_str(piece.trim_end_matches(['\r', '\n']));
+            if self.line.len() > MAX_LINE {
+                let cut = self.line.ceil_char_boundary(self.line.len() - MAX_LINE);
+                self.line.drain(..cut);
+            }
+            for (i, rule) in self.rules.iter().enumerate() {
+                if !self.fired[i] && rule.pattern.is_match(&self.line) {
+                    self.fired[i] = true;
+                    hits.push((i, self.line.clone()));
+                }
+            }
+            if piece.ends_with('\n') {
+                self.line.clear();
+                self.fired.fill(false);
+            }
+        }
+        hits.into_iter()
+            .map(|(i, line)| (&self.rules[i], line))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trigger(text: &str) -> Trigger {
+        Trigger::try_from(TriggerConfig {
+            text: Some(text.into()),
+            regex: None,
+            bell: true,
+            flash: true,
+            command: None,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn character_split_across_reads_still_matches() {
+        let mut triggers = Triggers::new(vec![trigger("température")]);
+        let text = "température\n".as_bytes();
+        // cut in the middle of the two byte é
+        let (first, second) = text.split_at(5);
+        assert!(triggers.feed(first).is_empty());
+        let hits = triggers.feed(second);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].1, "température");
+    }
+}
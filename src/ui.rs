@@ -1,44 +1,894 @@
-use std::mem::take;
+use std::{
+    borrow::Cow,
+    collections::{HashMap, VecDeque},
+    fmt::Display,
+    mem::take,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
 
+use clap::ValueEnum;
+use color_eyre::Result;
 use crossterm::event::{KeyEvent, KeyModifiers};
+use eyre::eyre;
 use ratatui::{
     Frame,
     buffer::Buffer,
     layout::{Constraint, Layout, Rect},
-    style::{Modifier, Style},
+    style::{Modifier, Style, Stylize},
     text::{Line, Span, Text},
     widgets::{
         Block, Paragraph, Scrollbar, ScrollbarOrientation, ScrollbarState, StatefulWidget, Widget,
     },
 };
+use serde::{Deserialize, Serialize};
 use tracing::{instrument, trace};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
-use crate::event::{
-    AppEvent, Drawable, EventListener, FromSerialData, GuiEvent, Messenger, Severity, ToSerialData,
+use crate::{
+    ansi::AnsiParser,
+    cli::TerminalOptions,
+    device_finder::ResetSequence,
+    error::SerialError,
+    event::{
+        AppEvent, Drawable, EventListener, FromSerialData, GuiEvent, Messenger, ModemStatus,
+        Severity, ToSerialData, time_of_day,
+    },
+    framing::LineSplitter,
+    highlight::{HighlightRule, highlight, line_text, restyle},
+    keys::{Action, KeyMap, Macro, encode_key},
+    state::ViewPrefs,
+    theme::Theme,
+    trigger::Triggers,
 };
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    Cr,
+    Crlf,
+    None,
+}
+
+impl LineEnding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::Cr => "\r",
+            LineEnding::Crlf => "\r\n",
+            LineEnding::None => "",
+        }
+    }
+
+    fn next(self) -> Self {
+        match self {
+            LineEnding::Lf => LineEnding::Cr,
+            LineEnding::Cr => LineEnding::Crlf,
+            LineEnding::Crlf => LineEnding::None,
+            LineEnding::None => LineEnding::Lf,
+        }
+    }
+}
+
+impl std::fmt::Display for LineEnding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            LineEnding::Lf => "LF",
+            LineEnding::Cr => "CR",
+            LineEnding::Crlf => "CRLF",
+            LineEnding::None => "None",
+        };
+        write!(f, "{}", s)
+    }
+}
+
 #[derive(Debug)]
 pub struct Dashboard {
     alive: bool,
     term_input: String,
+    // input is sent as raw bytes written in hex
+    hex_input: bool,
+    // every key is sent as it is typed, bypassing the input line
+    raw: bool,
+    history: History,
     term_state: TerminalStatus,
     status: Status,
+    keys: KeyMap,
+    // function key number to what it sends
+    macros: HashMap<u8, Macro>,
+    triggers: Triggers,
+    // the terminal window is in front, as far as focus events tell
+    focused: bool,
+    dim_unfocused: bool,
+    // as last written out, to only save when something changed
+    saved_view: ViewPrefs,
+    // label of a marker being typed
+    marker_label: Option<String>,
+    // how long alt+a collects a response for
+    response_timeout: Duration,
     to_app: Messenger,
 }
 
+const HISTORY_LEN: usize = 500;
+
+/// Previously sent input lines, newest at the back. Each remembers whether
+/// it was typed as hex, and recalling it switches the input back to that.
+#[derive(Debug, Default)]
+struct History {
+    entries: VecDeque<Sent>,
+    // position while browsing; None means the draft is being edited
+    index: Option<usize>,
+    draft: Sent,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct Sent {
+    text: String,
+    hex: bool,
+}
+
+impl History {
+    fn push(&mut self, line: &str, hex: bool) {
+        self.index = None;
+        let sent = Sent {
+            text: line.into(),
+            hex,
+        };
+        if line.is_empty() || self.entries.back() == Some(&sent) {
+            return;
+        }
+        if self.entries.len() == HISTORY_LEN {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(sent);
+    }
+
+    fn prev(&mut self, input: &mut String, hex: &mut bool) {
+        let i = match self.index {
+            None if self.entries.is_empty() => return,
+            None => {
+                self.draft = Sent {
+                    text: take(input),
+                    hex: *hex,
+                };
+                self.entries.len() - 1
+            }
+            Some(i) => i.saturating_sub(1),
+        };
+        self.index = Some(i);
+        input.clone_from(&self.entries[i].text);
+        *hex = self.entries[i].hex;
+    }
+
+    fn next(&mut self, input: &mut String, hex: &mut bool) {
+        let Some(i) = self.index else {
+            return;
+        };
+        let sent = if i + 1 < self.entries.len() {
+            self.index = Some(i + 1);
+            self.entries[i + 1].clone()
+        } else {
+            self.index = None;
+            take(&mut self.draft)
+        };
+        *input = sent.text;
+        *hex = sent.hex;
+    }
+}
+
+const RATE_WINDOW: Duration = Duration::from_secs(2);
+
+// running byte count, plus recent samples for a rough rate
+#[derive(Debug, Default)]
+struct Throughput {
+    total: u64,
+    recent: VecDeque<(Instant, usize)>,
+}
+
+impl Throughput {
+    fn record(&mut self, bytes: usize) {
+        let now = Instant::now();
+        self.total += bytes as u64;
+        self.recent.push_back((now, bytes));
+        while let Some((t, _)) = self.recent.front()
+            && now.duration_since(*t) > RATE_WINDOW
+        {
+            self.recent.pop_front();
+        }
+    }
+
+    // bytes per second over the last few seconds
+    fn rate(&self) -> f64 {
+        let now = Instant::now();
+        let bytes: usize = self
+            .recent
+            .iter()
+            .filter(|(t, _)| now.duration_since(*t) <= RATE_WINDOW)
+            .map(|(_, b)| b)
+            .sum();
+        bytes as f64 / RATE_WINDOW.as_secs_f64()
+    }
+}
+
+impl Display for Throughput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} ({}/s)",
+            human_bytes(self.total as f64),
+            human_bytes(self.rate())
+        )
+    }
+}
+
+fn human_bytes(b: f64) -> String {
+    if b < 1024.0 {
+        format!("{:.0} B", b)
+    } else if b < 1024.0 * 1024.0 {
+        format!("{:.1} KiB", b / 1024.0)
+    } else {
+        format!("{:.1} MiB", b / (1024.0 * 1024.0))
+    }
+}
+
 #[derive(Default)]
 struct Status {
     rts: bool,
     dtr: bool,
+    reset: ResetSequence,
+    modem: ModemStatus,
     device: String,
+    config: String,
+    capture: Option<PathBuf>,
+    line_ending: LineEnding,
+    rx: Throughput,
+    tx: Throughput,
+    // connected but nothing received for the idle timeout
+    idle: bool,
+    // XMODEM block acknowledged and total, while a transfer runs
+    xmodem: Option<(usize, usize)>,
+    // the device is not taking what we send
+    tx_blocked: bool,
+    // when the current port was opened
+    connected_at: Option<Instant>,
+    // closed for flashing and waiting to be reopened
+    reflashing: bool,
+    // port being opened and since when
+    connecting: Option<(PathBuf, Instant)>,
+    // flashes since the dashboard was opened, to number the separators
+    reflashes: usize,
+    // a trigger went off, shown until the next key press
+    alert: Option<String>,
     log: Vec<(Severity, String)>,
+    // least severe log entry shown, the rest are kept but hidden. None
+    // shows everything
+    log_level: Option<Severity>,
+}
+
+impl Status {
+    fn reset_counters(&mut self) {
+        self.rx = Throughput::default();
+        self.tx = Throughput::default();
+    }
+
+    fn clear_connection(&mut self) {
+        self.device.clear();
+        self.connected_at = None;
+        self.idle = false;
+        self.xmodem = None;
+        self.tx_blocked = false;
+        self.modem = ModemStatus::default();
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Dir {
+    // received from the device
+    #[default]
+    Rx,
+    // typed and sent by us
+    Tx,
+}
+
+struct Entry {
+    dir: Dir,
+    line: Line<'static>,
+    // drawn with a rule either side filling the view's width
+    rule: bool,
 }
 
 #[derive(Default)]
 struct TerminalStatus {
-    text: Vec<String>,
+    text: Vec<Entry>,
+    // index in text of the received line still waiting for its newline.
+    // Sent lines can come after it.
+    open_line: Option<usize>,
+    // characters in the last received line
+    line_len: usize,
+    // and the columns they take up, which tab stops are counted in
+    line_width: usize,
+    // received lines longer than this are split, so a stream without
+    // newlines does not become one enormous line. 0 for no limit
+    max_line_len: usize,
+    // columns between tab stops, tabs are kept if 0
+    tabstop: usize,
+    // cuts received text into lines
+    splitter: LineSplitter,
+    // show only one direction
+    only: Option<Dir>,
+    // draw control characters as ^G instead of passing them to the terminal
+    show_controls: bool,
+    // long lines continue on the next row instead of being cut off. Rows
+    // and scrolling then count screen rows rather than lines.
+    wrap: bool,
+    // width of the text area as of the last draw
+    width: usize,
+    ansi: AnsiParser,
+    // start of a multi-byte character cut off at the end of the last read
+    partial_char: Vec<u8>,
+    data: Vec<Vec<u8>>,
+    // bytes dropped from the front of data
+    data_offset: usize,
+    hex_rows: usize,
+    // most lines kept, 0 for no limit. Capture files keep everything.
+    scrollback: usize,
+    display_mode: DisplayMode,
+    // rows scrolled up from the newest one
     scroll_index: usize,
+    // rows that arrived while scrolled up or not following
+    unseen: usize,
+    // new output moves the view to the newest row, unless scrolled up.
+    // Otherwise the view stays where it is, even at the bottom.
+    follow: bool,
+    // height of the text area as of the last draw
+    visible_rows: usize,
     scroll_state: ScrollbarState,
+    search: Option<Search>,
+    selection: Option<Selection>,
+    highlights: Vec<HighlightRule>,
+}
+
+/// Rows picked for copying, as row indices from the oldest like search
+/// matches.
+#[derive(Clone, Copy, Debug)]
+struct Selection {
+    cursor: usize,
+    // the other end of the range once one is marked
+    anchor: Option<usize>,
+}
+
+impl Selection {
+    fn range(&self) -> std::ops::RangeInclusive<usize> {
+        let other = self.anchor.unwrap_or(self.cursor);
+        self.cursor.min(other)..=self.cursor.max(other)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Search {
+    query: String,
+    // the query is still being typed, otherwise n/N step between matches
+    editing: bool,
+    case_sensitive: bool,
+    // row indices of matching rows, oldest first
+    matches: Vec<usize>,
+    current: usize,
+}
+
+impl Search {
+    fn ranges(&self, hay: &str) -> Vec<std::ops::Range<usize>> {
+        if self.query.is_empty() {
+            return Vec::new();
+        }
+        // ascii-only folding keeps byte offsets valid for the original text
+        let (hay, query) = if self.case_sensitive {
+            (hay.to_string(), self.query.clone())
+        } else {
+            (hay.to_ascii_lowercase(), self.query.to_ascii_lowercase())
+        };
+        hay.match_indices(&query)
+            .map(|(i, m)| i..i + m.len())
+            .collect()
+    }
+
+    fn highlight(&self, line: &Line, theme: &Theme) -> Line<'static> {
+        let style = theme.highlight();
+        let ranges: Vec<_> = self
+            .ranges(&line_text(line))
+            .into_iter()
+            .map(|r| (r, style))
+            .collect();
+        restyle(line, &ranges)
+    }
+}
+
+/// Decodes as much as possible, carrying an incomplete trailing sequence
+/// over to the next call. Bytes that can never be valid become U+FFFD.
+pub fn decode_utf8(partial: &mut Vec<u8>, data: &[u8]) -> String {
+    partial.extend_from_slice(data);
+    let bytes = take(partial);
+    let mut out = String::with_capacity(bytes.len());
+    let mut rest = &bytes[..];
+    loop {
+        match std::str::from_utf8(rest) {
+            Ok(s) => {
+                out.push_str(s);
+                break;
+            }
+            Err(e) => {
+                let (valid, after) = rest.split_at(e.valid_up_to());
+                out.push_str(&String::from_utf8_lossy(valid));
+                match e.error_len() {
+                    Some(n) => {
+                        out.push(char::REPLACEMENT_CHARACTER);
+                        rest = &after[n..];
+                    }
+                    None => {
+                        partial.extend_from_slice(after);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    out
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DisplayMode {
+    #[default]
+    Text,
+    Hex,
+}
+
+const HEX_ROW_WIDTH: usize = 16;
+
+const LINE_BREAK: &str = "↩";
+// after a received line whose newline has not arrived yet
+const PARTIAL: &str = "…";
+
+impl TerminalStatus {
+    // number of rendered rows in the current display mode
+    fn rows(&self) -> usize {
+        match self.display_mode {
+            DisplayMode::Text if self.wrap => self.visible().map(|e| self.line_rows(e)).sum(),
+            DisplayMode::Text => self.visible().count(),
+            DisplayMode::Hex => self.hex_rows,
+        }
+    }
+
+    // screen rows an entry takes up, matching what wrap_line produces
+    fn line_rows(&self, e: &Entry) -> usize {
+        if !self.wrap || self.width < 2 {
+            return 1;
+        }
+        let controls;
+        let line = if self.show_controls {
+            controls = visible_controls(&e.line);
+            &controls
+        } else {
+            &e.line
+        };
+        let tag = if e.dir == Dir::Tx { 2 } else { 0 };
+        // the marker drawn after a line still waiting for its newline
+        let partial = match self.open_entry() {
+            Some(o) if std::ptr::eq(o, e) => PARTIAL,
+            _ => "",
+        };
+        let widths = line
+            .spans
+            .iter()
+            .flat_map(|s| s.content.chars())
+            .chain(partial.chars())
+            .map(|c| c.width().unwrap_or(0));
+        let mut rows = 1;
+        let mut used = tag;
+        for w in widths {
+            if used + w > self.width && used > 0 {
+                rows += 1;
+                used = 1;
+            }
+            used += w;
+        }
+        rows
+    }
+
+    fn open_entry(&self) -> Option<&Entry> {
+        self.open_line.and_then(|i| self.text.get(i))
+    }
+
+    fn toggle_wrap(&mut self) {
+        self.wrap = !self.wrap;
+        self.scroll_to(0);
+    }
+
+    // text entries passing the direction filter, oldest first
+    fn visible(&self) -> impl DoubleEndedIterator<Item = &Entry> {
+        let only = self.only;
+        self.text
+            .iter()
+            .filter(move |e| only.is_none_or(|d| d == e.dir))
+    }
+
+    fn push_data(&mut self, data: &[u8]) {
+        let before = self.rows();
+        self.push_rows(data);
+        self.hold_view(before);
+        self.trim_scrollback();
+    }
+
+    // Drops the oldest lines and hex chunks past the scrollback limit. The
+    // scroll position counts from the newest row, so the view stays put.
+    fn trim_scrollback(&mut self) {
+        if self.scrollback == 0 {
+            return;
+        }
+        let excess = self.text.len().saturating_sub(self.scrollback);
+        let only = self.only;
+        let dropped_lines = self.text[..excess]
+            .iter()
+            .filter(|e| only.is_none_or(|d| d == e.dir))
+            .count();
+        self.text.drain(..excess);
+        self.open_line = self.open_line.and_then(|i| i.checked_sub(excess));
+        let mut chunks = 0;
+        let mut dropped_hex = 0;
+        while self.hex_rows - dropped_hex > self.scrollback && chunks + 1 < self.data.len() {
+            let chunk = &self.data[chunks];
+            dropped_hex += chunk.len().div_ceil(HEX_ROW_WIDTH);
+            self.data_offset += chunk.len();
+            chunks += 1;
+        }
+        self.data.drain(..chunks);
+        self.hex_rows -= dropped_hex;
+        // search matches are row numbers from the oldest row
+        let dropped = match self.display_mode {
+            DisplayMode::Text => dropped_lines,
+            DisplayMode::Hex => dropped_hex,
+        };
+        if let Some(sel) = self.selection.as_mut() {
+            sel.cursor = sel.cursor.saturating_sub(dropped);
+            sel.anchor = sel.anchor.map(|a| a.saturating_sub(dropped));
+        }
+        if let Some(search) = self.search.as_mut()
+            && dropped > 0
+        {
+            let before = search.matches.len();
+            search.matches.retain(|m| *m >= dropped);
+            search.matches.iter_mut().for_each(|m| *m -= dropped);
+            let gone = before - search.matches.len();
+            search.current = search
+                .current
+                .saturating_sub(gone)
+                .min(search.matches.len().saturating_sub(1));
+        }
+    }
+
+    // a line of our own between received output, which starts afresh after it
+    fn push_marker(&mut self, line: Line<'static>) {
+        self.push_own(line, false);
+    }
+
+    // a user's marker, a rule across the view with the text in the middle.
+    // The rule and its color are added when drawn, following resizes and
+    // the theme.
+    fn push_rule(&mut self, text: &str) {
+        self.push_own(Line::raw(format!(" {} ", text)), true);
+    }
+
+    fn push_own(&mut self, line: Line<'static>, rule: bool) {
+        let before = self.rows();
+        self.text.push(Entry {
+            dir: Dir::Rx,
+            line,
+            rule,
+        });
+        self.open_line = None;
+        self.hold_view(before);
+        self.trim_scrollback();
+    }
+
+    // sent lines get their own rows, the line ending is implied
+    fn push_sent(&mut self, text: &str) {
+        let before = self.rows();
+        // composed lines are split on whichever line ending they were sent with
+        let text = text.replace("\r\n", "\n");
+        let text = text.trim_end_matches(['\r', '\n']);
+        for line in text.split(['\r', '\n']) {
+            self.text.push(Entry {
+                dir: Dir::Tx,
+                line: Line::raw(line.to_string()),
+                rule: false,
+            });
+        }
+        self.hold_view(before);
+        self.trim_scrollback();
+    }
+
+    // keep the view still while scrolled up or not following
+    fn hold_view(&mut self, before: usize) {
+        if self.scroll_index > 0 || !self.follow {
+            let added = self.rows() - before;
+            self.scroll_index += added;
+            self.unseen += added;
+        }
+    }
+
+    fn push_rows(&mut self, data: &[u8]) {
+        self.hex_rows += data.len().div_ceil(HEX_ROW_WIDTH);
+        self.data.push(data.to_vec());
+        let text = decode_utf8(&mut self.partial_char, data);
+        let limit = match self.max_line_len {
+            0 => usize::MAX,
+            n => n,
+        };
+        for span in self.ansi.parse(&text) {
+            for (content, terminated) in self.splitter.split(&span.content) {
+                // a sent line in between starts a new row
+                let continues = self.open_line.is_some_and(|i| i + 1 == self.text.len());
+                if !continues {
+                    self.start_line();
+                }
+                let expanded = expand_tabs(content, self.line_width, self.tabstop);
+                let mut rest = &*expanded;
+                while !rest.is_empty() {
+                    let room = limit.saturating_sub(self.line_len);
+                    if room == 0 {
+                        self.break_line();
+                        continue;
+                    }
+                    let cut = rest.char_indices().nth(room).map_or(rest.len(), |(i, _)| i);
+                    let (now, later) = rest.split_at(cut);
+                    let entry = self.text.last_mut().unwrap();
+                    entry
+                        .line
+                        .push_span(Span::styled(now.to_string(), span.style));
+                    self.line_len += now.chars().count();
+                    self.line_width += now.width();
+                    rest = later;
+                }
+                self.open_line = (!terminated).then(|| self.text.len() - 1);
+            }
+        }
+    }
+
+    fn start_line(&mut self) {
+        self.text.push(Entry {
+            dir: Dir::Rx,
+            line: Line::default(),
+            rule: false,
+        });
+        self.line_len = 0;
+        self.line_width = 0;
+    }
+
+    // ends a line that hit max_line_len, marking that it goes on in the next
+    fn break_line(&mut self) {
+        if let Some(entry) = self.text.last_mut() {
+            entry
+                .line
+                .push_span(Span::styled(LINE_BREAK, Style::new().dim()));
+        }
+        self.start_line();
+    }
+
+    // drops the scrollback, leaving only a marker behind. Capture files are
+    // written by the serial task so they are unaffected.
+    fn clear(&mut self) {
+        self.text = vec![Entry {
+            dir: Dir::Rx,
+            line: Line::styled("--- cleared ---", Style::default().dim()),
+            rule: false,
+        }];
+        self.open_line = None;
+        self.data.clear();
+        self.data_offset = 0;
+        self.hex_rows = 0;
+        self.scroll_index = 0;
+        self.unseen = 0;
+        self.scroll_state = ScrollbarState::default();
+        self.update_search();
+    }
+
+    // following again goes straight back to the newest output
+    fn toggle_follow(&mut self) {
+        self.follow = !self.follow;
+        if self.follow {
+            self.scroll_to(0);
+        }
+    }
+
+    // all, received only, sent only
+    fn cycle_direction(&mut self) {
+        self.only = match self.only {
+            None => Some(Dir::Rx),
+            Some(Dir::Rx) => Some(Dir::Tx),
+            Some(Dir::Tx) => None,
+        };
+        self.scroll_to(0);
+        self.update_search();
+    }
+
+    fn toggle_mode(&mut self) {
+        self.display_mode = match self.display_mode {
+            DisplayMode::Text => DisplayMode::Hex,
+            DisplayMode::Hex => DisplayMode::Text,
+        };
+        self.scroll_to(0);
+        self.update_search();
+    }
+
+    fn max_scroll(&self) -> usize {
+        self.rows().saturating_sub(self.visible_rows)
+    }
+
+    fn scroll_to(&mut self, index: usize) {
+        self.scroll_index = index.min(self.max_scroll());
+        if self.scroll_index == 0 {
+            self.unseen = 0;
+        }
+    }
+
+    fn scroll_by(&mut self, delta: isize) {
+        self.scroll_to(self.scroll_index.saturating_add_signed(delta));
+    }
+
+    fn page(&self) -> isize {
+        self.visible_rows.max(1) as isize
+    }
+
+    // how many rows row_strings gives, without building them
+    fn row_count(&self) -> usize {
+        match self.display_mode {
+            DisplayMode::Text => self.visible().count(),
+            DisplayMode::Hex => self.hex_rows,
+        }
+    }
+
+    // rendered rows in the current display mode, oldest first
+    fn row_strings(&self) -> Vec<String> {
+        match self.display_mode {
+            DisplayMode::Text => self
+                .visible()
+                .map(|e| {
+                    if self.show_controls {
+                        line_text(&visible_controls(&e.line))
+                    } else {
+                        line_text(&e.line)
+                    }
+                })
+                .collect(),
+            DisplayMode::Hex => {
+                let mut rows: Vec<_> = hex_dump(&self.data, self.data_offset).collect();
+                rows.reverse();
+                rows
+            }
+        }
+    }
+
+    // what the current view shows, oldest first, with sent lines marked
+    fn export(&self) -> String {
+        let mut out = String::new();
+        match self.display_mode {
+            DisplayMode::Text => {
+                for e in self.visible() {
+                    if e.dir == Dir::Tx {
+                        out.push_str("> ");
+                    }
+                    out.push_str(&line_text(&e.line));
+                    out.push('\n');
+                }
+            }
+            DisplayMode::Hex => {
+                for row in self.row_strings() {
+                    out.push_str(&row);
+                    out.push('\n');
+                }
+            }
+        }
+        out
+    }
+
+    // recomputes matches and jumps to the most recent one
+    fn update_search(&mut self) {
+        let rows = self.row_strings();
+        let Some(search) = self.search.as_mut() else {
+            return;
+        };
+        search.matches = rows
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| !search.ranges(r).is_empty())
+            .map(|(i, _)| i)
+            .collect();
+        search.current = search.matches.len().saturating_sub(1);
+        self.show_match();
+    }
+
+    fn step_search(&mut self, older: bool) {
+        let Some(search) = self.search.as_mut() else {
+            return;
+        };
+        let len = search.matches.len();
+        if len == 0 {
+            return;
+        }
+        search.current = if older {
+            (search.current + len - 1) % len
+        } else {
+            (search.current + 1) % len
+        };
+        self.show_match();
+    }
+
+    fn show_match(&mut self) {
+        let Some(row) = self.search.as_ref().and_then(|s| s.matches.get(s.current)) else {
+            return;
+        };
+        let from_bottom = self.rows_below(*row);
+        self.scroll_to(from_bottom.saturating_sub(self.visible_rows / 2));
+    }
+
+    // screen rows after the given row, which counts from the oldest
+    fn rows_below(&self, row: usize) -> usize {
+        match self.display_mode {
+            DisplayMode::Text if self.wrap => self
+                .visible()
+                .skip(row + 1)
+                .map(|e| self.line_rows(e))
+                .sum(),
+            _ => self.rows().saturating_sub(row + 1),
+        }
+    }
+
+    // scrolls only as far as needed to bring the row into view
+    fn reveal(&mut self, row: usize) {
+        let from_bottom = self.rows_below(row);
+        if from_bottom < self.scroll_index {
+            self.scroll_to(from_bottom);
+        } else if from_bottom >= self.scroll_index + self.visible_rows {
+            self.scroll_to(from_bottom + 1 - self.visible_rows.max(1));
+        }
+    }
+
+    // starts on the newest row in view
+    fn start_selection(&mut self) {
+        let rows = self.row_count();
+        if rows == 0 {
+            return;
+        }
+        let cursor = if self.wrap && self.display_mode == DisplayMode::Text {
+            rows - 1
+        } else {
+            rows.saturating_sub(self.scroll_index + 1)
+        };
+        self.selection = Some(Selection {
+            cursor,
+            anchor: None,
+        });
+        self.reveal(cursor);
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        let last = self.row_count().saturating_sub(1);
+        let Some(sel) = self.selection.as_mut() else {
+            return;
+        };
+        sel.cursor = sel.cursor.saturating_add_signed(delta).min(last);
+        let cursor = sel.cursor;
+        self.reveal(cursor);
+    }
+
+    fn selected_text(&self) -> Option<String> {
+        let sel = self.selection?;
+        let rows = self.row_strings();
+        let picked = rows.get(sel.range())?;
+        Some(picked.join("\n"))
+    }
 }
 
 impl EventListener for Dashboard {
@@ -51,58 +901,424 @@ impl EventListener for Dashboard {
             }
             Crossterm(c) => self.handle_term(c),
             Serial(s) => self.handle_serial(s),
+            GuiEvent::SerialConfig(config) => {
+                self.status.config = config.summary();
+                self.status.reset = config.reset;
+                false
+            }
+            GuiEvent::Capture(path) => {
+                self.status.capture.clone_from(path);
+                true
+            }
+            GuiEvent::SendLine(line) => {
+                self.term_input = format!("{}{}", line, self.status.line_ending.as_str());
+                self.send_serial();
+                true
+            }
+            GuiEvent::Connecting(path) => {
+                self.status.connecting = path.clone().map(|p| (p, Instant::now()));
+                true
+            }
+            GuiEvent::Reflashing(r) => {
+                self.status.reflashing = *r;
+                true
+            }
             GuiEvent::SerialDone => false,
         }
     }
 }
 
 impl Dashboard {
-    pub fn new(to_app: Messenger) -> Self {
-        Self {
+    pub fn new(to_app: Messenger, opts: TerminalOptions) -> Self {
+        let view = opts.view;
+        let mut dash = Self {
             alive: true,
             term_input: Default::default(),
-            term_state: Default::default(),
-            status: Default::default(),
+            hex_input: view.hex_input,
+            raw: false,
+            history: Default::default(),
+            term_state: TerminalStatus {
+                highlights: opts.highlights,
+                wrap: opts.wrap,
+                max_line_len: opts.max_line_len,
+                tabstop: opts.tabstop,
+                splitter: LineSplitter::new(opts.framing),
+                scrollback: opts.scrollback,
+                follow: opts.follow,
+                display_mode: view.display_mode,
+                show_controls: view.show_controls,
+                only: view.direction,
+                ..Default::default()
+            },
+            status: Status {
+                line_ending: opts.line_ending,
+                log_level: view.log_level,
+                ..Default::default()
+            },
+            keys: opts.keys,
+            macros: opts.macros,
+            triggers: Triggers::new(opts.triggers),
+            focused: true,
+            dim_unfocused: opts.dim_unfocused,
+            saved_view: ViewPrefs::default(),
+            marker_label: None,
+            response_timeout: Duration::from_millis(opts.response_timeout_ms),
             to_app,
+        };
+        dash.saved_view = dash.view();
+        dash
+    }
+
+    fn view(&self) -> ViewPrefs {
+        ViewPrefs {
+            display_mode: self.term_state.display_mode,
+            line_ending: self.status.line_ending,
+            hex_input: self.hex_input,
+            wrap: self.term_state.wrap,
+            show_controls: self.term_state.show_controls,
+            direction: self.term_state.only,
+            log_level: self.status.log_level,
         }
     }
 
+    // written out whenever a key changes how things are shown
+    fn save_view(&mut self) {
+        let view = self.view();
+        if view == self.saved_view {
+            return;
+        }
+        if let Err(e) = view.save() {
+            self.to_app.log(
+                Severity::Error,
+                format!("Unable to save view settings: {}", e),
+            );
+        }
+        self.saved_view = view;
+    }
+
+    /// Whether keys are being sent to the device as typed, in which case
+    /// the app-wide bindings are off too.
+    pub fn raw(&self) -> bool {
+        self.raw
+    }
+
     fn handle_term(&mut self, e: &crossterm::event::Event) -> bool {
-        use crossterm::event::Event::Key;
-        if let Key(k) = e {
-            self.handle_keybinds(*k);
+        use crossterm::event::Event::{FocusGained, FocusLost, Key, Paste};
+        if let Key(_) = e {
+            self.status.alert = None;
+        }
+        match e {
+            FocusGained => self.focused = true,
+            FocusLost => self.focused = false,
+            Key(k) if self.raw => self.handle_raw(*k),
+            Key(k) => {
+                self.handle_keybinds(*k);
+                self.save_view();
+            }
+            Paste(s) if self.raw => self.send_raw(s.clone()),
+            Paste(s) => self.handle_paste(s),
+            _ => {}
+        }
+        true
+    }
+
+    // While a marker label is typed text keys edit it, enter inserts the
+    // marker and backspacing past the start drops it.
+    fn handle_marker_keys(&mut self, event: KeyEvent) -> bool {
+        use crossterm::event::KeyCode::{Backspace, Char, Enter};
+        let Some(label) = self.marker_label.as_mut() else {
+            return false;
+        };
+        match (event.modifiers, event.code) {
+            (KeyModifiers::NONE | KeyModifiers::SHIFT, Char(c)) => label.push(c),
+            (KeyModifiers::NONE, Backspace) => {
+                if label.pop().is_none() {
+                    self.marker_label = None;
+                }
+            }
+            (KeyModifiers::NONE, Enter) => {
+                let label = take(label);
+                self.marker_label = None;
+                self.insert_marker(label);
+            }
+            _ => return false,
         }
         true
     }
 
+    fn insert_marker(&mut self, label: String) {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default();
+        let time = time_of_day(now);
+        if label.is_empty() {
+            self.term_state.push_rule(&time);
+        } else {
+            self.term_state.push_rule(&format!("{} {}", time, label));
+        }
+        self.to_app.send_app(AppEvent::Marker(label));
+    }
+
+    // While the query is typed all text keys edit it. Afterwards n/N step
+    // between matches and any other text key leaves search mode.
+    fn handle_search_keys(&mut self, event: KeyEvent) -> bool {
+        use crossterm::event::KeyCode::{Backspace, Char, Enter};
+        let state = &mut self.term_state;
+        let Some(search) = state.search.as_mut() else {
+            return false;
+        };
+        match (event.modifiers, event.code) {
+            (KeyModifiers::ALT, Char('/')) => state.search = None,
+            (KeyModifiers::ALT, Char('c')) => {
+                search.case_sensitive = !search.case_sensitive;
+                state.update_search();
+            }
+            (KeyModifiers::NONE | KeyModifiers::SHIFT, Char(c)) if search.editing => {
+                search.query.push(c);
+                state.update_search();
+            }
+            (KeyModifiers::NONE, Backspace) if search.editing => {
+                search.query.pop();
+                state.update_search();
+            }
+            (KeyModifiers::NONE, Enter) if search.editing => search.editing = false,
+            (KeyModifiers::NONE, Char('n')) => state.step_search(true),
+            (KeyModifiers::SHIFT, Char('N')) => state.step_search(false),
+            (_, Char(_) | Backspace | Enter) => {
+                state.search = None;
+                return false;
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    // Arrows and page keys move the cursor, v marks the other end of the
+    // range, y or enter copy. Any other text key leaves selection mode.
+    fn handle_select_keys(&mut self, event: KeyEvent) -> bool {
+        use crossterm::event::KeyCode::{Char, Down, End, Enter, Home, PageDown, PageUp, Up};
+        let state = &mut self.term_state;
+        if self.keys.lookup(&event) == Some(Action::Select) {
+            state.selection = None;
+            return true;
+        }
+        match (event.modifiers, event.code) {
+            (KeyModifiers::NONE, Up) => state.move_selection(-1),
+            (KeyModifiers::NONE, Down) => state.move_selection(1),
+            (KeyModifiers::NONE, PageUp) => state.move_selection(-state.page()),
+            (KeyModifiers::NONE, PageDown) => state.move_selection(state.page()),
+            (KeyModifiers::NONE, Home) => state.move_selection(isize::MIN),
+            (KeyModifiers::NONE, End) => state.move_selection(isize::MAX),
+            (KeyModifiers::NONE, Char('v' | ' ')) => {
+                if let Some(sel) = state.selection.as_mut() {
+                    sel.anchor = match sel.anchor {
+                        Some(_) => None,
+                        None => Some(sel.cursor),
+                    };
+                }
+            }
+            (KeyModifiers::NONE, Char('y') | Enter) => {
+                let text = state.selected_text().unwrap_or_default();
+                state.selection = None;
+                let lines = text.lines().count();
+                match crate::clipboard::copy(text) {
+                    Ok(()) => self
+                        .status
+                        .log
+                        .push((Severity::Info, format!("Copied {} lines", lines))),
+                    Err(e) => self
+                        .status
+                        .log
+                        .push((Severity::Error, format!("Copy failed: {}", e))),
+                }
+            }
+            (_, Char(_)) => {
+                state.selection = None;
+                return false;
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    // only the binding that leaves raw mode is still honoured
+    fn handle_raw(&mut self, event: KeyEvent) {
+        if self.keys.lookup(&event) == Some(Action::ToggleRaw) {
+            self.raw = false;
+            return;
+        }
+        if let Some(bytes) = encode_key(&event) {
+            self.send_raw(bytes);
+        }
+    }
+
+    // the device is expected to echo, so nothing is added to the scrollback
+    fn send_raw(&mut self, text: String) {
+        use crate::event::{AppEvent::SendSerial, ToSerialData::Data};
+        self.to_app.send_app(SendSerial(Data(text)));
+    }
+
+    // Every complete line of a paste is sent right away, any trailing
+    // partial line is left in the input for further editing.
+    fn handle_paste(&mut self, text: &str) {
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        let mut lines = text.split('\n').peekable();
+        while let Some(line) = lines.next() {
+            self.term_input.push_str(line);
+            if lines.peek().is_some() {
+                self.submit_line();
+            }
+        }
+    }
+
+    // The input line always has focus, so plain Up/Down belong to the input
+    // history. Scrolling the terminal pane uses the page/jump keys instead.
     fn handle_keybinds(&mut self, event: KeyEvent) -> bool {
+        if self.term_state.search.is_some() && self.handle_search_keys(event) {
+            return true;
+        }
+        if self.marker_label.is_some() && self.handle_marker_keys(event) {
+            return true;
+        }
+        if self.term_state.selection.is_some() && self.handle_select_keys(event) {
+            return true;
+        }
         let KeyEvent {
             code, modifiers, ..
         } = event;
         use AppEvent::SendSerial;
         use ToSerialData::{DTR, RTS};
         use crossterm::event::{
-            KeyCode::{Backspace, Char, Enter},
+            KeyCode::{Backspace, Char, Down, End, Enter, F, Home, PageDown, PageUp, Up},
             KeyEvent,
         };
+        match self.keys.lookup(&event) {
+            Some(Action::ToggleDtr) => {
+                self.status.dtr = !self.status.dtr;
+                self.to_app.send_app(SendSerial(DTR(self.status.dtr)));
+                return true;
+            }
+            Some(Action::ToggleRts) => {
+                self.status.rts = !self.status.rts;
+                self.to_app.send_app(SendSerial(RTS(self.status.rts)));
+                return true;
+            }
+            Some(Action::ResetPulse) => {
+                let reset = self.status.reset;
+                (self.status.dtr, self.status.rts) =
+                    reset.end_state(self.status.dtr, self.status.rts);
+                self.to_app
+                    .send_app(SendSerial(ToSerialData::ResetPulse(reset)));
+                return true;
+            }
+            Some(Action::ToggleHexView) => {
+                self.term_state.toggle_mode();
+                return true;
+            }
+            Some(Action::Select) => {
+                self.term_state.start_selection();
+                return true;
+            }
+            Some(Action::Search) => {
+                self.term_state.search = Some(Search {
+                    editing: true,
+                    ..Default::default()
+                });
+                return true;
+            }
+            Some(Action::ToggleRaw) => {
+                self.raw = true;
+                return true;
+            }
+            Some(Action::ToggleHexInput) => {
+                self.hex_input = !self.hex_input;
+                return true;
+            }
+            Some(Action::CycleLineEnding) => {
+                self.status.line_ending = self.status.line_ending.next();
+                return true;
+            }
+            Some(Action::ToggleCapture) => {
+                self.to_app.send_app(AppEvent::ToggleCapture);
+                return true;
+            }
+            Some(Action::ClearScrollback) => {
+                self.term_state.clear();
+                self.status.reset_counters();
+                return true;
+            }
+            Some(Action::CycleLogLevel) => {
+                self.status.log_level = match self.status.log_level {
+                    None => Some(Severity::Info),
+                    Some(Severity::Info) => Some(Severity::Error),
+                    _ => None,
+                };
+                return true;
+            }
+            Some(Action::ToggleFollow) => {
+                self.term_state.toggle_follow();
+                return true;
+            }
+            Some(Action::CycleDirection) => {
+                self.term_state.cycle_direction();
+                return true;
+            }
+            Some(Action::ExportScrollback) => {
+                self.export_scrollback();
+                return true;
+            }
+            Some(Action::ToggleWrap) => {
+                self.term_state.toggle_wrap();
+                return true;
+            }
+            Some(Action::SendAndWait) => {
+                self.submit_query();
+                return true;
+            }
+            Some(Action::InsertMarker) => {
+                // pressed again while typing the label, it cancels
+                self.marker_label = match self.marker_label {
+                    Some(_) => None,
+                    None => Some(String::new()),
+                };
+                return true;
+            }
+            Some(Action::ToggleControls) => {
+                self.term_state.show_controls = !self.term_state.show_controls;
+                self.term_state.update_search();
+                return true;
+            }
+            // application wide, handled by App
+            _ => {}
+        }
         match (modifiers, code) {
             (KeyModifiers::NONE | KeyModifiers::SHIFT, Char(c)) => {
                 self.term_input.push(c);
             }
+            (KeyModifiers::NONE, F(n)) if self.macros.contains_key(&n) => self.send_macro(n),
             (KeyModifiers::NONE, Backspace) => {
                 _ = self.term_input.pop();
             }
-            (KeyModifiers::NONE, Enter) => {
-                self.term_input.push('\n');
-                self.send_serial();
+            (KeyModifiers::NONE, Enter) => self.submit_line(),
+            // composes several lines to send together
+            (KeyModifiers::ALT, Enter) => self.term_input.push('\n'),
+            (KeyModifiers::NONE, Up) => {
+                self.history.prev(&mut self.term_input, &mut self.hex_input);
             }
-            (KeyModifiers::CONTROL, Char('d')) => {
-                self.status.dtr = !self.status.dtr;
-                self.to_app.send_app(SendSerial(DTR(self.status.dtr)));
+            (KeyModifiers::NONE, Down) => {
+                self.history.next(&mut self.term_input, &mut self.hex_input);
             }
-            (KeyModifiers::CONTROL, Char('r')) => {
-                self.status.rts = !self.status.rts;
-                self.to_app.send_app(SendSerial(RTS(self.status.rts)));
+            (KeyModifiers::NONE, PageUp) => {
+                self.term_state.scroll_by(self.term_state.page());
+            }
+            (KeyModifiers::NONE, PageDown) => {
+                self.term_state.scroll_by(-self.term_state.page());
+            }
+            (KeyModifiers::NONE, Home) => {
+                self.term_state.scroll_to(usize::MAX);
+            }
+            (KeyModifiers::NONE, End) => {
+                self.term_state.scroll_to(0);
             }
             _ => return false,
         }
@@ -112,26 +1328,215 @@ impl Dashboard {
     fn handle_serial(&mut self, se: &FromSerialData) -> bool {
         match se {
             FromSerialData::Data(items) => {
-                for line in String::from_utf8_lossy(items).split_inclusive('\n') {
-                    match self.term_state.text.last_mut() {
-                        Some(l) if l.ends_with('\n') => {
-                            self.term_state.text.push(line.into());
-                        }
-                        Some(l) => {
-                            l.push_str(line);
-                        }
-                        None => self.term_state.text.push(line.into()),
-                    }
+                self.status.idle = false;
+                self.status.rx.record(items.len());
+                self.term_state.push_data(items);
+                self.check_triggers(items);
+            }
+            FromSerialData::Status(modem) => self.status.modem = *modem,
+            FromSerialData::Sent(n) => self.status.tx.record(*n),
+            FromSerialData::SendComplete(n) => {
+                self.status.xmodem = None;
+                self.status
+                    .log
+                    .push((Severity::Info, format!("Sent {} bytes", n)));
+            }
+            FromSerialData::XmodemProgress(n, total) => {
+                if self.status.xmodem.is_none() {
+                    self.status
+                        .log
+                        .push((Severity::Info, format!("XMODEM: sending {} blocks", total)));
+                }
+                self.status.xmodem = Some((*n, *total));
+            }
+            FromSerialData::SendFailed(e) => {
+                self.status.xmodem = None;
+                // the receiver backing out is not our failure
+                let severity = match e {
+                    SerialError::Cancelled(_) => Severity::Info,
+                    _ => Severity::Error,
+                };
+                self.status
+                    .log
+                    .push((severity, format!("Send failed: {}", e)));
+            }
+            FromSerialData::TxBlocked(b) => {
+                self.status.tx_blocked = *b;
+                if *b {
+                    self.status.log.push((
+                        Severity::Debug,
+                        "Device is not accepting data, waiting".into(),
+                    ));
                 }
             }
-            FromSerialData::Connect(s) => self.status.device = s.clone(),
-            FromSerialData::Gone => self.status.device.clear(),
+            FromSerialData::Idle(d) => {
+                self.status.idle = true;
+                self.status.log.push((
+                    Severity::Info,
+                    format!("No data received for {}s", d.as_secs()),
+                ));
+            }
+            FromSerialData::Connect(s) => {
+                if self.status.reflashing {
+                    self.status.reflashing = false;
+                    self.status.reflashes += 1;
+                    let n = self.status.reflashes;
+                    self.term_state.push_marker(Line::styled(
+                        format!("--- reflash {}, reconnected to {} ---", n, s),
+                        Style::default().dim(),
+                    ));
+                }
+                self.status.device = s.clone();
+                self.status.connected_at = Some(Instant::now());
+                self.status.reset_counters();
+            }
+            FromSerialData::Lost(e) => {
+                self.status
+                    .log
+                    .push((Severity::Error, format!("Connection lost: {}", e)));
+                self.status.clear_connection();
+            }
+            FromSerialData::Response { sent, received } => self.log_response(sent, received),
+            FromSerialData::Configured(config) => {
+                self.status.config = config.summary();
+                self.status.log.push((
+                    Severity::Info,
+                    format!("Port changed to {}", config.summary()),
+                ));
+            }
+            FromSerialData::Gone => self.status.clear_connection(),
         };
         true
     }
 
+    fn check_triggers(&mut self, data: &[u8]) {
+        let mut bell = false;
+        for (trigger, line) in self.triggers.feed(data) {
+            bell |= trigger.bell;
+            if trigger.flash {
+                self.status.alert = Some(trigger.pattern().to_string());
+            }
+            self.status.log.push((
+                Severity::Info,
+                format!("Trigger {}: {}", trigger.pattern(), line),
+            ));
+            trigger.run_command(&line, &self.to_app);
+        }
+        // the terminal beeps on BEL without moving the cursor
+        if bell {
+            use std::io::Write;
+            let mut out = std::io::stdout();
+            _ = out.write_all(b"\x07").and_then(|_| out.flush());
+        }
+    }
+
+    // What enter sends for the typed input: the decoded hex, or the text
+    // with the line ending after every composed line, not only the last.
+    fn encode_input(&self) -> Result<Vec<u8>> {
+        if self.hex_input {
+            return parse_hex(&self.term_input);
+        }
+        let ending = self.status.line_ending.as_str();
+        Ok(self
+            .term_input
+            .split('\n')
+            .map(|line| format!("{}{}", line, ending))
+            .collect::<String>()
+            .into_bytes())
+    }
+
+    fn submit_line(&mut self) {
+        let bytes = match self.encode_input() {
+            Ok(bytes) if self.hex_input && bytes.is_empty() => return,
+            Ok(bytes) => bytes,
+            Err(e) => return self.to_app.log(Severity::Error, e.to_string()),
+        };
+        self.history.push(&self.term_input, self.hex_input);
+        self.term_state.push_sent(&take(&mut self.term_input));
+        let data = if self.hex_input {
+            ToSerialData::RawBytes(bytes)
+        } else {
+            ToSerialData::Data(String::from_utf8_lossy(&bytes).into_owned())
+        };
+        self.to_app.send_app(AppEvent::SendSerial(data));
+    }
+
+    // Like enter, but what the device sends back within the response
+    // timeout is also collected and logged.
+    fn submit_query(&mut self) {
+        let bytes = match self.encode_input() {
+            Ok(bytes) if bytes.is_empty() => return,
+            Ok(bytes) => bytes,
+            Err(e) => return self.to_app.log(Severity::Error, e.to_string()),
+        };
+        self.history.push(&self.term_input, self.hex_input);
+        self.term_state.push_sent(&take(&mut self.term_input));
+        self.to_app
+            .send_app(AppEvent::SendAndWait(bytes, self.response_timeout));
+    }
+
+    // a line of the log each, so a reply of several lines reads as it came
+    fn log_response(&mut self, sent: &[u8], received: &[u8]) {
+        let sent = String::from_utf8_lossy(sent);
+        let sent = sent.trim_end_matches(['\r', '\n']);
+        let received = String::from_utf8_lossy(received);
+        let lines: Vec<_> = received
+            .lines()
+            .map(|l| l.trim_end_matches('\r'))
+            .filter(|l| !l.is_empty())
+            .collect();
+        let log = &mut self.status.log;
+        if lines.is_empty() {
+            log.push((Severity::Info, format!("No response to {}", sent)));
+            return;
+        }
+        log.push((Severity::Info, format!("Response to {}:", sent)));
+        for line in lines {
+            log.push((Severity::Info, format!("  ⇐ {}", line)));
+        }
+    }
+
+    // sent right away, leaving whatever is being typed alone
+    fn send_macro(&mut self, key: u8) {
+        let Some(m) = self.macros.get(&key) else {
+            return;
+        };
+        let ending = m.line_ending.unwrap_or(self.status.line_ending);
+        let mut bytes = m.bytes.clone();
+        bytes.extend_from_slice(ending.as_str().as_bytes());
+        self.term_state.push_sent(&String::from_utf8_lossy(&bytes));
+        let data = match String::from_utf8(bytes) {
+            Ok(text) => ToSerialData::Data(text),
+            Err(e) => ToSerialData::RawBytes(e.into_bytes()),
+        };
+        self.to_app.send_app(AppEvent::SendSerial(data));
+    }
+
+    // a one-off snapshot, unlike capture which records from when it starts
+    fn export_scrollback(&mut self) {
+        let secs = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let kind = match self.term_state.display_mode {
+            DisplayMode::Text => "scrollback",
+            DisplayMode::Hex => "hexdump",
+        };
+        let path = PathBuf::from(format!("seterm-{}-{}.txt", kind, secs));
+        match std::fs::write(&path, self.term_state.export()) {
+            Ok(()) => self
+                .to_app
+                .log(Severity::Info, format!("Saved {}", path.display())),
+            Err(e) => self.to_app.log(
+                Severity::Error,
+                format!("Unable to write {}: {}", path.display(), e),
+            ),
+        }
+    }
+
     fn send_serial(&mut self) {
         use crate::event::{AppEvent::SendSerial, ToSerialData::Data};
+        self.term_state.push_sent(&self.term_input);
         self.to_app
             .send_app(SendSerial(Data(take(&mut self.term_input))));
     }
@@ -141,43 +1546,132 @@ impl Drawable for Dashboard {
     fn alive(&self) -> bool {
         self.alive
     }
-    #[instrument(skip(frame))]
-    fn draw(&mut self, area: Rect, frame: &mut Frame) {
+    #[instrument(skip(frame, theme))]
+    fn draw(&mut self, area: Rect, frame: &mut Frame, theme: &Theme) {
         trace!("Drawing dashboard");
         use ratatui::layout::Direction;
-        let a = Layout::default()
+        // areas can come out empty when the terminal is shrunk very small,
+        // every block below copes with that by drawing less
+        let [bigger, status_area] = Layout::default()
             .direction(Direction::Horizontal)
             .constraints([Constraint::Percentage(70), Constraint::Min(20)])
-            .split(area);
-        let [bigger, status_area] = &*a else {
-            panic!("Bigger area should have 1 item");
-        };
+            .areas(area);
 
-        let left_area = Layout::default()
-            .direction(Direction::Vertical)
-            .constraints([Constraint::Percentage(90), Constraint::Min(1)])
-            .split(*bigger);
-
-        let [term, input] = &*left_area else {
-            // really should not happen unless above fails somehow
-            panic!("Layout should have 2 items only");
+        // the input grows to show composed lines, up to half the height
+        let composed = self.term_input.matches('\n').count() as u16 + 1;
+        let input_height = if composed > 1 && !self.raw {
+            Constraint::Length((composed + 2).min(bigger.height / 2))
+        } else {
+            Constraint::Min(1)
         };
+        let [term, input] = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Percentage(90), input_height])
+            .areas(bigger);
         let buf = frame.buffer_mut();
 
-        render_terminal_block(&mut self.term_state, *term, buf);
+        render_terminal_block(&mut self.term_state, term, buf, theme);
         trace!("Drawing terminal");
-        render_input_block(&self.term_input, *input, buf);
+        match (&self.term_state.search, &self.marker_label) {
+            (Some(search), _) => render_search_block(search, input, buf, theme),
+            (None, Some(label)) => render_marker_block(label, input, buf, theme),
+            (None, None) if self.raw => render_raw_block(&self.keys, input, buf),
+            (None, None) => render_input_block(&self.term_input, self.hex_input, input, buf, theme),
+        }
         trace!("Drawing input");
-        render_status_block(&self.status, *status_area, buf);
+        render_status_block(&self.status, status_area, buf, theme);
         trace!("Drawing status");
+        if self.dim_unfocused && !self.focused {
+            let faded = Style::new()
+                .add_modifier(Modifier::DIM)
+                .remove_modifier(Modifier::SLOW_BLINK);
+            buf.set_style(area, faded);
+        }
+    }
+}
+
+fn render_input_block(input: &str, hex: bool, area: Rect, frame: &mut Buffer, theme: &Theme) {
+    let mut text: Text = input.split('\n').map(Line::raw).collect();
+    if let Some(last) = text.lines.last_mut() {
+        last.push_span(theme.cursor());
     }
+    let mut block = if hex {
+        Block::bordered().title("Hex bytes")
+    } else {
+        Block::bordered()
+    };
+    let lines = text.lines.len();
+    if lines > 1 {
+        block = block.title(format!("{} lines, enter sends all", lines));
+    }
+    // keeps the line being typed in view
+    let hidden = lines.saturating_sub(block.inner(area).height.into());
+    Paragraph::new(text)
+        .block(block)
+        .left_aligned()
+        .scroll((hidden as u16, 0))
+        .render(area, frame);
+}
+
+fn render_raw_block(keys: &KeyMap, area: Rect, frame: &mut Buffer) {
+    let leave = keys
+        .help_rows()
+        .into_iter()
+        .find(|(_, d)| *d == Action::ToggleRaw.description())
+        .map(|(k, _)| k)
+        .unwrap_or_default();
+    Paragraph::new(format!("Keys are sent as typed, {} to leave", leave))
+        .style(Style::new().dim())
+        .block(Block::bordered().title("Raw"))
+        .render(area, frame);
+}
+
+/// Parses whitespace separated hex bytes such as `1b 5b 41` or `0x1B5B41`.
+pub fn parse_hex(text: &str) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    for word in text.split_whitespace() {
+        let digits = word
+            .strip_prefix("0x")
+            .or_else(|| word.strip_prefix("0X"))
+            .unwrap_or(word);
+        if digits.is_empty() || digits.len() % 2 != 0 {
+            return Err(eyre!("Invalid hex byte: {}", word));
+        }
+        for i in (0..digits.len()).step_by(2) {
+            let byte = digits
+                .get(i..i + 2)
+                .and_then(|d| u8::from_str_radix(d, 16).ok())
+                .ok_or_else(|| eyre!("Invalid hex byte: {}", word))?;
+            bytes.push(byte);
+        }
+    }
+    Ok(bytes)
+}
+
+fn render_marker_block(label: &str, area: Rect, frame: &mut Buffer, theme: &Theme) {
+    let line = Line::from(vec![Span::raw(label), theme.cursor()]);
+    Paragraph::new(line)
+        .block(Block::bordered().title("Marker label, enter to insert"))
+        .left_aligned()
+        .render(area, frame);
 }
 
-fn render_input_block(input: &str, area: Rect, frame: &mut Buffer) {
-    let cursor = Span::raw("█").style(Style::default().add_modifier(Modifier::SLOW_BLINK));
-    let line = Line::from(vec![Span::raw(input), cursor]);
-    Paragraph::new(Text::from(line))
-        .block(Block::bordered())
+fn render_search_block(search: &Search, area: Rect, frame: &mut Buffer, theme: &Theme) {
+    let mut spans = vec![Span::raw("/"), Span::raw(search.query.as_str())];
+    if search.editing {
+        spans.push(theme.cursor());
+    }
+    let count = match search.matches.len() {
+        0 => "no matches".to_string(),
+        n => format!("{}/{}", search.current + 1, n),
+    };
+    let case = if search.case_sensitive {
+        "case sensitive"
+    } else {
+        "ignore case"
+    };
+    Paragraph::new(Line::from(spans))
+        .block(Block::bordered().title(format!("Search: {}, {}", count, case)))
         .left_aligned()
         .render(area, frame);
 }
@@ -193,62 +1687,382 @@ where
     }
 }
 
-fn render_status_block(stat: &Status, area: Rect, frame: &mut Buffer) {
+fn render_status_block(stat: &Status, area: Rect, frame: &mut Buffer, theme: &Theme) {
     let [stats, log_area] =
-        &*Layout::vertical([Constraint::Percentage(30), Constraint::Percentage(70)]).split(area)
-    else {
-        panic!("Status constraint failed!");
-    };
+        Layout::vertical([Constraint::Percentage(30), Constraint::Percentage(70)]).areas(area);
 
-    const ON: &str = "●";
-    const OFF: &str = "○";
-    let rts = if stat.rts { ON } else { OFF };
-    let dtr = if stat.dtr { ON } else { OFF };
+    let led = |b: bool| theme.led(b);
 
-    let log_block = Block::bordered();
-    let log_zone = log_block.inner(*log_area);
-    log_block.render(*log_area, frame);
+    let shown = |sev: &Severity| stat.log_level.is_none_or(|l| *sev <= l);
+    let hidden = stat.log.iter().filter(|(sev, _)| !shown(sev)).count();
+    let log_block = match stat.log_level {
+        None | Some(Severity::Debug) => Block::bordered(),
+        Some(Severity::Info) => Block::bordered().title(format!("Log: info ({} hidden)", hidden)),
+        Some(Severity::Error) => {
+            Block::bordered().title(format!("Log: errors ({} hidden)", hidden))
+        }
+    };
+    let log_zone = log_block.inner(log_area);
+    log_block.render(log_area, frame);
     let lines = stat
         .log
         .iter()
         .rev()
-        .map(|(sev, str)| render_text(*sev, str));
+        .filter(|(sev, _)| shown(sev))
+        .map(|(sev, str)| render_text(*sev, str, theme));
     render_log(lines, log_zone, frame);
 
-    let status = format!("RTS: {}\nDTR: {}\nConnected: {}", rts, dtr, stat.device,);
+    let capture = stat
+        .capture
+        .as_ref()
+        .map(|p| p.display().to_string())
+        .unwrap_or("off".into());
+    let status = format!(
+        "RTS: {} DTR: {} CTS: {}\nDSR: {} RI: {} CD: {}\n\
+        Connected: {}{}{}\n{}\nLine ending: {}\nCapture: {}\nRX: {}\nTX: {}{}{}",
+        led(stat.rts),
+        led(stat.dtr),
+        led(stat.modem.cts),
+        led(stat.modem.dsr),
+        led(stat.modem.ri),
+        led(stat.modem.cd),
+        match stat.connecting {
+            Some((ref path, since)) =>
+                format!("connecting to {} {}", path.display(), spinner(since)),
+            None if stat.reflashing && stat.device.is_empty() => "reconnecting…".into(),
+            None => stat.device.clone(),
+        },
+        stat.connected_at
+            .map(|at| format!(" ({})", elapsed(at.elapsed())))
+            .unwrap_or_default(),
+        if stat.idle { " (idle)" } else { "" },
+        if stat.device.is_empty() {
+            ""
+        } else {
+            &stat.config
+        },
+        stat.line_ending,
+        capture,
+        stat.rx,
+        stat.tx,
+        if stat.tx_blocked { " (blocked)" } else { "" },
+        stat.xmodem
+            .map(|(n, total)| format!("\nXMODEM: block {} of {}", n, total))
+            .unwrap_or_default()
+    );
+
+    let block = match stat.alert {
+        Some(ref pattern) => Block::bordered()
+            .border_style(theme.highlight())
+            .title(format!("Trigger: {}", pattern)),
+        None => Block::bordered(),
+    };
+    let status_block = Paragraph::new(status).block(block).centered();
+    status_block.render(stats, frame);
+}
+
+// turns once a redraw, which is at least every second
+fn spinner(since: Instant) -> char {
+    const FRAMES: [char; 4] = ['|', '/', '-', '\\'];
+    FRAMES[(since.elapsed().as_millis() / 250) as usize % FRAMES.len()]
+}
 
-    let status_block = Paragraph::new(status).block(Block::bordered()).centered();
-    status_block.render(*stats, frame);
+// hours keep counting past a day
+fn elapsed(d: Duration) -> String {
+    let secs = d.as_secs();
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
 }
 
-fn render_text(sev: Severity, t: &str) -> Text<'_> {
+fn render_text<'a>(sev: Severity, t: &'a str, theme: &Theme) -> Text<'a> {
     let color = match sev {
-        Severity::Error => ratatui::style::Color::Red,
-        Severity::Info => ratatui::style::Color::default(),
-        Severity::Debug => ratatui::style::Color::LightGreen,
+        Severity::Error => theme.error,
+        Severity::Info => theme.info,
+        Severity::Debug => theme.debug,
     };
     Text::styled(t, Style::new().fg(color))
 }
 
-fn render_terminal_block(input: &mut TerminalStatus, area: Rect, frame: &mut Buffer) {
-    let scrollbar = Scrollbar::new(ScrollbarOrientation::VerticalRight).thumb_symbol("#");
-    input.scroll_state = input
-        .scroll_state
-        .content_length(input.text.len())
-        .position(input.text.len() - input.scroll_index);
-    <Scrollbar as StatefulWidget>::render(scrollbar, area, frame, &mut input.scroll_state);
-    let block = Block::bordered();
+fn render_terminal_block(
+    input: &mut TerminalStatus,
+    area: Rect,
+    frame: &mut Buffer,
+    theme: &Theme,
+) {
+    let mut block = match (input.display_mode, input.only) {
+        (DisplayMode::Text, None) => Block::bordered(),
+        (DisplayMode::Text, Some(Dir::Rx)) => Block::bordered().title("Received only"),
+        (DisplayMode::Text, Some(Dir::Tx)) => Block::bordered().title("Sent only"),
+        // the hex view only ever shows received bytes
+        (DisplayMode::Hex, _) => Block::bordered().title("Hex"),
+    };
+    if !input.follow {
+        let badge = format!("● {} new, not following", input.unseen);
+        block = block.title_bottom(Line::raw(badge).style(theme.highlight()).right_aligned());
+    } else if input.unseen > 0 {
+        block = block.title_bottom(Line::raw(format!("● {} new", input.unseen)).right_aligned());
+    }
     let text_area = block.inner(area);
+    input.visible_rows = text_area.height.into();
+    // the scrollbar sits on the border, so it takes nothing from the text
+    input.width = text_area.width.into();
+    input.scroll_index = input.scroll_index.min(input.max_scroll());
     block.render(area, frame);
-    let lines = input.text.iter().rev().skip(input.scroll_index);
-    render_log(lines, text_area, frame);
+
+    let scrollbar = Scrollbar::new(ScrollbarOrientation::VerticalRight)
+        .thumb_symbol(&theme.scrollbar_thumb)
+        .track_symbol(theme.scrollbar_track.as_deref());
+    let max_scroll = input.max_scroll();
+    input.scroll_state = input
+        .scroll_state
+        .content_length(max_scroll)
+        .position(max_scroll - input.scroll_index);
+    // ratatui panics on a scrollbar with no room
+    if !area.is_empty() {
+        <Scrollbar as StatefulWidget>::render(scrollbar, area, frame, &mut input.scroll_state);
+    }
+    let search = input.search.as_ref();
+    let highlight_search = |line: Line<'static>| match search {
+        Some(s) => s.highlight(&line, theme),
+        None => line,
+    };
+    match input.display_mode {
+        DisplayMode::Text => {
+            let rules = &input.highlights;
+            let show_controls = input.show_controls;
+            let open = input.open_entry();
+            // when wrapping, scroll_index counts screen rows instead
+            let skip = if input.wrap { 0 } else { input.scroll_index };
+            // selected rows and the newest row's index, only counted while selecting
+            let selected = input
+                .selection
+                .map(|s| (s.range(), input.row_count().saturating_sub(1)));
+            let lines = input.visible().rev().enumerate().skip(skip);
+            let lines = lines.map(|(i, e)| {
+                let mut line = match e.dir {
+                    Dir::Rx if !rules.is_empty() => highlight(rules, &e.line),
+                    _ => e.line.clone(),
+                };
+                if show_controls {
+                    line = visible_controls(&line);
+                }
+                let mut line = highlight_search(line);
+                if e.rule {
+                    line = rule(line, input.width).patch_style(Style::new().fg(theme.info).bold());
+                }
+                if open.is_some_and(|o| std::ptr::eq(o, e)) {
+                    line.push_span(Span::styled(PARTIAL, Style::new().dim()));
+                }
+                if selected
+                    .as_ref()
+                    .is_some_and(|(r, newest)| r.contains(&(newest - i)))
+                {
+                    line = select_style(&line, theme);
+                }
+                (e.dir, line)
+            });
+            let lines = lines.map(|(dir, line)| tag(dir, line, theme));
+            if input.wrap {
+                let width = input.width;
+                let rows = lines
+                    .flat_map(|l| wrap_line(l, width).into_iter().rev())
+                    .skip(input.scroll_index);
+                render_log(rows, text_area, frame);
+            } else {
+                render_log(lines, text_area, frame);
+            }
+        }
+        DisplayMode::Hex => {
+            let newest = input.hex_rows.saturating_sub(1);
+            let selected = input.selection.map(|s| s.range());
+            let lines = hex_dump(&input.data, input.data_offset)
+                .map(Line::raw)
+                .map(highlight_search)
+                .enumerate()
+                .skip(input.scroll_index)
+                .map(|(i, line)| {
+                    if selected.as_ref().is_some_and(|r| r.contains(&(newest - i))) {
+                        select_style(&line, theme)
+                    } else {
+                        line
+                    }
+                });
+            render_log(lines, text_area, frame);
+        }
+    }
+}
+
+// selected rows are drawn like the highlight, over any colors they had
+fn select_style(line: &Line, theme: &Theme) -> Line<'static> {
+    let len = line_text(line).len();
+    restyle(line, &[(0..len, theme.highlight())]).style(line.style.patch(theme.highlight()))
+}
+
+// Replaces tabs with spaces up to the next stop, counting from the column
+// the text starts at so a line split across reads still lines up.
+fn expand_tabs(text: &str, column: usize, tabstop: usize) -> Cow<'_, str> {
+    if tabstop == 0 || !text.contains('\t') {
+        return Cow::Borrowed(text);
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut col = column;
+    for c in text.chars() {
+        if c == '\t' {
+            let pad = tabstop - col % tabstop;
+            out.extend(std::iter::repeat_n(' ', pad));
+            col += pad;
+        } else {
+            out.push(c);
+            col += c.width().unwrap_or(0);
+        }
+    }
+    Cow::Owned(out)
+}
+
+const CONTINUATION: &str = "↪";
+
+// Splits a line into rows of at most `width` columns. Rows after the first
+// start with a continuation mark.
+fn wrap_line(line: Line<'static>, width: usize) -> Vec<Line<'static>> {
+    if width < 2 {
+        return vec![line];
+    }
+    let continued = || Line::from(Span::styled(CONTINUATION, Style::new().dim())).style(line.style);
+    let mut rows = vec![Line::default().style(line.style)];
+    let mut used = 0;
+    for span in &line.spans {
+        let mut piece = String::new();
+        for c in span.content.chars() {
+            let w = c.width().unwrap_or(0);
+            if used + w > width && used > 0 {
+                if !piece.is_empty() {
+                    let row = rows.last_mut().unwrap();
+                    row.push_span(Span::styled(take(&mut piece), span.style));
+                }
+                rows.push(continued());
+                used = 1;
+            }
+            piece.push(c);
+            used += w;
+        }
+        if !piece.is_empty() {
+            let row = rows.last_mut().unwrap();
+            row.push_span(Span::styled(piece, span.style));
+        }
+    }
+    rows
+}
+
+// Replaces control characters with caret notation (^G, ^[) or <0x85> for
+// the C1 range, dimmed so they stand apart from real text. Line splitting
+// already happened, and a carriage return before the newline is left alone.
+fn visible_controls(line: &Line) -> Line<'static> {
+    let mut out = Line::default().style(line.style);
+    let last = line.spans.len().saturating_sub(1);
+    for (i, span) in line.spans.iter().enumerate() {
+        let content = if i == last {
+            span.content.trim_end_matches('\r')
+        } else {
+            span.content.as_ref()
+        };
+        let mut text = String::new();
+        for c in content.chars() {
+            let escaped = match c as u32 {
+                b @ 0..0x20 => format!("^{}", char::from(b as u8 + 0x40)),
+                0x7f => "^?".into(),
+                b @ 0x80..0xa0 => format!("<0x{:02x}>", b),
+                _ => {
+                    text.push(c);
+                    continue;
+                }
+            };
+            if !text.is_empty() {
+                out.push_span(Span::styled(take(&mut text), span.style));
+            }
+            out.push_span(Span::styled(escaped, span.style.dim()));
+        }
+        if !text.is_empty() {
+            out.push_span(Span::styled(text, span.style));
+        }
+    }
+    out
+}
+
+// pads a marker with a rule either side, out to the width of the view
+fn rule(line: Line<'static>, width: usize) -> Line<'static> {
+    let side = "─".repeat(width.saturating_sub(line.width()) / 2);
+    let mut spans = vec![Span::raw(side.clone())];
+    spans.extend(line.spans);
+    spans.push(Span::raw(side));
+    Line::from(spans).style(line.style)
+}
+
+// sent lines are marked so they stand out from the device's echo
+fn tag(dir: Dir, line: Line<'static>, theme: &Theme) -> Line<'static> {
+    match dir {
+        Dir::Rx => line,
+        Dir::Tx => {
+            let sent = Style::new().fg(theme.sent);
+            let mut spans = vec![Span::styled("> ", sent.bold())];
+            spans.extend(line.spans.into_iter().map(|s| s.patch_style(sent)));
+            Line::from(spans)
+        }
+    }
+}
+
+/// Lazily produces `hexdump -C` style rows for every chunk, newest row first.
+/// Each chunk starts on a fresh row, prefixed with its offset in the stream.
+/// `offset` is where the first chunk starts, after anything already dropped.
+fn hex_dump(data: &[Vec<u8>], offset: usize) -> impl Iterator<Item = String> {
+    let total: usize = data.iter().map(Vec::len).sum();
+    data.iter()
+        .rev()
+        .scan(offset + total, |end, chunk| {
+            *end -= chunk.len();
+            Some((*end, chunk))
+        })
+        .flat_map(|(start, chunk)| {
+            chunk
+                .chunks(HEX_ROW_WIDTH)
+                .enumerate()
+                .rev()
+                .map(move |(i, row)| format_hex_row(start + i * HEX_ROW_WIDTH, row))
+        })
+}
+
+fn format_hex_row(offset: usize, row: &[u8]) -> String {
+    use std::fmt::Write;
+    let mut s = format!("{:08x} ", offset);
+    for i in 0..HEX_ROW_WIDTH {
+        if i % 8 == 0 {
+            s.push(' ');
+        }
+        match row.get(i) {
+            Some(b) => _ = write!(s, "{:02x} ", b),
+            None => s.push_str("   "),
+        }
+    }
+    s.push_str(" |");
+    s.extend(row.iter().map(|&b| {
+        if b.is_ascii_graphic() || b == b' ' {
+            b as char
+        } else {
+            '.'
+        }
+    }));
+    s.push('|');
+    s
 }
 
 impl std::fmt::Debug for TerminalStatus {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("TerminalStatus")
             .field("text_size", &self.text.len())
+            .field("data_size", &self.data.len())
+            .field("display_mode", &self.display_mode)
             .field("scroll_index", &self.scroll_index)
+            .field("unseen", &self.unseen)
+            .field("follow", &self.follow)
+            .field("only", &self.only)
             .field("scroll_state", &self.scroll_state)
             .finish()
     }
@@ -259,8 +2073,284 @@ impl std::fmt::Debug for Status {
         f.debug_struct("Status")
             .field("rts", &self.rts)
             .field("dtr", &self.dtr)
+            .field("modem", &self.modem)
             .field("device", &self.device)
+            .field("config", &self.config)
+            .field("capture", &self.capture)
+            .field("line_ending", &self.line_ending)
             .field("log_size", &self.log.len())
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use tokio::{io::AsyncWriteExt, sync::mpsc};
+    use tokio_serial::SerialStream;
+
+    use super::*;
+    use crate::{
+        device_finder::DeviceConfig,
+        event::{Capture, SERIAL_QUEUE, serial_handler},
+        theme::ThemePreset,
+    };
+
+    // a few MB from a fast device, read off a pty in read_buffer sized chunks
+    #[tokio::test]
+    async fn large_reads_keep_every_line() {
+        let lines = 100_000;
+        let payload: Vec<u8> = (0..lines)
+            .flat_map(|i| format!("line {:06} of a long stream\n", i).into_bytes())
+            .collect();
+        let (computer, mut device) = SerialStream::pair().unwrap();
+        let (app_tx, _app_rx) = mpsc::unbounded_channel();
+        let (serial_tx, mut serial_rx) = mpsc::channel(SERIAL_QUEUE);
+        let _to_serial = serial_handler(
+            computer,
+            Messenger::new(app_tx, serial_tx),
+            Capture::default(),
+            &DeviceConfig::default(),
+        );
+        let sent = payload.clone();
+        let writer = tokio::spawn(async move {
+            device.write_all(&sent).await.unwrap();
+            device
+        });
+
+        let mut term = TerminalStatus::default();
+        let mut received = 0;
+        while received < payload.len() {
+            let next = tokio::time::timeout(Duration::from_secs(10), serial_rx.recv());
+            match next.await.expect("data stopped arriving").unwrap().1 {
+                FromSerialData::Data(d) => {
+                    received += d.len();
+                    term.push_data(&d);
+                }
+                FromSerialData::Lost(e) => panic!("port lost: {}", e),
+                _ => {}
+            }
+        }
+        let _device = writer.await.unwrap();
+
+        assert_eq!(received, payload.len());
+        assert_eq!(term.data.iter().map(Vec::len).sum::<usize>(), payload.len());
+        assert_eq!(term.rows(), lines);
+        assert!(term.open_line.is_none());
+        assert_eq!(
+            line_text(&term.text[0].line),
+            "line 000000 of a long stream"
+        );
+        assert_eq!(
+            line_text(&term.text[lines - 1].line),
+            format!("line {:06} of a long stream", lines - 1)
+        );
+    }
+
+    #[test]
+    fn character_split_between_reads_is_kept_whole() {
+        let emoji = "🦀".as_bytes();
+        let mut term = TerminalStatus::default();
+        term.push_data(&emoji[..1]);
+        term.push_data(&emoji[1..]);
+        term.push_data(b"!\n");
+        assert_eq!(term.rows(), 1);
+        assert_eq!(line_text(&term.text[0].line), "🦀!");
+        assert!(term.partial_char.is_empty());
+    }
+
+    #[test]
+    fn invalid_bytes_are_replaced_once() {
+        let mut partial = Vec::new();
+        assert_eq!(decode_utf8(&mut partial, b"a\xffb\xe2\x82"), "a\u{fffd}b");
+        assert_eq!(partial, b"\xe2\x82");
+        assert_eq!(decode_utf8(&mut partial, b"\xacc"), "€c");
+        assert!(partial.is_empty());
+    }
+
+    #[test]
+    fn stream_without_newlines_is_split() {
+        let mut term = TerminalStatus {
+            max_line_len: 4096,
+            ..Default::default()
+        };
+        let chunk = [b'x'; 1000];
+        for _ in 0..100 {
+            term.push_data(&chunk);
+        }
+        assert_eq!(term.rows(), 100_000usize.div_ceil(4096));
+        let mut total = 0;
+        for (i, e) in term.text.iter().enumerate() {
+            let text = line_text(&e.line);
+            let len = text.trim_end_matches(LINE_BREAK).chars().count();
+            assert!(len <= 4096);
+            if i + 1 < term.text.len() {
+                assert_eq!(len, 4096);
+                assert!(text.ends_with(LINE_BREAK));
+            }
+            total += len;
+        }
+        assert_eq!(total, 100_000);
+        assert!(term.open_line.is_some());
+    }
+
+    #[test]
+    fn partial_marker_stays_on_the_received_line() {
+        let mut term = TerminalStatus {
+            wrap: true,
+            width: 4,
+            ..Default::default()
+        };
+        term.push_data(b"abcd");
+        term.push_sent("cmd");
+        let open = term.open_entry().unwrap();
+        assert_eq!(open.dir, Dir::Rx);
+        assert_eq!(line_text(&open.line), "abcd");
+        // the marker takes the fifth column, on a row of its own
+        assert_eq!(term.line_rows(open), 2);
+        assert_eq!(term.line_rows(&term.text[1]), 2);
+
+        // more data after the sent line starts a new row that is now the open one
+        term.push_data(b"ef");
+        assert_eq!(term.open_line, Some(2));
+        assert_eq!(term.line_rows(&term.text[0]), 1);
+        term.push_data(b"\n");
+        assert!(term.open_entry().is_none());
+    }
+
+    #[test]
+    fn row_count_matches_the_rows() {
+        let mut term = TerminalStatus {
+            scrollback: 20,
+            ..Default::default()
+        };
+        for i in 0..50 {
+            term.push_data(format!("line {} with some padding to fill a hex row\n", i).as_bytes());
+            term.push_sent("sent");
+        }
+        for only in [None, Some(Dir::Rx), Some(Dir::Tx)] {
+            term.only = only;
+            for mode in [DisplayMode::Text, DisplayMode::Hex] {
+                term.display_mode = mode;
+                assert_eq!(term.row_count(), term.row_strings().len());
+            }
+        }
+    }
+
+    fn dashboard() -> Dashboard {
+        use clap::Parser;
+        let (app_tx, _) = mpsc::unbounded_channel();
+        let (serial_tx, _) = mpsc::channel(SERIAL_QUEUE);
+        let opts = TerminalOptions::parse_from(["seterm"]);
+        Dashboard::new(Messenger::new(app_tx, serial_tx), opts)
+    }
+
+    #[test]
+    fn input_is_encoded_for_sending() {
+        let mut dash = dashboard();
+        dash.status.line_ending = LineEnding::Crlf;
+        dash.term_input = "one\ntwo".into();
+        assert_eq!(dash.encode_input().unwrap(), b"one\r\ntwo\r\n");
+        dash.hex_input = true;
+        dash.term_input = "de ad be ef".into();
+        assert_eq!(dash.encode_input().unwrap(), [0xde, 0xad, 0xbe, 0xef]);
+        dash.term_input = "xyz".into();
+        assert!(dash.encode_input().is_err());
+    }
+
+    // shrinking the terminal to nothing must not take the app down
+    #[test]
+    fn draws_into_tiny_areas() {
+        use ratatui::{Terminal, backend::TestBackend};
+
+        let mut dash = dashboard();
+        dash.term_state
+            .push_data(b"some output\nand a partial line");
+        for (mode, wrap) in [
+            (DisplayMode::Text, false),
+            (DisplayMode::Text, true),
+            (DisplayMode::Hex, false),
+        ] {
+            dash.term_state.display_mode = mode;
+            dash.term_state.wrap = wrap;
+            for (width, height) in [(1, 1), (0, 10), (10, 0), (0, 0), (2, 3)] {
+                let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+                terminal
+                    .draw(|frame| dash.draw(frame.area(), frame, &Theme::preset(ThemePreset::Dark)))
+                    .unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn rules_follow_the_width() {
+        use ratatui::{Terminal, backend::TestBackend};
+
+        let mut dash = dashboard();
+        dash.term_state.push_rule("mark");
+        for width in [40, 80] {
+            let mut terminal = Terminal::new(TestBackend::new(width, 10)).unwrap();
+            terminal
+                .draw(|frame| dash.draw(frame.area(), frame, &Theme::preset(ThemePreset::Dark)))
+                .unwrap();
+            let text = dash.term_state.width;
+            let buffer = terminal.backend().buffer();
+            let row = (0..buffer.area.height)
+                .map(|y| {
+                    (0..width)
+                        .map(|x| buffer[(x, y)].symbol())
+                        .collect::<String>()
+                })
+                .find(|row| row.contains(" mark "))
+                .unwrap();
+            let rule = row.matches('─').count();
+            assert!(rule + " mark ".len() + 1 >= text, "{row}");
+        }
+    }
+
+    // the same scrollback is drawn in whichever theme it is given
+    #[test]
+    fn sent_lines_take_the_theme_color() {
+        use ratatui::{Terminal, backend::TestBackend, style::Color};
+
+        let mut dash = dashboard();
+        dash.term_state.push_sent("hello");
+        for (preset, color) in [
+            (ThemePreset::Dark, Color::Cyan),
+            (ThemePreset::HighContrast, Color::Yellow),
+        ] {
+            let mut terminal = Terminal::new(TestBackend::new(40, 10)).unwrap();
+            terminal
+                .draw(|frame| dash.draw(frame.area(), frame, &Theme::preset(preset)))
+                .unwrap();
+            let buffer = terminal.backend().buffer();
+            let marker = buffer.content().iter().find(|c| c.symbol() == ">").unwrap();
+            assert_eq!(marker.fg, color);
+        }
+    }
+
+    #[test]
+    fn history_recalls_the_input_mode() {
+        let mut history = History::default();
+        history.push("hello", false);
+        history.push("de ad", true);
+        let (mut input, mut hex) = ("draft".to_string(), false);
+        history.prev(&mut input, &mut hex);
+        assert_eq!((input.as_str(), hex), ("de ad", true));
+        history.prev(&mut input, &mut hex);
+        assert_eq!((input.as_str(), hex), ("hello", false));
+        history.next(&mut input, &mut hex);
+        history.next(&mut input, &mut hex);
+        assert_eq!((input.as_str(), hex), ("draft", false));
+    }
+
+    #[test]
+    fn tabs_count_wide_characters() {
+        let mut term = TerminalStatus {
+            tabstop: 8,
+            ..Default::default()
+        };
+        term.push_data("日本\t".as_bytes());
+        term.push_data(b"x\ty\n");
+        assert_eq!(line_text(&term.text[0].line), "日本    x       y");
+    }
+}
@@ -0,0 +1,224 @@
+use std::time::Duration;
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    time::timeout,
+};
+use tracing::trace;
+
+use crate::error::SerialError;
+
+type Result<T> = std::result::Result<T, SerialError>;
+
+const SOH: u8 = 0x01;
+const EOT: u8 = 0x04;
+const ACK: u8 = 0x06;
+const NAK: u8 = 0x15;
+const CAN: u8 = 0x18;
+// receiver asks for CRC-16 instead of the additive checksum
+const CRC: u8 = b'C';
+// pads the final block
+const SUB: u8 = 0x1a;
+
+const BLOCK_SIZE: usize = 128;
+const RETRIES: usize = 10;
+// bootloaders are often started by hand after the file is picked
+const START_TIMEOUT: Duration = Duration::from_secs(60);
+const REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Number of blocks `len` bytes are sent as.
+pub fn block_count(len: usize) -> usize {
+    len.div_ceil(BLOCK_SIZE).max(1)
+}
+
+/// Sends `data` with XMODEM, using CRC-16 if the receiver asks for it and
+/// the 8 bit checksum otherwise. `progress` is called with the block
+/// number and total after each block is acknowledged.
+pub async fn send<P>(
+    port: &mut P,
+    data: &[u8],
+    mut progress: impl FnMut(usize, usize),
+) -> Result<()>
+where
+    P: AsyncRead + AsyncWrite + Unpin,
+{
+    let use_crc = wait_for_start(port).await?;
+    trace!("XMODEM receiver ready, crc = {}", use_crc);
+    let total = block_count(data.len());
+    let blocks = data
+        .chunks(BLOCK_SIZE)
+        .chain(data.is_empty().then_some(&[][..]));
+    for (i, chunk) in blocks.enumerate() {
+        let packet = packet((i + 1) as u8, chunk, use_crc);
+        send_until_ack(port, &packet).await?;
+        progress(i + 1, total);
+    }
+    send_until_ack(port, &[EOT]).await
+}
+
+/// Tells the receiver the transfer is over, for when the sender gives up.
+pub async fn cancel<P>(port: &mut P) -> Result<()>
+where
+    P: AsyncWrite + Unpin,
+{
+    port.write_all(&[CAN, CAN]).await?;
+    port.flush().await?;
+    Ok(())
+}
+
+// true if the receiver wants CRC-16
+async fn wait_for_start<P>(port: &mut P) -> Result<bool>
+where
+    P: AsyncRead + Unpin,
+{
+    let deadline = tokio::time::Instant::now() + START_TIMEOUT;
+    loop {
+        let b = tokio::time::timeout_at(deadline, read_byte(port))
+            .await
+            .map_err(|_| SerialError::Timeout("Receiver did not start the transfer".into()))??;
+        match b {
+            CRC => return Ok(true),
+            NAK => return Ok(false),
+            CAN => return Err(cancelled()),
+            // leftover output from before the receiver started
+            _ => {}
+        }
+    }
+}
+
+async fn send_until_ack<P>(port: &mut P, packet: &[u8]) -> Result<()>
+where
+    P: AsyncRead + AsyncWrite + Unpin,
+{
+    for _ in 0..RETRIES {
+        port.write_all(packet).await?;
+        port.flush().await?;
+        match timeout(REPLY_TIMEOUT, read_reply(port)).await {
+            Ok(Ok(ACK)) => return Ok(()),
+            Ok(Ok(CAN)) => return Err(cancelled()),
+            Ok(Ok(_)) => trace!("XMODEM block rejected, resending"),
+            Ok(Err(e)) => return Err(e),
+            Err(_) => trace!("XMODEM reply timed out, resending"),
+        }
+    }
+    Err(SerialError::Timeout(format!(
+        "No acknowledgement after {} tries",
+        RETRIES
+    )))
+}
+
+// skips anything that is not a reply, such as the receiver repeating 'C'
+async fn read_reply<P>(port: &mut P) -> Result<u8>
+where
+    P: AsyncRead + Unpin,
+{
+    loop {
+        let b = read_byte(port).await?;
+        if matches!(b, ACK | NAK | CAN) {
+            return Ok(b);
+        }
+    }
+}
+
+async fn read_byte<P>(port: &mut P) -> Result<u8>
+where
+    P: AsyncRead + Unpin,
+{
+    let mut b = [0];
+    if port.read(&mut b).await? == 0 {
+        return Err(SerialError::Disconnected(
+            "Port closed during transfer".into(),
+        ));
+    }
+    Ok(b[0])
+}
+
+fn cancelled() -> SerialError {
+    SerialError::Cancelled("Transfer cancelled by receiver".into())
+}
+
+fn packet(num: u8, chunk: &[u8], use_crc: bool) -> Vec<u8> {
+    let mut block = [SUB; BLOCK_SIZE];
+    block[..chunk.len()].copy_from_slice(chunk);
+    let mut packet = Vec::with_capacity(BLOCK_SIZE + 5);
+    packet.extend([SOH, num, !num]);
+    packet.extend(block);
+    if use_crc {
+        packet.extend(crc16(&block).to_be_bytes());
+    } else {
+        packet.push(block.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)));
+    }
+    packet
+}
+
+// CRC-16/XMODEM: polynomial 0x1021, starting from 0
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for &b in data {
+        crc ^= (b as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc16_check_value() {
+        assert_eq!(crc16(b"123456789"), 0x31c3);
+    }
+
+    #[test]
+    fn short_blocks_are_padded() {
+        let packet = packet(3, b"hi", false);
+        assert_eq!(packet.len(), BLOCK_SIZE + 4);
+        assert_eq!(packet[..3], [SOH, 3, !3]);
+        assert_eq!(packet[3..5], *b"hi");
+        assert!(packet[5..BLOCK_SIZE + 3].iter().all(|&b| b == SUB));
+        let sum = packet[3..BLOCK_SIZE + 3]
+            .iter()
+            .fold(0u8, |sum, b| sum.wrapping_add(*b));
+        assert_eq!(packet[BLOCK_SIZE + 3], sum);
+
+        let packet = super::packet(255, b"hi", true);
+        assert_eq!(packet.len(), BLOCK_SIZE + 5);
+        assert_eq!(packet[1..3], [255, 0]);
+        let crc = crc16(&packet[3..BLOCK_SIZE + 3]).to_be_bytes();
+        assert_eq!(packet[BLOCK_SIZE + 3..], crc);
+    }
+
+    // the receiver turns the first copy of the block away
+    #[tokio::test]
+    async fn rejected_block_is_sent_again() {
+        let (mut sender, mut receiver) = tokio::io::duplex(1024);
+        let data = b"hello xmodem".to_vec();
+        let expected = packet(1, &data, false);
+        let device = tokio::spawn(async move {
+            let mut block = vec![0; expected.len()];
+            receiver.write_all(&[NAK]).await.unwrap();
+            receiver.read_exact(&mut block).await.unwrap();
+            assert_eq!(block, expected);
+            receiver.write_all(&[NAK]).await.unwrap();
+            receiver.read_exact(&mut block).await.unwrap();
+            assert_eq!(block, expected);
+            receiver.write_all(&[ACK]).await.unwrap();
+            assert_eq!(read_byte(&mut receiver).await.unwrap(), EOT);
+            receiver.write_all(&[ACK]).await.unwrap();
+        });
+
+        let mut acked = Vec::new();
+        send(&mut sender, &data, |n, total| acked.push((n, total)))
+            .await
+            .unwrap();
+        device.await.unwrap();
+        assert_eq!(acked, [(1, 1)]);
+    }
+}